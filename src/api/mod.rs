@@ -1,37 +1,69 @@
 use crate::core::data_structures::*;
 use axum::{
-    extract::State,
-    http::{Method, StatusCode},
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, HeaderName, Method, StatusCode},
     response::IntoResponse,
     routing::{get, post},
     Router,
 };
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::collections::HashMap;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use axum::response::Html;
 use base64::Engine;
 use crate::core::data_structures::{RegisterAOIKeyRequest, RegisterAOIKeyResponse, GetAOIKeyRequest, GetAOIKeyResponse};
 
-pub mod dux_coin;
-
 #[derive(Clone)]
 pub struct ApiState {
     pub node: Arc<crate::core::DuxNetNode>,
 }
 
-pub async fn start_api_server(port: u16) -> Result<(), Box<dyn std::error::Error>> {
+/// Names the local identity a request acts as. Requests without it act as the
+/// node's primary identity. Like the list of identities, it is only for
+/// clients on the node's own machine.
+pub const IDENTITY_HEADER: &str = "x-duxnet-identity";
+
+/// The local identity selected by a request's `X-DuxNet-Identity` header.
+pub struct ActiveIdentity(pub crate::core::identities::LocalIdentity);
+
+#[async_trait::async_trait]
+impl FromRequestParts<ApiState> for ActiveIdentity {
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &ApiState) -> Result<Self, Self::Rejection> {
+        let reject = |status: StatusCode, error: String| {
+            (status, axum::Json(serde_json::json!({ "success": false, "error": error }))).into_response()
+        };
+        let did = match parts.headers.get(IDENTITY_HEADER).map(|value| value.to_str()) {
+            Some(Ok(did)) => Some(did),
+            Some(Err(_)) => return Err(reject(StatusCode::BAD_REQUEST, format!("{} must be a DID", IDENTITY_HEADER))),
+            None => None,
+        };
+        if did.is_some() {
+            let client = parts.extensions.get::<axum::extract::ConnectInfo<SocketAddr>>().map(|info| info.0);
+            match client {
+                Some(client) => require_local(&client).map_err(IntoResponse::into_response)?,
+                None => return Err(reject(StatusCode::FORBIDDEN, format!("{} needs a local client", IDENTITY_HEADER))),
+            }
+        }
+        match state.node.identities.select(did).await {
+            Ok(identity) => Ok(ActiveIdentity(identity)),
+            Err(e) => Err(reject(StatusCode::NOT_FOUND, e.to_string())),
+        }
+    }
+}
+
+pub async fn start_api_server(port: u16, node: Arc<crate::core::DuxNetNode>) -> Result<(), Box<dyn std::error::Error>> {
     info!("Starting API server on port {}", port);
     
-    // Create a mock node for the API (in a real app, this would be shared)
-    let node = Arc::new(crate::core::DuxNetNode::new(8080).await?);
-    
     let state = ApiState { node };
     
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::CONTENT_TYPE, HeaderName::from_static(IDENTITY_HEADER)])
         .allow_origin(Any);
     
     let app = Router::new()
@@ -40,8 +72,15 @@ pub async fn start_api_server(port: u16) -> Result<(), Box<dyn std::error::Error
         .route("/api/services/search", post(search_services))
         .route("/api/tasks/submit", post(submit_task))
         .route("/api/escrow/create", post(create_escrow))
+        .route("/api/reputation", get(get_reputation))
         .route("/api/reputation/:did", get(get_reputation))
+        .route("/api/did/:did", get(resolve_did))
+        .route("/api/credentials/issue", post(issue_credential))
+        .route("/api/credentials/verify", post(verify_credential))
+        .route("/api/credentials/revoke", post(revoke_credential))
         .route("/api/stats", get(get_stats))
+        .route("/api/identities", get(list_identities).post(create_identity))
+        .route("/api/identities/:did/archive", post(archive_identity))
         .route("/api/wallet/info", get(get_wallet_info))
         .route("/api/wallet/balances", get(get_wallet_balances))
         .route("/api/wallet/addresses", get(get_wallet_addresses))
@@ -71,6 +110,11 @@ pub async fn start_api_server(port: u16) -> Result<(), Box<dyn std::error::Error
         .route("/api/dux/mine/stop", post(stop_dux_mining))
         .route("/api/dux/mine/status", get(get_dux_mining_status))
         .route("/api/dux/sync", post(sync_dux_balance))
+        .route("/api/admin/dht/snapshot", get(export_dht_snapshot).post(import_dht_snapshot))
+        .route("/api/admin/keystore/export", get(export_keystore))
+        .route("/api/admin/keystore/password", post(change_keystore_password))
+        .route("/api/admin/keystore/rotate", post(rotate_key))
+        .route("/api/admin/keystore/mnemonic", post(reveal_recovery_phrase))
         .route("/api/shutdown", post(shutdown_node))
         .route("/", get(serve_index))
         .route("/index.html", get(serve_index))
@@ -81,7 +125,7 @@ pub async fn start_api_server(port: u16) -> Result<(), Box<dyn std::error::Error
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!("API server listening on port {}", port);
     
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
 
@@ -110,11 +154,15 @@ async fn get_status(State(state): State<ApiState>) -> impl IntoResponse {
 
 async fn register_service(
     State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<RegisterServiceRequest>,
 ) -> impl IntoResponse {
     let node = &state.node;
     
-    match node.register_service(request.name, request.description, request.price).await {
+    match node
+        .register_service_with_credentials(&identity, request.name, request.description, request.price, request.tags, request.credentials)
+        .await
+    {
         Ok(service_id) => axum::Json(RegisterServiceResponse {
             service_id: service_id.0,
             success: true,
@@ -137,7 +185,7 @@ async fn search_services(
 ) -> impl IntoResponse {
     let node = &state.node;
     
-    let services = node.find_services(&request.query).await;
+    let services = node.search_services(&request.query, request.sort, request.limit).await;
     
     axum::Json(FindServicesResponse {
         services: services.clone(),
@@ -148,6 +196,7 @@ async fn search_services(
 
 async fn submit_task(
     State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<SubmitTaskRequest>,
 ) -> impl IntoResponse {
     let node = &state.node;
@@ -159,7 +208,7 @@ async fn submit_task(
         timeout_seconds: request.timeout_seconds,
     };
     
-    match node.submit_task(service_id, request.payload.into_bytes(), requirements).await {
+    match node.submit_task(&identity, service_id, request.payload.into_bytes(), requirements).await {
         Ok(task_id) => axum::Json(SubmitTaskResponse {
             task_id: task_id.0,
             success: true,
@@ -178,13 +227,14 @@ async fn submit_task(
 
 async fn create_escrow(
     State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<CreateEscrowRequest>,
 ) -> impl IntoResponse {
     let node = &state.node;
     
     let service_id = ServiceId(request.service_id);
     
-    match node.create_escrow_for_service(&service_id, request.seller_did, request.amount).await {
+    match node.create_escrow_for_service(&identity, &service_id, request.seller_did, request.amount).await {
         Ok(escrow_id) => axum::Json(CreateEscrowResponse {
             escrow_id,
             success: true,
@@ -201,11 +251,17 @@ async fn create_escrow(
     }
 }
 
+/// The reputation of `did`, or without one, of the identity the request acts as.
 async fn get_reputation(
     State(state): State<ApiState>,
-    axum::extract::Path(did): axum::extract::Path<String>,
+    ActiveIdentity(identity): ActiveIdentity,
+    did: Option<axum::extract::Path<String>>,
 ) -> impl IntoResponse {
     let node = &state.node;
+    let did = match did {
+        Some(axum::extract::Path(did)) => did,
+        None => identity.did().to_string(),
+    };
     let reputation = node.get_reputation(&did).await;
     
     axum::Json(serde_json::json!({
@@ -215,6 +271,107 @@ async fn get_reputation(
     }))
 }
 
+async fn resolve_did(
+    State(state): State<ApiState>,
+    axum::extract::Path(did): axum::extract::Path<String>,
+) -> axum::response::Response {
+    match state.node.did_resolver.resolve(&did).await {
+        Ok(document) => axum::Json(serde_json::json!({
+            "success": true,
+            "document": document
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to resolve {}: {}", did, e)
+            })),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IssueCredentialRequest {
+    subject_did: String,
+    credential_type: String,
+    #[serde(default)]
+    claims: std::collections::BTreeMap<String, String>,
+    expires_at: Option<u64>,
+}
+
+async fn issue_credential(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<IssueCredentialRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    let credential =
+        state.node.issue_credential(&request.subject_did, &request.credential_type, request.claims, request.expires_at);
+    axum::Json(serde_json::json!({
+        "success": true,
+        "credential": credential
+    }))
+    .into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyCredentialRequest {
+    credential: crate::core::credentials::VerifiableCredential,
+}
+
+async fn verify_credential(
+    State(state): State<ApiState>,
+    axum::Json(request): axum::Json<VerifyCredentialRequest>,
+) -> impl IntoResponse {
+    match state.node.did_resolver.verify_credential(&request.credential).await {
+        Ok(()) => axum::Json(serde_json::json!({
+            "success": true,
+            "valid": true
+        })),
+        Err(e) => axum::Json(serde_json::json!({
+            "success": true,
+            "valid": false,
+            "reason": e.to_string()
+        })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RevokeCredentialRequest {
+    credential_id: String,
+}
+
+async fn revoke_credential(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<RevokeCredentialRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    match state.node.revoke_credential(&request.credential_id).await {
+        Ok(()) => axum::Json(serde_json::json!({
+            "success": true,
+            "message": format!("Revoked credential {}", request.credential_id)
+        }))
+        .into_response(),
+        Err(e) => {
+            error!("Failed to revoke credential: {}", e);
+            axum::Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to revoke credential: {}", e)
+            }))
+            .into_response()
+        }
+    }
+}
+
 async fn get_stats(State(state): State<ApiState>) -> impl IntoResponse {
     let node = &state.node;
     
@@ -231,6 +388,8 @@ async fn get_stats(State(state): State<ApiState>) -> impl IntoResponse {
             "service_entries": dht_stats.service_entries,
             "reputation_entries": dht_stats.reputation_entries,
             "escrow_entries": dht_stats.escrow_entries,
+            "quota_rejections": dht_stats.quota_rejections,
+            "publisher_usage": dht_stats.publisher_usage,
         },
         "reputation": {
             "total_nodes": reputation_stats.total_nodes,
@@ -262,9 +421,8 @@ async fn get_stats(State(state): State<ApiState>) -> impl IntoResponse {
 }
 
 // Wallet API endpoints
-async fn get_wallet_info(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn get_wallet_info(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     match wallet.get_wallet_info() {
         Ok(wallet_info) => axum::Json(serde_json::json!({
             "success": true,
@@ -280,9 +438,8 @@ async fn get_wallet_info(State(state): State<ApiState>) -> impl IntoResponse {
     }
 }
 
-async fn get_wallet_balances(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn get_wallet_balances(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let balances = wallet.get_all_balances();
     let mut formatted_balances = HashMap::new();
     
@@ -299,9 +456,8 @@ async fn get_wallet_balances(State(state): State<ApiState>) -> impl IntoResponse
     }))
 }
 
-async fn get_wallet_addresses(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn get_wallet_addresses(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let addresses = wallet.get_all_addresses();
     let mut formatted_addresses = HashMap::new();
     
@@ -317,17 +473,25 @@ async fn get_wallet_addresses(State(state): State<ApiState>) -> impl IntoRespons
 
 async fn send_funds(
     State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<crate::wallet::SendRequest>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    let mut wallet = node.wallet.write().await;
-    match wallet.send_funds(request) {
-        Ok(response) => axum::Json(serde_json::json!({
-            "success": true,
-            "transaction_id": response.transaction_id,
-            "message": response.message,
-            "fee": response.fee
-        })),
+    let (currency, amount) = (request.currency, request.amount);
+    let sent = identity.wallet.write().await.send_funds(request);
+    match sent {
+        Ok(response) => {
+            // The wallet has already charged the tax; credit it to the fund.
+            let tax = state.node.community_fund_manager.calculate_tax_amount(amount);
+            if let Err(e) = state.node.add_tax_to_community_fund(currency, tax).await {
+                warn!("Failed to credit {} tax to the community fund: {}", currency.symbol(), e);
+            }
+            axum::Json(serde_json::json!({
+                "success": true,
+                "transaction_id": response.transaction_id,
+                "message": response.message,
+                "fee": response.fee
+            }))
+        }
         Err(e) => {
             error!("Failed to send funds: {}", e);
             axum::Json(serde_json::json!({
@@ -339,11 +503,9 @@ async fn send_funds(
 }
 
 async fn receive_funds(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    
     let from_address = request["from_address"].as_str().unwrap_or("");
     let amount = request["amount"].as_u64().unwrap_or(0);
     let currency_str = request["currency"].as_str().unwrap_or("USDC");
@@ -360,12 +522,9 @@ async fn receive_funds(
         _ => crate::wallet::Currency::USDC,
     };
     
-    let signature_bytes = match base64::engine::general_purpose::STANDARD.decode(signature) {
-        Ok(bytes) => bytes,
-        Err(_) => vec![],
-    };
+    let signature_bytes = base64::engine::general_purpose::STANDARD.decode(signature).unwrap_or_default();
     
-    let mut wallet = node.wallet.write().await;
+    let mut wallet = identity.wallet.write().await;
     match wallet.receive_funds(from_address.to_string(), amount, currency, 
                                    transaction_id.to_string(), signature_bytes) {
         Ok(_) => axum::Json(serde_json::json!({
@@ -382,9 +541,8 @@ async fn receive_funds(
     }
 }
 
-async fn get_transaction_history(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn get_transaction_history(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let transactions = wallet.get_transaction_history();
     
     axum::Json(serde_json::json!({
@@ -394,11 +552,10 @@ async fn get_transaction_history(State(state): State<ApiState>) -> impl IntoResp
 }
 
 async fn get_transaction_by_id(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::extract::Path(transaction_id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+    let wallet = identity.wallet.read().await;
     match wallet.get_transaction_by_id(&transaction_id) {
         Some(transaction) => axum::Json(serde_json::json!({
             "success": true,
@@ -411,9 +568,8 @@ async fn get_transaction_by_id(
     }
 }
 
-async fn backup_wallet(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn backup_wallet(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     match wallet.backup_wallet() {
         Ok(backup_data) => axum::Json(serde_json::json!({
             "success": true,
@@ -430,13 +586,12 @@ async fn backup_wallet(State(state): State<ApiState>) -> impl IntoResponse {
 }
 
 async fn restore_wallet(
-    State(state): State<ApiState>,
     axum::Json(request): axum::Json<serde_json::Value>,
 ) -> impl IntoResponse {
     let backup_data = request["backup_data"].as_str().unwrap_or("");
     
     match crate::wallet::Wallet::restore_wallet(backup_data) {
-        Ok(wallet) => {
+        Ok(_) => {
             // In a real implementation, you'd replace the node's wallet
             axum::Json(serde_json::json!({
                 "success": true,
@@ -453,9 +608,8 @@ async fn restore_wallet(
     }
 }
 
-async fn get_wallet_keys(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = node.wallet.read().await;
+async fn get_wallet_keys(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let public_key_res = wallet.get_public_key_base64();
     let private_key_res = wallet.get_private_key_base64();
     match (public_key_res, private_key_res) {
@@ -613,21 +767,306 @@ async fn distribute_community_fund(
     }
 } 
 
-async fn shutdown_node() -> impl IntoResponse {
+// Admin endpoints, and those that sign with the node's keys, only answer
+// clients on the node's own machine.
+fn require_local(client: &SocketAddr) -> Result<(), (StatusCode, axum::Json<serde_json::Value>)> {
+    if client.ip().is_loopback() {
+        return Ok(());
+    }
+    Err((
+        StatusCode::FORBIDDEN,
+        axum::Json(serde_json::json!({
+            "success": false,
+            "error": "Admin operations are only available from localhost"
+        })),
+    ))
+}
+
+async fn export_dht_snapshot(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    let mut snapshot = Vec::new();
+    match state.node.dht.export_snapshot(&mut snapshot).await {
+        Ok(count) => {
+            info!("Exported {} DHT entries for {}", count, client);
+            ([(axum::http::header::CONTENT_TYPE, "application/x-ndjson")], snapshot).into_response()
+        }
+        Err(e) => {
+            error!("Failed to export DHT snapshot: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to export DHT snapshot: {}", e)
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ImportSnapshotParams {
+    #[serde(default)]
+    merge: crate::core::snapshot::MergePolicy,
+}
+
+async fn import_dht_snapshot(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::extract::Query(params): axum::extract::Query<ImportSnapshotParams>,
+    body: axum::body::Bytes,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    match state.node.dht.import_snapshot(body.as_ref(), params.merge).await {
+        Ok(report) => axum::Json(serde_json::json!({
+            "success": true,
+            "report": report
+        }))
+        .into_response(),
+        Err(e) => {
+            error!("Failed to import DHT snapshot: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to import DHT snapshot: {}", e)
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn export_keystore(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    match state.node.export_keystore().await {
+        Ok(keystore) => axum::Json(serde_json::json!({
+            "success": true,
+            "keystore": keystore
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            axum::Json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to export keystore: {}", e)
+            })),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+async fn change_keystore_password(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<ChangePasswordRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    let old_password = crate::core::keystore::Password::new(request.old_password);
+    let new_password = crate::core::keystore::Password::new(request.new_password);
+    match state.node.change_keystore_password(&old_password, &new_password).await {
+        Ok(()) => axum::Json(serde_json::json!({
+            "success": true,
+            "message": "Keystore password changed"
+        }))
+        .into_response(),
+        Err(e) => {
+            error!("Failed to change keystore password: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to change keystore password: {}", e)
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RotateKeyRequest {
+    password: String,
+    /// Set when the current key may be compromised.
+    #[serde(default)]
+    revoke_current: bool,
+}
+
+async fn rotate_key(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<RotateKeyRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    let password = crate::core::keystore::Password::new(request.password);
+    match state.node.rotate_key(&password, request.revoke_current).await {
+        Ok(update) => axum::Json(serde_json::json!({
+            "success": true,
+            "message": "Key rotated; the node uses the new key from its next start",
+            "sequence": update.sequence,
+            "new_key": update.new_key.map(hex::encode)
+        }))
+        .into_response(),
+        Err(e) => {
+            error!("Failed to rotate key: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to rotate key: {}", e)
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RecoveryPhraseRequest {
+    password: String,
+}
+
+async fn reveal_recovery_phrase(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<RecoveryPhraseRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    let password = crate::core::keystore::Password::new(request.password);
+    match state.node.recovery_phrase(&password).await {
+        Ok(phrase) => axum::Json(serde_json::json!({
+            "success": true,
+            "recovery_phrase": phrase
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to read the recovery phrase: {}", e)
+            })),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CreateIdentityRequest {
+    label: String,
+}
+
+async fn list_identities(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    axum::Json(serde_json::json!({
+        "success": true,
+        "identities": state.node.list_identities().await
+    }))
+    .into_response()
+}
+
+async fn create_identity(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Json(request): axum::Json<CreateIdentityRequest>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    match state.node.create_identity(request.label).await {
+        Ok(identity) => axum::Json(serde_json::json!({
+            "success": true,
+            "did": identity.did(),
+            "label": identity.label
+        }))
+        .into_response(),
+        Err(e) => {
+            error!("Failed to create identity: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                axum::Json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to create identity: {}", e)
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn archive_identity(
+    State(state): State<ApiState>,
+    axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>,
+    axum::extract::Path(did): axum::extract::Path<String>,
+) -> axum::response::Response {
+    if let Err(rejection) = require_local(&client) {
+        return rejection.into_response();
+    }
+
+    match state.node.archive_identity(&did).await {
+        Ok(()) => axum::Json(serde_json::json!({
+            "success": true,
+            "message": format!("Archived identity {}", did)
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            axum::Json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to archive identity: {}", e)
+            })),
+        )
+            .into_response(),
+    }
+}
+
+async fn shutdown_node() -> StatusCode {
     // In a real app, signal the main loop to exit gracefully
-    std::process::exit(0);
-    // This line is unreachable, but required for the type
-    StatusCode::OK
+    std::process::exit(0)
 }
 
 // Messaging API endpoints
 async fn send_message(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<crate::core::data_structures::MessageRequest>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    
-    match node.messaging_system.send_message(request).await {
+    match identity.messaging.send_message(request).await {
         Ok(response) => axum::Json(response),
         Err(e) => {
             error!("Failed to send message: {}", e);
@@ -640,10 +1079,8 @@ async fn send_message(
     }
 }
 
-async fn get_conversations(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    
-    let conversations = node.messaging_system.get_conversations().await;
+async fn get_conversations(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let conversations = identity.messaging.get_conversations().await;
     
     axum::Json(serde_json::json!({
         "success": true,
@@ -652,12 +1089,10 @@ async fn get_conversations(State(state): State<ApiState>) -> impl IntoResponse {
 }
 
 async fn get_messages(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::extract::Path(peer_did): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    
-    let messages = node.messaging_system.get_messages(&peer_did).await;
+    let messages = identity.messaging.get_messages(&peer_did).await;
     
     axum::Json(serde_json::json!({
         "success": true,
@@ -667,12 +1102,10 @@ async fn get_messages(
 }
 
 async fn mark_message_read(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::extract::Path(message_id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    
-    match node.messaging_system.mark_message_read(&message_id).await {
+    match identity.messaging.mark_message_read(&message_id).await {
         Ok(_) => axum::Json(serde_json::json!({
             "success": true,
             "message": "Message marked as read"
@@ -688,12 +1121,10 @@ async fn mark_message_read(
 }
 
 async fn delete_message(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::extract::Path(message_id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    
-    match node.messaging_system.delete_message(&message_id).await {
+    match identity.messaging.delete_message(&message_id).await {
         Ok(_) => axum::Json(serde_json::json!({
             "success": true,
             "message": "Message deleted"
@@ -708,10 +1139,8 @@ async fn delete_message(
     }
 }
 
-async fn get_messaging_stats(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    
-    let stats = node.messaging_system.get_message_stats().await;
+async fn get_messaging_stats(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let stats = identity.messaging.get_message_stats().await;
     
     axum::Json(serde_json::json!({
         "success": true,
@@ -720,9 +1149,8 @@ async fn get_messaging_stats(State(state): State<ApiState>) -> impl IntoResponse
 }
 
 // DUX Coin API endpoints
-async fn get_dux_balance(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = &node.wallet;
+async fn get_dux_balance(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let dux_address = wallet.get_address(&crate::wallet::Currency::DUX);
     
     // In a real implementation, this would call the DUX coin daemon
@@ -738,9 +1166,8 @@ async fn get_dux_balance(State(state): State<ApiState>) -> impl IntoResponse {
     }))
 }
 
-async fn get_dux_transactions(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = &node.wallet;
+async fn get_dux_transactions(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let transactions = wallet.get_transactions_by_currency(&crate::wallet::Currency::DUX);
     
     axum::Json(serde_json::json!({
@@ -752,11 +1179,10 @@ async fn get_dux_transactions(State(state): State<ApiState>) -> impl IntoRespons
 }
 
 async fn send_dux(
-    State(state): State<ApiState>,
+    ActiveIdentity(identity): ActiveIdentity,
     axum::Json(request): axum::Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let node = &state.node;
-    let mut wallet = node.wallet.clone();
+    let mut wallet = identity.wallet.write().await;
     
     let to_address = request["to_address"].as_str().unwrap_or("");
     let amount = request["amount"].as_u64().unwrap_or(0);
@@ -788,7 +1214,7 @@ async fn send_dux(
     }
 }
 
-async fn get_dux_network(State(state): State<ApiState>) -> impl IntoResponse {
+async fn get_dux_network() -> impl IntoResponse {
     // Mock network information for DUX coin
     axum::Json(serde_json::json!({
         "difficulty": 1.0,
@@ -801,7 +1227,6 @@ async fn get_dux_network(State(state): State<ApiState>) -> impl IntoResponse {
 }
 
 async fn start_dux_mining(
-    State(state): State<ApiState>,
     axum::Json(request): axum::Json<serde_json::Value>,
 ) -> impl IntoResponse {
     let threads = request["threads"].as_i64().unwrap_or(1) as i32;
@@ -813,14 +1238,14 @@ async fn start_dux_mining(
     }))
 }
 
-async fn stop_dux_mining(State(state): State<ApiState>) -> impl IntoResponse {
+async fn stop_dux_mining() -> impl IntoResponse {
     axum::Json(serde_json::json!({
         "success": true,
         "message": "DUX mining stopped"
     }))
 }
 
-async fn get_dux_mining_status(State(state): State<ApiState>) -> impl IntoResponse {
+async fn get_dux_mining_status() -> impl IntoResponse {
     axum::Json(serde_json::json!({
         "mining": false,
         "hash_rate": 0.0,
@@ -829,9 +1254,8 @@ async fn get_dux_mining_status(State(state): State<ApiState>) -> impl IntoRespon
     }))
 }
 
-async fn sync_dux_balance(State(state): State<ApiState>) -> impl IntoResponse {
-    let node = &state.node;
-    let wallet = &node.wallet;
+async fn sync_dux_balance(ActiveIdentity(identity): ActiveIdentity) -> impl IntoResponse {
+    let wallet = identity.wallet.read().await;
     let balance = wallet.get_balance(&crate::wallet::Currency::DUX);
     
     axum::Json(serde_json::json!({
@@ -0,0 +1,208 @@
+//! Inverted index over the services held in the DHT, so searches only touch
+//! the listings that match.
+
+use crate::core::data_structures::{ServiceMetadata, ServiceSort};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const NAME_WEIGHT: f64 = 3.0;
+const TAG_WEIGHT: f64 = 2.0;
+const DESCRIPTION_WEIGHT: f64 = 1.0;
+/// A query term that is only a prefix of an indexed term scores this much of a full match.
+const PREFIX_FACTOR: f64 = 0.5;
+
+/// Lowercased alphanumeric words of `text`.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Debug, Default)]
+pub struct ServiceCatalogue {
+    services: HashMap<String, ServiceMetadata>,
+    /// Term -> service id -> weight of the term in that service.
+    postings: BTreeMap<String, HashMap<String, f64>>,
+}
+
+impl ServiceCatalogue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a service, replacing any earlier version of it.
+    pub fn insert(&mut self, service: ServiceMetadata) {
+        let service_id = service.id.0.clone();
+        self.remove(&service_id);
+
+        for (term, weight) in term_weights(&service) {
+            self.postings.entry(term).or_default().insert(service_id.clone(), weight);
+        }
+        self.services.insert(service_id, service);
+    }
+
+    pub fn remove(&mut self, service_id: &str) -> Option<ServiceMetadata> {
+        let service = self.services.remove(service_id)?;
+        for term in term_weights(&service).into_keys() {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(service_id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        Some(service)
+    }
+
+    /// Services matching every word of `query`, either exactly or as a prefix
+    /// of an indexed word. An empty query lists everything.
+    pub fn search(&self, query: &str, sort: ServiceSort, limit: Option<usize>) -> Vec<ServiceMetadata> {
+        let terms: HashSet<String> = tokenize(query).collect();
+
+        let mut scores: HashMap<&str, f64> = if terms.is_empty() {
+            self.services.keys().map(|id| (id.as_str(), 0.0)).collect()
+        } else {
+            let mut terms = terms.iter();
+            let first = terms.next().expect("terms is not empty");
+            let mut scores = self.matches(first);
+            for term in terms {
+                if scores.is_empty() {
+                    break;
+                }
+                let matches = self.matches(term);
+                scores.retain(|id, _| matches.contains_key(id));
+                for (id, score) in scores.iter_mut() {
+                    *score += matches[id];
+                }
+            }
+            scores
+        };
+
+        let mut results: Vec<(&ServiceMetadata, f64)> = scores
+            .drain()
+            .filter_map(|(id, score)| self.services.get(id).map(|service| (service, score)))
+            .collect();
+        results.sort_by(|a, b| rank(a, b, sort));
+        results
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(service, _)| service.clone())
+            .collect()
+    }
+
+    /// Best weight per service for one query term, over every indexed term it prefixes.
+    fn matches(&self, term: &str) -> HashMap<&str, f64> {
+        let mut matches: HashMap<&str, f64> = HashMap::new();
+        for (indexed, postings) in self.postings.range(term.to_string()..) {
+            if !indexed.starts_with(term) {
+                break;
+            }
+            let factor = if indexed == term { 1.0 } else { PREFIX_FACTOR };
+            for (id, weight) in postings {
+                let best = matches.entry(id.as_str()).or_insert(0.0);
+                *best = best.max(weight * factor);
+            }
+        }
+        matches
+    }
+}
+
+fn term_weights(service: &ServiceMetadata) -> HashMap<String, f64> {
+    let mut weights = HashMap::new();
+    let fields = [
+        (service.name.as_str(), NAME_WEIGHT),
+        (service.description.as_str(), DESCRIPTION_WEIGHT),
+    ];
+    let tags = service.tags.iter().map(|tag| (tag.as_str(), TAG_WEIGHT));
+    for (text, weight) in fields.into_iter().chain(tags) {
+        for term in tokenize(text) {
+            *weights.entry(term).or_insert(0.0) += weight;
+        }
+    }
+    weights
+}
+
+fn rank(a: &(&ServiceMetadata, f64), b: &(&ServiceMetadata, f64), sort: ServiceSort) -> Ordering {
+    let relevance = b.1.total_cmp(&a.1);
+    let reputation = b.0.reputation_score.total_cmp(&a.0.reputation_score);
+    let price = a.0.price.cmp(&b.0.price);
+    let primary = match sort {
+        ServiceSort::Relevance => relevance.then(reputation).then(price),
+        ServiceSort::Price => price.then(relevance).then(reputation),
+        ServiceSort::Reputation => reputation.then(relevance).then(price),
+    };
+    primary.then_with(|| a.0.id.0.cmp(&b.0.id.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_structures::ServiceId;
+
+    fn service(id: &str, name: &str, description: &str, tags: &[&str], price: u64, reputation: f64) -> ServiceMetadata {
+        ServiceMetadata {
+            id: ServiceId(id.to_string()),
+            provider_did: "did:duxnet:provider".to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+            endpoint: "tcp://127.0.0.1:8080".to_string(),
+            price,
+            reputation_score: reputation,
+            last_updated: 1,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            credentials: vec![],
+        }
+    }
+
+    fn ids(services: Vec<ServiceMetadata>) -> Vec<String> {
+        services.into_iter().map(|service| service.id.0).collect()
+    }
+
+    fn catalogue() -> ServiceCatalogue {
+        let mut catalogue = ServiceCatalogue::new();
+        catalogue.insert(service("render", "GPU Rendering", "Renders frames on a GPU farm", &["3d"], 50, 4.0));
+        catalogue.insert(service("vision", "Image Analysis", "Computer vision on the GPU", &["ml", "images"], 20, 4.5));
+        catalogue.insert(service("storage", "Cold Storage", "Archive your images", &[], 5, 3.0));
+        catalogue
+    }
+
+    #[test]
+    fn test_multi_term_and_prefix_queries() {
+        let catalogue = catalogue();
+
+        // Every term has to match; a name match outranks a description match.
+        assert_eq!(ids(catalogue.search("gpu", ServiceSort::Relevance, None)), vec!["render", "vision"]);
+        assert_eq!(ids(catalogue.search("GPU vision", ServiceSort::Relevance, None)), vec!["vision"]);
+        assert!(catalogue.search("gpu archive", ServiceSort::Relevance, None).is_empty());
+
+        // Prefixes match, and tags are searchable.
+        assert_eq!(ids(catalogue.search("rend", ServiceSort::Relevance, None)), vec!["render"]);
+        assert_eq!(ids(catalogue.search("image", ServiceSort::Relevance, None)), vec!["vision", "storage"]);
+        assert_eq!(catalogue.search("", ServiceSort::Relevance, None).len(), 3);
+    }
+
+    #[test]
+    fn test_sort_orders_and_limit() {
+        let catalogue = catalogue();
+
+        assert_eq!(ids(catalogue.search("", ServiceSort::Price, None)), vec!["storage", "vision", "render"]);
+        assert_eq!(ids(catalogue.search("", ServiceSort::Reputation, Some(2))), vec!["vision", "render"]);
+        // Equal relevance falls back to reputation.
+        assert_eq!(ids(catalogue.search("on", ServiceSort::Relevance, None)), vec!["vision", "render"]);
+    }
+
+    #[test]
+    fn test_updates_and_removals_leave_no_stale_terms() {
+        let mut catalogue = catalogue();
+
+        catalogue.insert(service("render", "CPU Rendering", "Renders frames", &[], 40, 4.0));
+        assert_eq!(ids(catalogue.search("gpu", ServiceSort::Relevance, None)), vec!["vision"]);
+        assert_eq!(ids(catalogue.search("cpu", ServiceSort::Relevance, None)), vec!["render"]);
+
+        catalogue.remove("render");
+        catalogue.remove("vision");
+        assert!(catalogue.search("gpu", ServiceSort::Relevance, None).is_empty());
+        assert!(!catalogue.postings.contains_key("gpu"));
+        assert_eq!(catalogue.services.len(), 1);
+    }
+}
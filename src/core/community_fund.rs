@@ -1,17 +1,15 @@
 use crate::core::data_structures::*;
-use crate::wallet::{Currency, Wallet, MultiSigWallet};
+use crate::wallet::Currency;
 use anyhow::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{info, error, warn};
-use serde::{Deserialize, Serialize};
 
 pub struct CommunityFundManager {
     funds: Arc<RwLock<HashMap<Currency, CommunityFund>>>,
     dht: Arc<crate::core::dht::DHT>,
     distribution_interval: u64, // 12 hours in seconds
-    trusted_nodes: Vec<String>, // DIDs of trusted nodes for signing distributions
 }
 
 impl CommunityFundManager {
@@ -33,11 +31,6 @@ impl CommunityFundManager {
             funds: Arc::new(RwLock::new(funds)),
             dht,
             distribution_interval: 12 * 60 * 60, // 12 hours
-            trusted_nodes: vec![
-                "did:duxnet:trusted-node-1".to_string(),
-                "did:duxnet:trusted-node-2".to_string(),
-                "did:duxnet:trusted-node-3".to_string(),
-            ],
         }
     }
 
@@ -55,8 +48,8 @@ impl CommunityFundManager {
             info!("Added {} {} tax to community fund. New balance: {}", 
                   tax_amount, currency.symbol(), fund.balance);
             
-            // Store updated balance in DHT
-            self.store_fund_balance(&currency, fund.balance).await?;
+            // Store updated state in DHT
+            self.store_fund_state(&currency, fund).await?;
         }
         
         Ok(())
@@ -68,12 +61,6 @@ impl CommunityFundManager {
         funds.get(currency).map(|f| f.balance).unwrap_or(0)
     }
 
-    /// Get all community fund balances
-    pub async fn get_all_fund_balances(&self) -> HashMap<Currency, CommunityFund> {
-        let funds = self.funds.read().await;
-        funds.clone()
-    }
-
     /// Check if community fund should be distributed
     pub async fn should_distribute(&self, currency: &Currency) -> bool {
         // First check if the fund exists and has balance
@@ -223,11 +210,7 @@ impl CommunityFundManager {
         for (currency, fund) in funds.iter() {
             let now = get_current_timestamp();
             let next_distribution = fund.last_distribution + self.distribution_interval;
-            let next_distribution_in = if next_distribution > now {
-                next_distribution - now
-            } else {
-                0
-            };
+            let next_distribution_in = next_distribution.saturating_sub(now);
 
             let balance_info = CommunityFundBalance {
                 currency: currency.symbol().to_string(),
@@ -284,14 +267,6 @@ impl CommunityFundManager {
         Ok(tx_id)
     }
 
-    /// Store fund balance in DHT
-    async fn store_fund_balance(&self, currency: &Currency, balance: u64) -> Result<()> {
-        let key = format!("community_fund_balance_{}", currency.symbol());
-        let value = serde_json::to_string(&balance)?;
-        self.dht.as_ref().store(key, value.into_bytes(), 86400).await?;
-        Ok(())
-    }
-
     /// Store fund state in DHT
     async fn store_fund_state(&self, currency: &Currency, fund: &CommunityFund) -> Result<()> {
         let key = format!("community_fund_state_{}", currency.symbol());
@@ -307,7 +282,7 @@ impl CommunityFundManager {
         if let Some(data) = self.dht.get(&key).await {
             if let Ok(fund) = serde_json::from_slice::<CommunityFund>(&data) {
                 let mut funds = self.funds.write().await;
-                funds.insert(currency.clone(), fund.clone());
+                funds.insert(*currency, fund.clone());
                 info!("Loaded community fund state for {}: balance {}", 
                       currency.symbol(), fund.balance);
             }
@@ -330,10 +305,11 @@ mod tests {
     use super::*;
     use crate::core::dht::DHT;
     use crate::core::data_structures::NodeId;
+    use crate::core::identity::DIDManager;
 
     #[tokio::test]
     async fn test_tax_calculation() {
-        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())));
+        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())).with_identity(DIDManager::new(vec![])));
         let cf_manager = CommunityFundManager::new(dht);
         
         // Test 5% tax calculation
@@ -344,7 +320,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_tax_to_fund() {
-        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())));
+        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())).with_identity(DIDManager::new(vec![])));
         let cf_manager = CommunityFundManager::new(dht);
         
         // Add tax to BTC fund
@@ -362,7 +338,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_should_distribute() {
-        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())));
+        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())).with_identity(DIDManager::new(vec![])));
         let cf_manager = CommunityFundManager::new(dht);
         
         // Initially should not distribute (no time has passed)
@@ -377,7 +353,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_stats() {
-        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())));
+        let dht = Arc::new(DHT::new(NodeId("test-node".to_string())).with_identity(DIDManager::new(vec![])));
         let cf_manager = CommunityFundManager::new(dht);
         
         // Add funds to multiple currencies
@@ -0,0 +1,175 @@
+use crate::core::keystore::Password;
+use crate::core::quota::QuotaPolicy;
+use crate::core::reputation::{ScoringStrategy, UnbackedPolicy};
+use crate::network::discovery::DEFAULT_DISCOVERY_PORT;
+use anyhow::Result;
+use std::env;
+use std::path::PathBuf;
+
+pub const DEFAULT_P2P_PORT: u16 = 8080;
+pub const DEFAULT_API_PORT: u16 = 8081;
+
+/// Ports and peer discovery settings for a node.
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    pub p2p_port: u16,
+    pub api_port: u16,
+    /// Where other nodes reach this one (`tcp://host:port`), published in its
+    /// DID document and service listings. Unset, it is `tcp://127.0.0.1:<p2p_port>`,
+    /// which only nodes on the same machine can dial.
+    pub advertised_endpoint: Option<String>,
+    /// Endpoints (`tcp://host:port`) dialed on startup and retried while unreachable.
+    pub bootstrap_peers: Vec<String>,
+    pub lan_discovery: bool,
+    pub discovery_port: u16,
+    /// Directory the node keeps its identity, DHT records, escrows, tasks,
+    /// messages and reputation in across restarts. Everything stays in memory
+    /// only when unset.
+    pub data_dir: Option<PathBuf>,
+    /// Encrypts the keys kept in `data_dir`, which are never stored in the
+    /// clear; a node with a data directory will not start without one.
+    pub keystore_password: Option<Password>,
+    /// Limits on what each publisher may store in this node's DHT.
+    pub dht_quotas: QuotaPolicy,
+    /// How reputation scores are computed. Under EigenTrust the node's own DID
+    /// is always pre-trusted, besides any listed here.
+    pub reputation_scoring: ScoringStrategy,
+    /// What attestations count for when they name no settled escrow or task
+    /// between attester and target.
+    pub unbacked_attestations: UnbackedPolicy,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            p2p_port: DEFAULT_P2P_PORT,
+            api_port: DEFAULT_API_PORT,
+            advertised_endpoint: None,
+            bootstrap_peers: Vec::new(),
+            lan_discovery: false,
+            discovery_port: DEFAULT_DISCOVERY_PORT,
+            data_dir: None,
+            keystore_password: None,
+            dht_quotas: QuotaPolicy::default(),
+            reputation_scoring: ScoringStrategy::default(),
+            unbacked_attestations: UnbackedPolicy::default(),
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Reads `DUXNET_P2P_PORT`, `DUXNET_API_PORT`, `DUXNET_ADVERTISED_ENDPOINT`,
+    /// `DUXNET_BOOTSTRAP_PEERS` (comma separated), `DUXNET_LAN_DISCOVERY`, `DUXNET_DISCOVERY_PORT`,
+    /// `DUXNET_DATA_DIR`, `DUXNET_KEYSTORE_PASSWORD`, `DUXNET_REPUTATION_SCORING`
+    /// (`eigentrust` or `average`), `DUXNET_PRE_TRUSTED_DIDS` (comma separated)
+    /// and `DUXNET_UNBACKED_ATTESTATIONS` (`accept`, `reject` or the fraction
+    /// they count for), falling back to the defaults for anything unset.
+    pub fn from_env() -> Result<Self> {
+        let mut config = NodeConfig::default();
+
+        if let Some(port) = port_from_env("DUXNET_P2P_PORT")? {
+            config.p2p_port = port;
+        }
+        if let Some(port) = port_from_env("DUXNET_API_PORT")? {
+            config.api_port = port;
+        }
+        if let Some(port) = port_from_env("DUXNET_DISCOVERY_PORT")? {
+            config.discovery_port = port;
+        }
+        if let Ok(endpoint) = env::var("DUXNET_ADVERTISED_ENDPOINT") {
+            config.advertised_endpoint = Some(parse_endpoint(&endpoint)?);
+        }
+        if let Ok(peers) = env::var("DUXNET_BOOTSTRAP_PEERS") {
+            config.bootstrap_peers = parse_peer_list(&peers);
+        }
+        config.lan_discovery = env::var("DUXNET_LAN_DISCOVERY").unwrap_or_default() == "1";
+        if let Ok(path) = env::var("DUXNET_DATA_DIR") {
+            config.data_dir = Some(PathBuf::from(path));
+        }
+        if let Ok(password) = env::var("DUXNET_KEYSTORE_PASSWORD") {
+            config.keystore_password = Some(Password::new(password));
+        }
+        match env::var("DUXNET_REPUTATION_SCORING").as_deref().map(str::trim) {
+            Ok("average") => config.reputation_scoring = ScoringStrategy::Average,
+            Ok("eigentrust") | Err(_) => {}
+            Ok(other) => {
+                return Err(anyhow::anyhow!("DUXNET_REPUTATION_SCORING must be eigentrust or average, got {:?}", other))
+            }
+        }
+        if let (Ok(dids), ScoringStrategy::EigenTrust(eigentrust)) =
+            (env::var("DUXNET_PRE_TRUSTED_DIDS"), &mut config.reputation_scoring)
+        {
+            eigentrust.pre_trusted = parse_peer_list(&dids);
+        }
+        if let Ok(policy) = env::var("DUXNET_UNBACKED_ATTESTATIONS") {
+            config.unbacked_attestations = parse_unbacked_policy(&policy)?;
+        }
+
+        Ok(config)
+    }
+}
+
+fn port_from_env(name: &str) -> Result<Option<u16>> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("{} must be a port number, got {:?}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_unbacked_policy(value: &str) -> Result<UnbackedPolicy> {
+    match value.trim() {
+        "accept" => Ok(UnbackedPolicy::Accept),
+        "reject" => Ok(UnbackedPolicy::Reject),
+        fraction => match fraction.parse::<f64>() {
+            Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(UnbackedPolicy::Discount(fraction)),
+            _ => Err(anyhow::anyhow!(
+                "DUXNET_UNBACKED_ATTESTATIONS must be accept, reject or a fraction from 0 to 1, got {:?}",
+                value
+            )),
+        },
+    }
+}
+
+fn parse_endpoint(value: &str) -> Result<String> {
+    let endpoint = value.trim();
+    match endpoint.strip_prefix("tcp://").and_then(|address| address.rsplit_once(':')) {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(endpoint.to_string()),
+        _ => Err(anyhow::anyhow!("DUXNET_ADVERTISED_ENDPOINT must look like tcp://host:port, got {:?}", value)),
+    }
+}
+
+fn parse_peer_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|peer| !peer.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_list_ignores_blanks() {
+        assert_eq!(
+            parse_peer_list(" tcp://10.0.0.1:8080, ,tcp://10.0.0.2:9000,"),
+            vec!["tcp://10.0.0.1:8080".to_string(), "tcp://10.0.0.2:9000".to_string()]
+        );
+        assert!(parse_peer_list("").is_empty());
+    }
+
+    #[test]
+    fn test_advertised_endpoint_must_be_dialable() {
+        assert_eq!(parse_endpoint(" tcp://node.example.org:8080 ").unwrap(), "tcp://node.example.org:8080");
+        assert_eq!(parse_endpoint("tcp://[2001:db8::1]:8080").unwrap(), "tcp://[2001:db8::1]:8080");
+        assert!(parse_endpoint("node.example.org:8080").is_err());
+        assert!(parse_endpoint("tcp://node.example.org").is_err());
+        assert!(parse_endpoint("tcp://:8080").is_err());
+    }
+}
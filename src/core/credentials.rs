@@ -0,0 +1,125 @@
+//! Verifiable credentials: claims one DID signs about another, such as "KYC'd
+//! by X" or "certified GPU provider". Anyone can check a credential against the
+//! issuer's resolved DID document, and issuers withdraw credentials by listing
+//! them in a revocation list they publish to the DHT.
+
+use crate::core::signing::{CanonicalEncoder, Signable};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const BASE_CREDENTIAL_TYPE: &str = "VerifiableCredential";
+
+pub fn revocation_list_key(issuer: &str) -> String {
+    format!("revocations:{}", issuer)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiableCredential {
+    /// `urn:uuid:<uuid>`.
+    pub id: String,
+    /// `VerifiableCredential` followed by the specific type, e.g. `KycCredential`.
+    pub types: Vec<String>,
+    pub issuer: String,
+    pub subject: String,
+    pub claims: BTreeMap<String, String>,
+    pub issued_at: u64,
+    pub expires_at: Option<u64>,
+    /// The issuer's signature over the credential's signing payload.
+    pub proof: Vec<u8>,
+}
+
+impl VerifiableCredential {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+}
+
+impl Signable for VerifiableCredential {
+    const DOMAIN: &'static str = "duxnet/credential/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder.str(&self.id).u64(self.types.len() as u64);
+        for credential_type in &self.types {
+            encoder.str(credential_type);
+        }
+        encoder.str(&self.issuer).str(&self.subject).u64(self.claims.len() as u64);
+        for (name, value) in &self.claims {
+            encoder.str(name).str(value);
+        }
+        encoder.u64(self.issued_at).option_u64(self.expires_at);
+    }
+}
+
+/// The IDs of every credential an issuer has revoked, signed by the issuer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationList {
+    pub issuer: String,
+    pub revoked: BTreeSet<String>,
+    pub updated: u64,
+    pub signature: Vec<u8>,
+}
+
+impl Signable for RevocationList {
+    const DOMAIN: &'static str = "duxnet/revocation-list/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder.str(&self.issuer).u64(self.revoked.len() as u64);
+        for id in &self.revoked {
+            encoder.str(id);
+        }
+        encoder.u64(self.updated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_structures::*;
+    use crate::core::dht::DHT;
+    use crate::core::did::DidResolver;
+    use crate::core::identity::DIDManager;
+
+    #[tokio::test]
+    async fn test_credentials_verify_until_expired_or_revoked() {
+        let issuer = DIDManager::new(vec![]);
+        let subject = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(issuer.did.id.clone())).with_identity(issuer.clone());
+        dht.publish_did_document(&issuer.document()).await.unwrap();
+        let resolver = DidResolver::new(dht.clone());
+
+        let claims = BTreeMap::from([("level".to_string(), "2".to_string())]);
+        let credential = issuer.issue_credential(&subject.did.id, "KycCredential", claims, None);
+        assert_eq!(credential.types, [BASE_CREDENTIAL_TYPE, "KycCredential"]);
+        resolver.verify_credential(&credential).await.unwrap();
+
+        let mut tampered = credential.clone();
+        tampered.claims.insert("level".to_string(), "3".to_string());
+        assert!(resolver.verify_credential(&tampered).await.is_err());
+        let forged = VerifiableCredential { issuer: subject.did.id.clone(), ..credential.clone() };
+        assert!(resolver.verify_credential(&forged).await.is_err());
+
+        let expired = issuer.issue_credential(&subject.did.id, "KycCredential", BTreeMap::new(), Some(1));
+        assert!(resolver.verify_credential(&expired).await.is_err());
+
+        let revocations = issuer.sign_revocation_list(BTreeSet::from([credential.id.clone()]));
+        dht.publish_revocation_list(&revocations).await.unwrap();
+        assert!(resolver.verify_credential(&credential).await.is_err());
+
+        // A list the issuer did not sign leaves its credentials unverifiable, not valid.
+        let other = issuer.issue_credential(&subject.did.id, "GpuProvider", BTreeMap::new(), None);
+        let mut forged_list = issuer.sign_revocation_list(BTreeSet::from([other.id.clone()]));
+        forged_list.signature = subject.sign(&forged_list);
+        dht.store_entry_local(crate::core::dht::DHTEntry::signed(
+            &issuer,
+            revocation_list_key(&issuer.did.id),
+            serde_json::to_vec(&forged_list).unwrap(),
+            3600,
+            10,
+        ))
+        .await
+        .unwrap();
+        assert!(resolver.is_revoked(&issuer.did.id, &other.id).await.is_err());
+        assert!(resolver.verify_credential(&other).await.is_err());
+    }
+}
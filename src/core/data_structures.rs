@@ -1,4 +1,8 @@
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::core::credentials::VerifiableCredential;
+use crate::core::dht::DHTEntry;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -14,6 +18,7 @@ pub struct TaskId(pub String);
 
 // Decentralized Identity
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct DID {
     pub id: String,
     pub public_key: Vec<u8>,
@@ -32,6 +37,11 @@ pub struct ServiceMetadata {
     pub price: u64,
     pub reputation_score: f64,
     pub last_updated: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Credentials issued to `provider_did`. Check them with `DidResolver::verify_credential`.
+    #[serde(default)]
+    pub credentials: Vec<VerifiableCredential>,
 }
 
 // Reputation system
@@ -43,6 +53,16 @@ pub struct ReputationAttestation {
     pub interaction_type: String,
     pub timestamp: u64,
     pub signature: Vec<u8>,
+    /// The escrow or task the rating is about, if the attester names one.
+    #[serde(default)]
+    pub interaction: Option<InteractionRef>,
+}
+
+/// An escrow or task two DIDs took part in, by id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InteractionRef {
+    Escrow(String),
+    Task(TaskId),
 }
 
 // Escrow system
@@ -75,6 +95,9 @@ pub struct Task {
     pub id: TaskId,
     pub escrow_id: String,
     pub service_id: ServiceId,
+    /// The local identity or peer that submitted the task.
+    #[serde(default)]
+    pub submitter_did: String,
     pub payload: Vec<u8>,
     pub requirements: TaskRequirements,
     pub created_at: u64,
@@ -94,6 +117,9 @@ pub struct TaskResult {
     pub result: Vec<u8>,
     pub proof: Vec<u8>,
     pub completed_at: u64,
+    /// The escrow that paid for the task.
+    #[serde(default)]
+    pub escrow_id: String,
 }
 
 // Messaging system
@@ -148,7 +174,7 @@ pub struct Conversation {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NetworkMessage {
     // Service discovery
-    ServiceAnnouncement(ServiceMetadata),
+    ServiceAnnouncement(DHTEntry), // the provider's signed service:{id} record
     ServiceQuery(String),
     ServiceResponse(Vec<ServiceMetadata>),
     
@@ -175,6 +201,65 @@ pub enum NetworkMessage {
     // P2P ping/pong
     Ping,
     Pong,
+
+    // Topic pub/sub, relayed hop by hop
+    Gossip(GossipEnvelope),
+
+    // Request/response correlation: the reply to `Request(id, ..)` is `Response(id, ..)`.
+    // Neither may carry another request or response.
+    Request(u64, #[serde(deserialize_with = "deserialize_unnested")] Box<NetworkMessage>),
+    Response(u64, #[serde(deserialize_with = "deserialize_unnested")] Box<NetworkMessage>),
+
+    // Kademlia DHT RPCs
+    DhtFindNode(String), // key
+    DhtFindValue(String), // key
+    DhtStore(DHTEntry),
+    DhtNodes(Vec<PeerContact>),
+    DhtValue(DHTEntry),
+    DhtStoreAck(bool),
+}
+
+thread_local! {
+    /// Set while the body of a `Request` or `Response` is being decoded.
+    static DECODING_NESTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Decodes the message inside a `Request` or `Response`. Decoding recurses, so
+/// a frame nesting requests millions deep would overflow the reader's stack;
+/// a second level is refused before it is entered.
+fn deserialize_unnested<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<NetworkMessage>, D::Error> {
+    if DECODING_NESTED.with(|nested| nested.replace(true)) {
+        return Err(D::Error::custom("requests and responses cannot be nested"));
+    }
+    let message = Box::<NetworkMessage>::deserialize(deserializer);
+    DECODING_NESTED.with(|nested| nested.set(false));
+    message
+}
+
+/// How to reach a peer: its DID and a dialable `tcp://host:port` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerContact {
+    pub did: String,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GossipEnvelope {
+    pub message_id: String,
+    pub topic: String,
+    pub origin_did: String,
+    pub hops: u8,
+    pub max_hops: u8,
+    pub payload: GossipPayload,
+}
+
+/// What may be gossiped: records that carry their own signatures, so they can
+/// be checked whoever relays them. Kept apart from `NetworkMessage` so that an
+/// envelope cannot nest another message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GossipPayload {
+    ServiceAnnouncement(DHTEntry),
+    ReputationAttestation(ReputationAttestation),
 }
 
 // API request/response structures
@@ -183,6 +268,10 @@ pub struct RegisterServiceRequest {
     pub name: String,
     pub description: String,
     pub price: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub credentials: Vec<VerifiableCredential>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -195,6 +284,22 @@ pub struct RegisterServiceResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FindServicesRequest {
     pub query: String,
+    #[serde(default)]
+    pub sort: ServiceSort,
+    pub limit: Option<usize>,
+}
+
+/// Order of service search results. Ties fall back to relevance, then to
+/// higher reputation and lower price.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceSort {
+    #[default]
+    Relevance,
+    /// Cheapest first.
+    Price,
+    /// Best reputation first.
+    Reputation,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,58 +1,475 @@
+use crate::core::catalogue::ServiceCatalogue;
 use crate::core::data_structures::*;
+use crate::core::credentials::{revocation_list_key, RevocationList};
+use crate::core::dht_store::{DhtStore, MemoryStore};
+use crate::core::did::{did_document_key, DidDocument};
+use crate::core::identity::DIDManager;
+use crate::core::lookup;
+use crate::core::quota::{PublisherUsage, QuotaPolicy, QuotaTracker};
+use crate::core::routing_table::{routing_key, InsertOutcome, RoutingTable, DEFAULT_K};
+use crate::core::snapshot::{self, ImportReport, MergePolicy};
 use anyhow::Result;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{debug, info};
 
-#[derive(Debug, Clone)]
+const RECORD_DOMAIN: &[u8] = b"duxnet-dht-record-v1";
+
+/// Records this node keeps alive for as long as it runs. Everything else it
+/// publishes (escrows, fund transactions, attestations) is left to expire.
+const REPUBLISHED_PREFIXES: &[&str] = &["service:", "aoi:", "active_did:", "did_doc:", "revocations:"];
+
+/// A DHT record. Records are signed by their publisher, who owns the key until
+/// the record expires; only a higher `seq` from the same publisher replaces it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DHTEntry {
     pub key: String,
     pub value: Vec<u8>,
     pub timestamp: u64,
     pub ttl: u64,
+    /// Derived from `publisher_key`. For a DID that has rotated its key this is
+    /// the current key's own DID, not the rotated one.
+    pub publisher_did: String,
+    /// Ed25519 key the record is signed with; `publisher_did` must be derived from it.
+    pub publisher_key: Vec<u8>,
+    /// Increases with every update the publisher makes to the key.
+    pub seq: u64,
+    pub signature: Vec<u8>,
 }
 
-#[derive(Debug, Clone)]
+impl DHTEntry {
+    /// Builds a record for `key` and signs it as `publisher`.
+    pub fn signed(publisher: &DIDManager, key: String, value: Vec<u8>, ttl: u64, seq: u64) -> Self {
+        let publisher_key = publisher.get_public_key();
+        let mut entry = DHTEntry {
+            key,
+            value,
+            timestamp: get_current_timestamp(),
+            ttl,
+            publisher_did: DIDManager::did_from_public_key(&publisher_key),
+            publisher_key,
+            seq,
+            signature: Vec::new(),
+        };
+        entry.signature = publisher.sign_message(&entry.signing_payload());
+        entry
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.timestamp + self.ttl
+    }
+
+    /// Digest of every field except the signature. Variable-length fields are
+    /// length-prefixed so no two records share a payload.
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(RECORD_DOMAIN);
+        for field in [self.key.as_bytes(), &self.value, self.publisher_did.as_bytes(), &self.publisher_key] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field);
+        }
+        hasher.update(self.timestamp.to_be_bytes());
+        hasher.update(self.ttl.to_be_bytes());
+        hasher.update(self.seq.to_be_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Checks that the record is signed by the key its publisher DID belongs to.
+    pub fn verify(&self) -> Result<()> {
+        if self.signature.is_empty() {
+            return Err(anyhow::anyhow!("DHT record {} is unsigned", self.key));
+        }
+        if DIDManager::did_from_public_key(&self.publisher_key) != self.publisher_did {
+            return Err(anyhow::anyhow!(
+                "DHT record {} names publisher {} but carries someone else's key",
+                self.key,
+                self.publisher_did
+            ));
+        }
+        if !DIDManager::verify_with_public_key(&self.publisher_key, &self.signature, &self.signing_payload()) {
+            return Err(anyhow::anyhow!("DHT record {} has an invalid signature", self.key));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a record under a key naming a DID (`did_doc:`, `revocations:`,
+/// `active_did:`) or a provider (`service:`) is published by that DID, so
+/// nobody can squat another DID's keys. A service's AOI key (`aoi:`) belongs
+/// to whoever provides the service, so it needs the service's record first.
+fn check_key_binding(store: &dyn DhtStore, entry: &DHTEntry) -> Result<()> {
+    if entry.key.starts_with("did_doc:") {
+        return DidDocument::from_record(entry).map(|_| ());
+    }
+    let did = if let Some(issuer) = entry.key.strip_prefix("revocations:") {
+        let list: RevocationList = serde_json::from_slice(&entry.value)
+            .map_err(|e| anyhow::anyhow!("DHT record {} is not a revocation list: {}", entry.key, e))?;
+        if list.issuer != issuer {
+            return Err(anyhow::anyhow!("DHT record {} holds the revocation list of {}", entry.key, list.issuer));
+        }
+        list.issuer
+    } else if let Some(did) = entry.key.strip_prefix("active_did:") {
+        did.to_string()
+    } else if let Some(service_id) = entry.key.strip_prefix("service:") {
+        let service: ServiceMetadata = serde_json::from_slice(&entry.value)
+            .map_err(|e| anyhow::anyhow!("DHT record {} is not a service: {}", entry.key, e))?;
+        if service.id.0 != service_id {
+            return Err(anyhow::anyhow!("DHT record {} holds service {}", entry.key, service.id.0));
+        }
+        service.provider_did
+    } else if let Some(service_id) = entry.key.strip_prefix("aoi:") {
+        let service = store
+            .get(&format!("service:{}", service_id))
+            .filter(|record| !record.is_expired(get_current_timestamp()))
+            .ok_or_else(|| anyhow::anyhow!("DHT record {} is for service {}, which is not known here", entry.key, service_id))?;
+        serde_json::from_slice::<ServiceMetadata>(&service.value)?.provider_did
+    } else {
+        return Ok(());
+    };
+    if !publishes_for(store, entry, &did) {
+        return Err(anyhow::anyhow!("DHT key {} belongs to {}, not {}", entry.key, did, entry.publisher_did));
+    }
+    Ok(())
+}
+
+/// Whether `entry` is signed by `did`: by the current key of the DID document
+/// held for it, or, without one, by the key the DID was derived from.
+fn publishes_for(store: &dyn DhtStore, entry: &DHTEntry, did: &str) -> bool {
+    let document = store
+        .get(&did_document_key(did))
+        .filter(|record| !record.is_expired(get_current_timestamp()))
+        .and_then(|record| DidDocument::from_record(record).ok());
+    match document {
+        Some(document) => entry.publisher_key == document.current_key().public_key,
+        None => entry.publisher_did == did,
+    }
+}
+
+/// Checks that `entry` may replace `existing`, the live record under its key:
+/// see `DHT::store_entry_local`. Returns false if `entry` is `existing` again,
+/// which leaves nothing to do.
+fn check_replacement(entry: &DHTEntry, existing: &DHTEntry) -> Result<bool> {
+    if is_did_takeover(entry, existing) {
+        return Ok(true);
+    }
+    if existing.publisher_did != entry.publisher_did {
+        return Err(anyhow::anyhow!(
+            "DHT key {} is owned by {}, not {}",
+            entry.key,
+            existing.publisher_did,
+            entry.publisher_did
+        ));
+    }
+    if entry.seq < existing.seq {
+        return Err(anyhow::anyhow!(
+            "Stale write to DHT key {}: seq {} is behind {}",
+            entry.key,
+            entry.seq,
+            existing.seq
+        ));
+    }
+    if entry.seq == existing.seq {
+        if entry.signature == existing.signature {
+            return Ok(false);
+        }
+        return Err(anyhow::anyhow!("Conflicting write to DHT key {} at seq {}", entry.key, entry.seq));
+    }
+    Ok(true)
+}
+
+/// A DID document record from a key the DID rotated to, replacing one from an earlier key.
+fn is_did_takeover(entry: &DHTEntry, existing: &DHTEntry) -> bool {
+    entry.key.starts_with("did_doc:")
+        && existing.publisher_did != entry.publisher_did
+        && DidDocument::supersedes(entry, existing)
+}
+
+pub enum FindValueResult {
+    Found(DHTEntry),
+    Closer(Vec<PeerContact>),
+}
+
+/// The network side of the DHT: liveness checks and the Kademlia RPCs.
+#[async_trait::async_trait]
+pub trait DhtTransport: Send + Sync {
+    /// Checks whether a peer is still reachable before the routing table evicts it.
+    async fn ping(&self, peer_id: &str) -> bool;
+    async fn find_node(&self, contact: &PeerContact, key: &str) -> Result<Vec<PeerContact>>;
+    async fn find_value(&self, contact: &PeerContact, key: &str) -> Result<FindValueResult>;
+    async fn store(&self, contact: &PeerContact, entry: &DHTEntry) -> Result<bool>;
+}
+
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct DHT {
     pub node_id: NodeId,
-    pub entries: Arc<RwLock<HashMap<String, DHTEntry>>>,
-    pub peers: Arc<RwLock<Vec<String>>>,
+    pub entries: Arc<RwLock<Box<dyn DhtStore>>>,
+    pub routing_table: Arc<RwLock<RoutingTable>>,
+    /// Endpoints of the peers in the routing table, by DID.
+    pub contacts: Arc<RwLock<HashMap<String, String>>>,
+    /// Peers known to hold the current version of each record.
+    replicas: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+    /// Search index over the `service:` records in `entries`.
+    catalogue: Arc<RwLock<ServiceCatalogue>>,
+    /// What each publisher holds in `entries`, checked against `quota_policy`.
+    quotas: Arc<RwLock<QuotaTracker>>,
+    quota_policy: QuotaPolicy,
     pub k_bucket_size: usize,
+    identity: Option<DIDManager>,
+    transport: Option<Arc<dyn DhtTransport>>,
 }
 
 impl DHT {
+    /// `node_id` positions this node in the XOR keyspace; peers are added by DID,
+    /// so nodes pass their DID here. Records are kept in memory only.
     pub fn new(node_id: NodeId) -> Self {
+        Self::with_store(node_id, Box::new(MemoryStore::new()))
+    }
+
+    /// Like `new`, but keeps records in `store`, e.g. a `DiskStore` so they
+    /// survive restarts.
+    pub fn with_store(node_id: NodeId, store: Box<dyn DhtStore>) -> Self {
+        let routing_table = RoutingTable::new(&node_id.0, DEFAULT_K);
+        let mut catalogue = ServiceCatalogue::new();
+        let mut quotas = QuotaTracker::new();
+        for entry in store.entries() {
+            quotas.add(entry);
+            if !entry.key.starts_with("service:") {
+                continue;
+            }
+            if let Ok(service) = serde_json::from_slice::<ServiceMetadata>(&entry.value) {
+                catalogue.insert(service);
+            }
+        }
         DHT {
             node_id,
-            entries: Arc::new(RwLock::new(HashMap::new())),
-            peers: Arc::new(RwLock::new(Vec::new())),
-            k_bucket_size: 20,
+            entries: Arc::new(RwLock::new(store)),
+            routing_table: Arc::new(RwLock::new(routing_table)),
+            contacts: Arc::new(RwLock::new(HashMap::new())),
+            replicas: Arc::new(RwLock::new(HashMap::new())),
+            catalogue: Arc::new(RwLock::new(catalogue)),
+            quotas: Arc::new(RwLock::new(quotas)),
+            quota_policy: QuotaPolicy::default(),
+            k_bucket_size: DEFAULT_K,
+            identity: None,
+            transport: None,
         }
     }
 
+    /// Lets the DHT publish records, signed as `identity`. Without one it can
+    /// still hold and serve other nodes' records.
+    pub fn with_identity(mut self, identity: DIDManager) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Replaces the default `QuotaPolicy` records are checked against.
+    pub fn with_quotas(mut self, policy: QuotaPolicy) -> Self {
+        self.quota_policy = policy;
+        self
+    }
+
+    /// Connects the DHT to the network. Without a transport every operation
+    /// stays local and full buckets always keep their oldest peers.
+    pub fn with_transport(mut self, transport: Arc<dyn DhtTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Publishes a record locally and replicates it to the `k` nodes closest to its key.
     pub async fn store(&self, key: String, value: Vec<u8>, ttl: u64) -> Result<()> {
-        let entry = DHTEntry {
-            key: key.clone(),
-            value,
-            ttl,
-            timestamp: get_current_timestamp(),
+        self.publish(key, value, ttl).await.map(|_| ())
+    }
+
+    /// Like `store`, but returns the signed record so it can also be gossiped.
+    pub async fn publish(&self, key: String, value: Vec<u8>, ttl: u64) -> Result<DHTEntry> {
+        let entry = self.sign_record(key, value, ttl).await?;
+        self.store_entry_local(entry.clone()).await?;
+
+        if let Some(transport) = &self.transport {
+            let closest = lookup::find_node(self, transport.as_ref(), &entry.key).await;
+            let holders = lookup::replicate(transport.as_ref(), &closest, &entry).await;
+            debug!("Replicated DHT entry {} to {} of {} peers", entry.key, holders.len(), closest.len());
+            self.replicas.write().await.insert(entry.key.clone(), holders.into_iter().collect());
+        }
+        Ok(entry)
+    }
+
+    /// Signs a new version of `key` as this node, one `seq` past the copy we hold.
+    async fn sign_record(&self, key: String, value: Vec<u8>, ttl: u64) -> Result<DHTEntry> {
+        let identity = self
+            .identity
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("This DHT has no identity to sign records with"))?;
+        let seq = match self.entries.read().await.get(&key) {
+            Some(existing) if !existing.is_expired(get_current_timestamp()) => existing.seq + 1,
+            _ => 1,
         };
-        
+        Ok(DHTEntry::signed(identity, key, value, ttl, seq))
+    }
+
+    /// Stores a record on this node only, e.g. one received from a peer.
+    ///
+    /// The record must carry a valid signature. While a record is live its
+    /// publisher owns the key: writes from anyone else are rejected, and the
+    /// publisher's own writes must raise `seq`. The same record arriving twice
+    /// is accepted as a no-op; a different record with the same `seq` is not.
+    /// A DID document that extends the update chain of the live one replaces
+    /// it whatever key it is signed with, so a rotated key can take over.
+    /// Keys that name a DID only take records that DID published; see
+    /// `check_key_binding`. Writes that would take the publisher over its
+    /// quota fail with a `QuotaError`.
+    pub async fn store_entry_local(&self, entry: DHTEntry) -> Result<()> {
+        entry.verify()?;
+
         let mut store = self.entries.write().await;
-        store.insert(key.clone(), entry);
-        debug!("Stored DHT entry: {}", key);
+        let replaced = store.get(&entry.key).cloned();
+        let live = replaced.as_ref().filter(|e| !e.is_expired(get_current_timestamp()));
+        if let Some(existing) = live {
+            if !check_replacement(&entry, existing)? {
+                return Ok(());
+            }
+        }
+        self.insert_record(store.as_mut(), entry, replaced).await
+    }
+
+    /// Puts a verified record in `store` in place of `replaced`, subject to
+    /// `check_key_binding` and quotas, and updates the replica map and service
+    /// index to match.
+    async fn insert_record(&self, store: &mut dyn DhtStore, entry: DHTEntry, replaced: Option<DHTEntry>) -> Result<()> {
+        check_key_binding(store, &entry)?;
+        let mut quotas = self.quotas.write().await;
+        quotas.check(&self.quota_policy, &entry, replaced.as_ref())?;
+
+        let key = entry.key.clone();
+        let service = key
+            .starts_with("service:")
+            .then(|| serde_json::from_slice::<ServiceMetadata>(&entry.value));
+        debug!("Stored DHT entry: {} (seq {})", key, entry.seq);
+        store.insert(entry.clone())?;
+        if let Some(replaced) = &replaced {
+            quotas.remove(replaced);
+        }
+        quotas.add(&entry);
+        drop(quotas);
+
+        // Nobody is known to hold this version yet.
+        self.replicas.write().await.remove(&key);
+        if let Some(service) = service {
+            let mut catalogue = self.catalogue.write().await;
+            match service {
+                Ok(service) => catalogue.insert(service),
+                Err(e) => {
+                    debug!("Service record {} is not a service: {}", key, e);
+                    catalogue.remove(&key["service:".len()..]);
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Writes every live record this node holds to `writer` in the snapshot
+    /// format, and returns how many were written.
+    pub async fn export_snapshot(&self, writer: &mut impl Write) -> Result<usize> {
+        let now = get_current_timestamp();
+        let mut entries: Vec<DHTEntry> = self
+            .entries
+            .read()
+            .await
+            .entries()
+            .filter(|entry| !entry.is_expired(now))
+            .cloned()
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        snapshot::write_snapshot(writer, &self.node_id.0, &entries)?;
+        debug!("Exported {} DHT entries", entries.len());
+        Ok(entries.len())
+    }
+
+    /// Loads the records of a snapshot into this node, e.g. to seed a fresh
+    /// node. Records are verified and held to quotas as usual; `policy` decides
+    /// what happens when a key is already held here. Nothing is replicated.
+    pub async fn import_snapshot(&self, reader: impl BufRead, policy: MergePolicy) -> Result<ImportReport> {
+        let (header, records) = snapshot::read_snapshot(reader)?;
+        let now = get_current_timestamp();
+        let mut report = ImportReport::default();
+
+        let mut store = self.entries.write().await;
+        for entry in records {
+            if entry.is_expired(now) {
+                report.expired += 1;
+                continue;
+            }
+            if let Err(e) = entry.verify() {
+                debug!("Rejected snapshot record: {}", e);
+                report.rejected += 1;
+                continue;
+            }
+            let replaced = store.get(&entry.key).cloned();
+            if let Some(existing) = replaced.as_ref().filter(|e| !e.is_expired(now)) {
+                match check_replacement(&entry, existing) {
+                    Ok(true) if policy.replaces(&entry, existing) => {}
+                    Ok(_) => {
+                        report.skipped += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!("Rejected snapshot record: {}", e);
+                        report.rejected += 1;
+                        continue;
+                    }
+                }
+            }
+            match self.insert_record(store.as_mut(), entry, replaced).await {
+                Ok(()) => report.imported += 1,
+                Err(e) => {
+                    debug!("Rejected snapshot record: {}", e);
+                    report.rejected += 1;
+                }
+            }
+        }
+
+        info!("Imported DHT snapshot from {}: {:?}", header.node_id, report);
+        Ok(report)
+    }
+
+    /// Looks a key up locally first, then across the network. Values found
+    /// remotely are cached here until they expire.
     pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_entry(key).await.map(|entry| entry.value)
+    }
+
+    /// Like `get`, but returns the whole signed record.
+    pub async fn get_entry(&self, key: &str) -> Option<DHTEntry> {
+        if let Some(entry) = self.get_entry_local(key).await {
+            return Some(entry);
+        }
+
+        let transport = self.transport.as_ref()?;
+        let entry = lookup::find_value(self, transport.as_ref(), key).await?;
+        if let Err(e) = self.store_entry_local(entry.clone()).await {
+            debug!("Failed to cache DHT entry {}: {}", key, e);
+        }
+        Some(entry)
+    }
+
+    /// Whether `entry`'s publisher may hold its key, going by the records held
+    /// here; see `check_key_binding`.
+    pub async fn check_key_binding(&self, entry: &DHTEntry) -> Result<()> {
+        check_key_binding(self.entries.read().await.as_ref(), entry)
+    }
+
+    pub async fn get_entry_local(&self, key: &str) -> Option<DHTEntry> {
         let entries = self.entries.read().await;
-        let now = get_current_timestamp();
         
         if let Some(entry) = entries.get(key) {
-            if now < entry.timestamp + entry.ttl {
+            if !entry.is_expired(get_current_timestamp()) {
                 debug!("Retrieved DHT entry: {}", key);
-                return Some(entry.value.clone());
+                return Some(entry.clone());
             } else {
                 debug!("DHT entry expired: {}", key);
             }
@@ -62,71 +479,42 @@ impl DHT {
 
     pub async fn remove(&self, key: &str) -> Result<()> {
         let mut entries = self.entries.write().await;
-        entries.remove(key);
+        if let Some(removed) = entries.remove(key)? {
+            self.quotas.write().await.remove(&removed);
+        }
+        self.replicas.write().await.remove(key);
+        if let Some(service_id) = key.strip_prefix("service:") {
+            self.catalogue.write().await.remove(service_id);
+        }
         debug!("Removed DHT entry: {}", key);
         Ok(())
     }
 
-    pub async fn announce_service(&self, service: &ServiceMetadata) -> Result<()> {
+    /// Publishes a service and returns its signed record for gossip.
+    pub async fn announce_service(&self, service: &ServiceMetadata) -> Result<DHTEntry> {
         let key = format!("service:{}", service.id.0);
         let value = serde_json::to_vec(service)?;
-        self.store(key, value, 3600).await // 1 hour TTL
-    }
-
-    pub async fn find_services(&self, query: &str) -> Vec<ServiceMetadata> {
-        let entries = self.entries.read().await;
-        let mut services = Vec::new();
-        
-        for (key, entry) in entries.iter() {
-            if key.starts_with("service:") {
-                if let Ok(service) = serde_json::from_slice::<ServiceMetadata>(&entry.value) {
-                    if service.name.to_lowercase().contains(&query.to_lowercase()) || 
-                       service.description.to_lowercase().contains(&query.to_lowercase()) {
-                        services.push(service);
-                    }
-                }
-            }
-        }
-        
-        debug!("Found {} services for query: {}", services.len(), query);
-        services
-    }
-
-    pub async fn store_reputation_attestation(&self, attestation: &ReputationAttestation) -> Result<()> {
-        let key = format!("reputation:{}:{}", attestation.target_did, attestation.timestamp);
-        let value = serde_json::to_vec(attestation)?;
-        self.store(key, value, 86400).await // 24 hour TTL
+        self.publish(key, value, 3600).await // 1 hour TTL
     }
 
-    pub async fn get_reputation_attestations(&self, target_did: &str) -> Vec<ReputationAttestation> {
-        let entries = self.entries.read().await;
-        let mut attestations = Vec::new();
-        
-        for (key, entry) in entries.iter() {
-            if key.starts_with(&format!("reputation:{}:", target_did)) {
-                if let Ok(attestation) = serde_json::from_slice::<ReputationAttestation>(&entry.value) {
-                    attestations.push(attestation);
-                }
-            }
+    /// Keeps a service record announced by another node in the local catalogue.
+    /// Only a service's provider may publish it.
+    pub async fn cache_service(&self, entry: DHTEntry) -> Result<()> {
+        if !entry.key.starts_with("service:") {
+            return Err(anyhow::anyhow!("DHT record {} is not a service record", entry.key));
         }
-        
-        debug!("Found {} reputation attestations for: {}", attestations.len(), target_did);
-        attestations
+        self.store_entry_local(entry).await
     }
 
-    pub async fn store_escrow_contract(&self, contract: &EscrowContract) -> Result<()> {
-        let key = format!("escrow:{}", contract.id);
-        let value = serde_json::to_vec(contract)?;
-        self.store(key, value, 7200).await // 2 hour TTL
+    pub async fn find_services(&self, query: &str) -> Vec<ServiceMetadata> {
+        self.search_services(query, ServiceSort::Relevance, None).await
     }
 
-    pub async fn get_escrow_contract(&self, escrow_id: &str) -> Option<EscrowContract> {
-        let key = format!("escrow:{}", escrow_id);
-        if let Some(value) = self.get(&key).await {
-            serde_json::from_slice(&value).ok()
-        } else {
-            None
-        }
+    /// Searches the local service catalogue; see `ServiceCatalogue::search`.
+    pub async fn search_services(&self, query: &str, sort: ServiceSort, limit: Option<usize>) -> Vec<ServiceMetadata> {
+        let services = self.catalogue.read().await.search(query, sort, limit);
+        debug!("Found {} services for query: {}", services.len(), query);
+        services
     }
 
     pub async fn store_aoi_key(&self, aoi_key: &AOIKey) -> Result<()> {
@@ -159,13 +547,27 @@ impl DHT {
         self.store(key, value_bytes, 86400).await // 24 hour TTL
     }
 
-    pub async fn get_community_fund_transaction(&self, tx_id: &str) -> Option<serde_json::Value> {
-        let key = format!("cf_tx:{}", tx_id);
-        if let Some(value) = self.get(&key).await {
-            serde_json::from_slice(&value).ok()
-        } else {
-            None
-        }
+    /// Publishes this node's DID document, signed with the DID's current key.
+    pub async fn publish_did_document(&self, document: &DidDocument) -> Result<DHTEntry> {
+        let value = serde_json::to_vec(document)?;
+        self.publish(did_document_key(&document.id), value, 86400).await // 24 hour TTL
+    }
+
+    /// Publishes an issuer's revocation list, replacing the previous one.
+    pub async fn publish_revocation_list(&self, list: &RevocationList) -> Result<DHTEntry> {
+        let value = serde_json::to_vec(list)?;
+        self.publish(revocation_list_key(&list.issuer), value, 7 * 86400).await // 7 day TTL
+    }
+
+    /// The revocation list published for `issuer`, if there is one. A record
+    /// that does not hold a list is an error.
+    pub async fn get_revocation_list(&self, issuer: &str) -> Result<Option<RevocationList>> {
+        let Some(value) = self.get(&revocation_list_key(issuer)).await else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("The revocation list published for {} is unreadable: {}", issuer, e))
     }
 
     pub async fn store_active_did(&self, did: &str) -> Result<()> {
@@ -183,8 +585,8 @@ impl DHT {
         let entries = self.entries.read().await;
         let mut active_dids = Vec::new();
         
-        for (key, entry) in entries.iter() {
-            if key.starts_with("active_did:") {
+        for entry in entries.entries() {
+            if entry.key.starts_with("active_did:") {
                 if let Ok(data) = serde_json::from_slice::<serde_json::Value>(&entry.value) {
                     if let Some(did) = data["did"].as_str() {
                         active_dids.push(did.to_string());
@@ -197,38 +599,65 @@ impl DHT {
         active_dids
     }
 
+    /// Records a peer we have heard from. When its bucket is full, the least
+    /// recently seen peer is pinged and only evicted if it does not answer.
     pub async fn add_peer(&self, peer_id: String) -> Result<()> {
-        let mut peers = self.peers.write().await;
-        if !peers.contains(&peer_id) {
-            peers.push(peer_id.clone());
-            if peers.len() > self.k_bucket_size {
-                peers.remove(0); // Remove oldest peer
+        let outcome = self.routing_table.write().await.insert(&peer_id);
+        match outcome {
+            InsertOutcome::Added => debug!("Added peer: {}", peer_id),
+            InsertOutcome::BucketFull { least_recently_seen } => {
+                // Without a transport, long-lived peers win, as in Kademlia.
+                let alive = match &self.transport {
+                    Some(transport) => transport.ping(&least_recently_seen).await,
+                    None => true,
+                };
+                let mut routing_table = self.routing_table.write().await;
+                if alive {
+                    routing_table.mark_alive(&least_recently_seen);
+                } else if let Some(promoted) = routing_table.remove(&least_recently_seen) {
+                    debug!("Evicted unresponsive peer {} in favour of {}", least_recently_seen, promoted);
+                }
             }
-            debug!("Added peer: {}", peer_id);
+            InsertOutcome::Refreshed | InsertOutcome::Ignored => {}
         }
         Ok(())
     }
 
-    pub async fn remove_peer(&self, peer_id: &str) -> Result<()> {
-        let mut peers = self.peers.write().await;
-        peers.retain(|p| p != peer_id);
-        debug!("Removed peer: {}", peer_id);
-        Ok(())
+    /// Like `add_peer`, but also remembers where the peer can be dialed so it can
+    /// be handed out in lookups.
+    pub async fn add_contact(&self, contact: PeerContact) -> Result<()> {
+        self.contacts.write().await.insert(contact.did.clone(), contact.endpoint);
+        self.add_peer(contact.did).await
     }
 
-    pub async fn get_peers(&self) -> Vec<String> {
-        let peers = self.peers.read().await;
-        peers.clone()
+    /// The `count` dialable peers closest to `key`.
+    pub async fn closest_contacts(&self, key: &str, count: usize) -> Vec<PeerContact> {
+        let routing_table = self.routing_table.read().await;
+        let contacts = self.contacts.read().await;
+        routing_table
+            .closest_peers(&routing_key(key), routing_table.len())
+            .into_iter()
+            .filter_map(|did| contacts.get(&did).map(|endpoint| PeerContact { did, endpoint: endpoint.clone() }))
+            .take(count)
+            .collect()
     }
 
     pub async fn cleanup_expired_entries(&self) -> Result<usize> {
-        let mut entries = self.entries.write().await;
         let now = get_current_timestamp();
-        let initial_count = entries.len();
-        
-        entries.retain(|_, entry| now < entry.timestamp + entry.ttl);
+        let expired: Vec<String> = self
+            .entries
+            .read()
+            .await
+            .entries()
+            .filter(|entry| entry.is_expired(now))
+            .map(|entry| entry.key.clone())
+            .collect();
+
+        for key in &expired {
+            self.remove(key).await?;
+        }
         
-        let removed_count = initial_count - entries.len();
+        let removed_count = expired.len();
         if removed_count > 0 {
             debug!("Cleaned up {} expired DHT entries", removed_count);
         }
@@ -236,20 +665,113 @@ impl DHT {
         Ok(removed_count)
     }
 
+    /// Publishes a fresh version of every service, AOI key and active DID record
+    /// this node owns that would expire within `within` seconds.
+    pub async fn republish_owned(&self, within: u64) -> Result<usize> {
+        let Some(identity) = &self.identity else {
+            return Ok(0);
+        };
+        let now = get_current_timestamp();
+        let publisher_did = DIDManager::did_from_public_key(&identity.get_public_key());
+        let due: Vec<DHTEntry> = self
+            .entries
+            .read()
+            .await
+            .entries()
+            .filter(|entry| {
+                entry.publisher_did == publisher_did
+                    && REPUBLISHED_PREFIXES.iter().any(|prefix| entry.key.starts_with(prefix))
+                    && !entry.is_expired(now)
+                    && entry.timestamp + entry.ttl <= now + within
+            })
+            .cloned()
+            .collect();
+
+        for entry in &due {
+            self.publish(entry.key.clone(), entry.value.clone(), entry.ttl).await?;
+        }
+        if !due.is_empty() {
+            debug!("Republished {} owned DHT entries", due.len());
+        }
+        Ok(due.len())
+    }
+
+    /// Sends each live record to the peers now among the `k` closest to its key
+    /// that are not yet known to hold it, e.g. peers that joined since it was
+    /// stored. Returns how many peers were sent a record.
+    pub async fn refresh_replication(&self) -> usize {
+        let Some(transport) = &self.transport else {
+            return 0;
+        };
+        let now = get_current_timestamp();
+        let live: Vec<DHTEntry> = self
+            .entries
+            .read()
+            .await
+            .entries()
+            .filter(|entry| !entry.is_expired(now))
+            .cloned()
+            .collect();
+
+        let mut sent = 0;
+        for entry in live {
+            let targets: Vec<PeerContact> = {
+                let replicas = self.replicas.read().await;
+                let holders = replicas.get(&entry.key);
+                self.closest_contacts(&entry.key, self.k_bucket_size)
+                    .await
+                    .into_iter()
+                    .filter(|contact| !holders.is_some_and(|holders| holders.contains(&contact.did)))
+                    .collect()
+            };
+            if targets.is_empty() {
+                continue;
+            }
+
+            sent += targets.len();
+            let holders = lookup::replicate(transport.as_ref(), &targets, &entry).await;
+            self.replicas.write().await.entry(entry.key.clone()).or_default().extend(holders);
+        }
+        if sent > 0 {
+            debug!("Sent DHT records to {} newly closest peers", sent);
+        }
+        sent
+    }
+
+    /// One maintenance pass: sweep expired records, republish owned records
+    /// that expire within `republish_within` seconds, then refresh replication.
+    pub async fn run_maintenance(&self, republish_within: u64) -> Result<MaintenanceReport> {
+        let expired = self.cleanup_expired_entries().await?;
+        let republished = self.republish_owned(republish_within).await?;
+        let replicated = self.refresh_replication().await;
+        Ok(MaintenanceReport { expired, republished, replicated })
+    }
+
     pub async fn get_stats(&self) -> DHTStats {
         let entries = self.entries.read().await;
-        let peers = self.peers.read().await;
+        let total_peers = self.routing_table.read().await.len();
+        let quotas = self.quotas.read().await;
         
         DHTStats {
             total_entries: entries.len(),
-            total_peers: peers.len(),
-            service_entries: entries.keys().filter(|k| k.starts_with("service:")).count(),
-            reputation_entries: entries.keys().filter(|k| k.starts_with("reputation:")).count(),
-            escrow_entries: entries.keys().filter(|k| k.starts_with("escrow:")).count(),
+            total_peers,
+            service_entries: entries.entries().filter(|e| e.key.starts_with("service:")).count(),
+            reputation_entries: entries.entries().filter(|e| e.key.starts_with("reputation:")).count(),
+            escrow_entries: entries.entries().filter(|e| e.key.starts_with("escrow:")).count(),
+            quota_rejections: quotas.rejected(),
+            publisher_usage: quotas.usage(),
         }
     }
 }
 
+/// What a `DHT::run_maintenance` pass did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub expired: usize,
+    pub republished: usize,
+    pub replicated: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct DHTStats {
     pub total_entries: usize,
@@ -257,4 +779,389 @@ pub struct DHTStats {
     pub service_entries: usize,
     pub reputation_entries: usize,
     pub escrow_entries: usize,
-} 
\ No newline at end of file
+    /// Writes refused for exceeding a quota since the node started.
+    pub quota_rejections: u64,
+    /// What each publisher holds, largest first.
+    pub publisher_usage: Vec<PublisherUsage>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::core::dht_store::DiskStore;
+    use crate::core::quota::{QuotaError, QuotaLimits};
+
+    /// A listing for tests to adjust with struct-update syntax.
+    pub(crate) fn service(id: &str, provider_did: &str) -> ServiceMetadata {
+        ServiceMetadata {
+            id: ServiceId(id.to_string()),
+            provider_did: provider_did.to_string(),
+            name: "GPU Rendering".to_string(),
+            description: "Renders frames".to_string(),
+            endpoint: "tcp://127.0.0.1:8080".to_string(),
+            price: 10,
+            reputation_score: 0.0,
+            last_updated: 1,
+            tags: vec![],
+            credentials: vec![],
+        }
+    }
+
+    fn record(publisher: &DIDManager, value: &[u8], seq: u64) -> DHTEntry {
+        DHTEntry::signed(publisher, "escrow:e1".to_string(), value.to_vec(), 3600, seq)
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_and_forged_records_are_rejected() {
+        let dht = DHT::new(NodeId("local".to_string()));
+        let publisher = DIDManager::new(vec![]);
+
+        let mut unsigned = record(&publisher, b"v1", 1);
+        unsigned.signature.clear();
+        assert!(dht.store_entry_local(unsigned).await.is_err());
+
+        let mut tampered = record(&publisher, b"v1", 1);
+        tampered.value = b"v2".to_vec();
+        assert!(dht.store_entry_local(tampered).await.is_err());
+
+        // Signed correctly, but by a key that is not the named publisher's.
+        let mut impersonated = record(&DIDManager::new(vec![]), b"v1", 1);
+        impersonated.publisher_did = publisher.did.id.clone();
+        assert!(dht.store_entry_local(impersonated).await.is_err());
+
+        assert!(dht.store("escrow:e1".to_string(), b"v1".to_vec(), 60).await.is_err());
+        assert!(dht.get_entry_local("escrow:e1").await.is_none());
+    }
+
+    /// While a record is live, its publisher owns the key and must raise `seq`
+    /// to change it. A redelivered record is a no-op; a different record at the
+    /// same `seq` is a conflict. Once the record expires, anyone may claim the key.
+    #[tokio::test]
+    async fn test_conflict_resolution() {
+        let dht = DHT::new(NodeId("local".to_string()));
+        let owner = DIDManager::new(vec![]);
+        let intruder = DIDManager::new(vec![]);
+
+        dht.store_entry_local(record(&owner, b"v2", 2)).await.unwrap();
+        assert!(dht.store_entry_local(record(&owner, b"v1", 1)).await.is_err());
+        assert!(dht.store_entry_local(record(&owner, b"other", 2)).await.is_err());
+        assert!(dht.store_entry_local(record(&intruder, b"mine", 3)).await.is_err());
+
+        let current = dht.get_entry_local("escrow:e1").await.unwrap();
+        dht.store_entry_local(current.clone()).await.unwrap();
+        assert_eq!(current.value, b"v2");
+
+        dht.store_entry_local(record(&owner, b"v3", 3)).await.unwrap();
+        assert_eq!(dht.get("escrow:e1").await, Some(b"v3".to_vec()));
+
+        let mut lapsed = record(&owner, b"v4", 4);
+        lapsed.timestamp = 0;
+        lapsed.ttl = 1;
+        lapsed.signature = owner.sign_message(&lapsed.signing_payload());
+        dht.entries.write().await.insert(lapsed).unwrap();
+        dht.store_entry_local(record(&intruder, b"mine", 1)).await.unwrap();
+        assert_eq!(dht.get_entry_local("escrow:e1").await.unwrap().publisher_did, intruder.did.id);
+    }
+
+    #[tokio::test]
+    async fn test_keys_naming_a_did_take_only_its_records() {
+        let dht = DHT::new(NodeId("local".to_string()));
+        let mut victim = DIDManager::new(vec![]);
+        let squatter = DIDManager::new(vec![]);
+        let did = victim.did.id.clone();
+        let service = service("s1", &did);
+        let records = |publisher: &DIDManager, owner: &DIDManager| {
+            let revocations = owner.sign_revocation_list(Default::default());
+            vec![
+                (did_document_key(&did), serde_json::to_vec(&owner.document()).unwrap()),
+                (revocation_list_key(&did), serde_json::to_vec(&revocations).unwrap()),
+                (format!("active_did:{}", did), serde_json::to_vec(&serde_json::json!({ "did": did })).unwrap()),
+                ("service:s1".to_string(), serde_json::to_vec(&service).unwrap()),
+            ]
+            .into_iter()
+            .map(|(key, value)| DHTEntry::signed(publisher, key, value, 3600, 1))
+            .collect::<Vec<_>>()
+        };
+
+        for squatted in records(&squatter, &victim) {
+            assert!(dht.store_entry_local(squatted).await.is_err());
+        }
+        let renamed = ServiceMetadata { id: ServiceId("s2".to_string()), ..service.clone() };
+        let misfiled = DHTEntry::signed(&victim, "service:s1".to_string(), serde_json::to_vec(&renamed).unwrap(), 3600, 1);
+        assert!(dht.store_entry_local(misfiled).await.is_err());
+        for owned in records(&victim, &victim) {
+            dht.store_entry_local(owned).await.unwrap();
+        }
+        // A service's AOI key is its provider's to publish, once the service is known.
+        let aoi = |publisher: &DIDManager, service_id: &str| {
+            DHTEntry::signed(publisher, format!("aoi:{}", service_id), b"key".to_vec(), 3600, 1)
+        };
+        assert!(dht.store_entry_local(aoi(&squatter, "s1")).await.is_err());
+        assert!(dht.store_entry_local(aoi(&victim, "s2")).await.is_err());
+        dht.store_entry_local(aoi(&victim, "s1")).await.unwrap();
+
+        // Once the DID has moved to a new key, its old one no longer speaks for it.
+        let old_key = victim.clone();
+        victim.rotate_key(true).unwrap();
+        let document = DHTEntry::signed(&victim, did_document_key(&did), serde_json::to_vec(&victim.document()).unwrap(), 3600, 1);
+        dht.store_entry_local(document).await.unwrap();
+        let mut stale = records(&old_key, &old_key).remove(2);
+        stale.seq = 2;
+        stale.signature = old_key.sign_message(&stale.signing_payload());
+        assert!(dht.store_entry_local(stale).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_index_follows_the_records() {
+        let identity = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(identity.did.id.clone())).with_identity(identity.clone());
+        let mut service = ServiceMetadata { tags: vec!["3d".to_string()], ..service("s1", &identity.did.id) };
+
+        dht.announce_service(&service).await.unwrap();
+        assert_eq!(dht.find_services("gpu 3d").await.len(), 1);
+
+        service.name = "CPU Rendering".to_string();
+        dht.announce_service(&service).await.unwrap();
+        assert!(dht.find_services("gpu").await.is_empty());
+        assert_eq!(dht.find_services("cpu").await.len(), 1);
+
+        dht.remove("service:s1").await.unwrap();
+        assert!(dht.find_services("rendering").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_records_survive_a_restart_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dht.log");
+        let identity = DIDManager::new(vec![]);
+        let open = || {
+            DHT::with_store(NodeId(identity.did.id.clone()), Box::new(DiskStore::open(&path).unwrap()))
+                .with_identity(identity.clone())
+        };
+        let service = service("s1", &identity.did.id);
+
+        let dht = open();
+        dht.announce_service(&service).await.unwrap();
+        drop(dht);
+
+        let dht = open();
+        assert_eq!(dht.find_services("gpu").await.len(), 1);
+        // Updates carry on from the stored sequence number.
+        dht.announce_service(&service).await.unwrap();
+        assert_eq!(dht.get_entry_local("service:s1").await.unwrap().seq, 2);
+    }
+
+    #[tokio::test]
+    async fn test_publisher_sequence_numbers_increase() {
+        let identity = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(identity.did.id.clone())).with_identity(identity.clone());
+
+        dht.store("escrow:e1".to_string(), b"k1".to_vec(), 60).await.unwrap();
+        dht.store("escrow:e1".to_string(), b"k2".to_vec(), 60).await.unwrap();
+        let entry = dht.get_entry_local("escrow:e1").await.unwrap();
+        assert_eq!((entry.seq, entry.value.as_slice()), (2, b"k2".as_slice()));
+        assert_eq!(entry.publisher_did, identity.did.id);
+        entry.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quotas_bound_each_publisher() {
+        let identity = DIDManager::new(vec![]);
+        let limits = QuotaLimits { max_entries: 2, max_bytes: 1024, max_value_size: 64, max_ttl: 3600 };
+        let dht = DHT::new(NodeId(identity.did.id.clone()))
+            .with_identity(identity.clone())
+            .with_quotas(QuotaPolicy::default().with_namespace("escrow:", limits));
+        let flooder = DIDManager::new(vec![]);
+
+        dht.store_entry_local(record(&flooder, b"v1", 1)).await.unwrap();
+        let second = DHTEntry::signed(&flooder, "escrow:e2".to_string(), b"v".to_vec(), 3600, 1);
+        dht.store_entry_local(second).await.unwrap();
+        let third = DHTEntry::signed(&flooder, "escrow:e3".to_string(), b"v".to_vec(), 3600, 1);
+        let error = dht.store_entry_local(third.clone()).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<QuotaError>(), Some(QuotaError::TooManyEntries { limit: 2, .. })));
+
+        // Updates in place still fit, other publishers are unaffected, and removals free room.
+        dht.store_entry_local(record(&flooder, b"v2", 2)).await.unwrap();
+        dht.store("escrow:mine".to_string(), b"v".to_vec(), 3600).await.unwrap();
+        let error = dht.store("escrow:big".to_string(), vec![0; 65], 3600).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<QuotaError>(), Some(QuotaError::ValueTooLarge { .. })));
+        dht.remove("escrow:e2").await.unwrap();
+        dht.store_entry_local(third).await.unwrap();
+
+        let stats = dht.get_stats().await;
+        assert_eq!(stats.quota_rejections, 2);
+        assert_eq!(stats.publisher_usage.len(), 2);
+        assert_eq!(stats.publisher_usage[0].publisher_did, flooder.did.id);
+        assert_eq!(stats.publisher_usage[0].entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip_and_merge_policies() {
+        let source_identity = DIDManager::new(vec![]);
+        let source = DHT::new(NodeId(source_identity.did.id.clone())).with_identity(source_identity.clone());
+        source.store("escrow:e2".to_string(), b"key".to_vec(), 3600).await.unwrap();
+        source.store_entry_local(record(&source_identity, b"newer", 2)).await.unwrap();
+        let mut snapshot = Vec::new();
+        assert_eq!(source.export_snapshot(&mut snapshot).await.unwrap(), 2);
+
+        let seed = || async {
+            let target = DHT::new(NodeId("target".to_string()));
+            let mut older = record(&source_identity, b"older", 1);
+            older.timestamp -= 10;
+            older.signature = source_identity.sign_message(&older.signing_payload());
+            target.store_entry_local(older).await.unwrap();
+            target
+        };
+
+        let target = seed().await;
+        let report = target.import_snapshot(snapshot.as_slice(), MergePolicy::Skip).await.unwrap();
+        assert_eq!(report, ImportReport { imported: 1, skipped: 1, ..ImportReport::default() });
+        assert_eq!(target.get_entry_local("escrow:e1").await.unwrap().value, b"older");
+
+        let target = seed().await;
+        let report = target.import_snapshot(snapshot.as_slice(), MergePolicy::NewerWins).await.unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(target.get_entry_local("escrow:e1").await.unwrap().value, b"newer");
+        assert_eq!(target.get("escrow:e2").await, Some(b"key".to_vec()));
+        // Importing again changes nothing.
+        let report = target.import_snapshot(snapshot.as_slice(), MergePolicy::NewerWins).await.unwrap();
+        assert_eq!(report.skipped, 2);
+
+        // Overwrite ignores age, but not ownership, sequence numbers or signatures.
+        let mut backdated = record(&source_identity, b"backdated", 3);
+        backdated.timestamp -= 100;
+        backdated.signature = source_identity.sign_message(&backdated.signing_payload());
+        let mut forged = Vec::new();
+        snapshot::write_snapshot(&mut forged, "source", &[backdated]).unwrap();
+        let report = target.import_snapshot(forged.as_slice(), MergePolicy::Overwrite).await.unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(target.get_entry_local("escrow:e1").await.unwrap().value, b"backdated");
+
+        let intruder = DIDManager::new(vec![]);
+        let mut tampered = record(&source_identity, b"mine", 4);
+        tampered.value = b"changed".to_vec();
+        let rejected = [record(&intruder, b"mine", 4), record(&source_identity, b"conflict", 3), tampered];
+        forged.clear();
+        snapshot::write_snapshot(&mut forged, "intruder", &rejected).unwrap();
+        let report = target.import_snapshot(forged.as_slice(), MergePolicy::Overwrite).await.unwrap();
+        assert_eq!(report.rejected, 3);
+        assert_eq!(target.get_entry_local("escrow:e1").await.unwrap().value, b"backdated");
+
+        // Nor the provider a service record names.
+        let service = service("s1", &source_identity.did.id);
+        let squatted = DHTEntry::signed(&intruder, "service:s1".to_string(), serde_json::to_vec(&service).unwrap(), 3600, 1);
+        forged.clear();
+        snapshot::write_snapshot(&mut forged, "intruder", &[squatted]).unwrap();
+        let report = target.import_snapshot(forged.as_slice(), MergePolicy::Overwrite).await.unwrap();
+        assert_eq!(report.rejected, 1);
+        assert!(target.find_services("gpu").await.is_empty());
+    }
+
+    struct DeadPeers;
+
+    #[async_trait::async_trait]
+    impl DhtTransport for DeadPeers {
+        async fn ping(&self, _peer_id: &str) -> bool {
+            false
+        }
+
+        async fn find_node(&self, contact: &PeerContact, _key: &str) -> Result<Vec<PeerContact>> {
+            Err(anyhow::anyhow!("{} is unreachable", contact.did))
+        }
+
+        async fn find_value(&self, contact: &PeerContact, _key: &str) -> Result<FindValueResult> {
+            Err(anyhow::anyhow!("{} is unreachable", contact.did))
+        }
+
+        async fn store(&self, contact: &PeerContact, _entry: &DHTEntry) -> Result<bool> {
+            Err(anyhow::anyhow!("{} is unreachable", contact.did))
+        }
+    }
+
+    /// Accepts every STORE and remembers who received what.
+    #[derive(Default)]
+    struct RecordingPeers {
+        stores: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DhtTransport for RecordingPeers {
+        async fn ping(&self, _peer_id: &str) -> bool {
+            true
+        }
+
+        async fn find_node(&self, _contact: &PeerContact, _key: &str) -> Result<Vec<PeerContact>> {
+            Ok(Vec::new())
+        }
+
+        async fn find_value(&self, _contact: &PeerContact, _key: &str) -> Result<FindValueResult> {
+            Ok(FindValueResult::Closer(Vec::new()))
+        }
+
+        async fn store(&self, contact: &PeerContact, entry: &DHTEntry) -> Result<bool> {
+            self.stores.lock().unwrap().push((contact.did.clone(), entry.key.clone()));
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_sweeps_republishes_and_replicates() {
+        let identity = DIDManager::new(vec![]);
+        let peers = Arc::new(RecordingPeers::default());
+        let dht = DHT::new(NodeId(identity.did.id.clone()))
+            .with_identity(identity.clone())
+            .with_transport(peers.clone());
+
+        let active_did = format!("active_did:{}", identity.did.id);
+        dht.store(active_did.clone(), b"online".to_vec(), 60).await.unwrap();
+        dht.store("escrow:e1".to_string(), b"contract".to_vec(), 60).await.unwrap();
+        let mut expired = record(&DIDManager::new(vec![]), b"old", 1);
+        expired.key = "aoi:gone".to_string();
+        expired.timestamp = 0;
+        dht.entries.write().await.insert(expired).unwrap();
+
+        // A peer joins after the records were stored.
+        dht.add_contact(PeerContact { did: "peer-1".to_string(), endpoint: "tcp://peer-1".to_string() })
+            .await
+            .unwrap();
+        let report = dht.run_maintenance(120).await.unwrap();
+        // Only the DID announcement is ours to keep alive; the escrow is left to lapse.
+        assert_eq!(report, MaintenanceReport { expired: 1, republished: 1, replicated: 1 });
+        assert_eq!(dht.get_entry_local(&active_did).await.unwrap().seq, 2);
+        assert_eq!(dht.get_entry_local("escrow:e1").await.unwrap().seq, 1);
+
+        let mut stores = peers.stores.lock().unwrap().clone();
+        stores.sort();
+        assert_eq!(
+            stores,
+            vec![
+                ("peer-1".to_string(), active_did.clone()),
+                ("peer-1".to_string(), "escrow:e1".to_string()),
+            ]
+        );
+
+        // The peer now holds everything, and nothing is close to expiry.
+        let report = dht.run_maintenance(30).await.unwrap();
+        assert_eq!(report, MaintenanceReport::default());
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_peers_make_room() {
+        let probing = DHT::new(NodeId("local".to_string())).with_transport(Arc::new(DeadPeers));
+        let trusting = DHT::new(NodeId("local".to_string()));
+        // 200 peers overflow the farthest buckets, which cover most of the keyspace.
+        for i in 0..200 {
+            probing.add_peer(format!("peer-{}", i)).await.unwrap();
+            trusting.add_peer(format!("peer-{}", i)).await.unwrap();
+        }
+
+        let held = |table: &RoutingTable| (0..200).map(|i| format!("peer-{}", i)).filter(|peer| table.contains(peer)).collect::<Vec<_>>();
+        let evicting = held(&*probing.routing_table.read().await);
+        let keeping = held(&*trusting.routing_table.read().await);
+        assert_eq!(evicting.len(), keeping.len());
+        // Dead peers gave way to the newest arrival; without a transport the old ones stay.
+        assert!(evicting.contains(&"peer-199".to_string()));
+        assert!(keeping.contains(&"peer-0".to_string()));
+        assert_ne!(evicting, keeping);
+    }
+}
@@ -0,0 +1,126 @@
+//! Storage backends for DHT records.
+
+use crate::core::data_structures::get_current_timestamp;
+use crate::core::dht::DHTEntry;
+use crate::core::storage::Collection;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::info;
+
+/// Where a DHT keeps its records, keyed by `DHTEntry::key`. Ownership and
+/// expiry rules are enforced by the DHT, not the store.
+pub trait DhtStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<&DHTEntry>;
+    fn insert(&mut self, entry: DHTEntry) -> Result<()>;
+    fn remove(&mut self, key: &str) -> Result<Option<DHTEntry>>;
+    fn entries(&self) -> Box<dyn Iterator<Item = &DHTEntry> + '_>;
+    fn len(&self) -> usize;
+}
+
+/// Keeps records in memory only; they are gone when the node stops.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: HashMap<String, DHTEntry>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DhtStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<&DHTEntry> {
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, entry: DHTEntry) -> Result<()> {
+        self.entries.insert(entry.key.clone(), entry);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<Option<DHTEntry>> {
+        Ok(self.entries.remove(key))
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &DHTEntry> + '_> {
+        Box::new(self.entries.values())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Keeps records in a `Collection` on disk so they survive restarts. Records
+/// that expired while the node was down are dropped on open.
+pub struct DiskStore {
+    records: Collection<DHTEntry>,
+}
+
+impl DiskStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut records: Collection<DHTEntry> = Collection::open(path.as_ref())?;
+        let now = get_current_timestamp();
+        records.retain(|_, entry| !entry.is_expired(now))?;
+        info!("Loaded {} DHT entries from {}", records.len(), path.as_ref().display());
+        Ok(DiskStore { records })
+    }
+}
+
+impl DhtStore for DiskStore {
+    fn get(&self, key: &str) -> Option<&DHTEntry> {
+        self.records.get(key)
+    }
+
+    fn insert(&mut self, entry: DHTEntry) -> Result<()> {
+        self.records.insert(entry.key.clone(), entry)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<Option<DHTEntry>> {
+        self.records.remove(key)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = &DHTEntry> + '_> {
+        Box::new(self.records.values())
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::identity::DIDManager;
+
+    fn entry(publisher: &DIDManager, key: &str, ttl: u64) -> DHTEntry {
+        DHTEntry::signed(publisher, key.to_string(), b"value".to_vec(), ttl, 1)
+    }
+
+    #[test]
+    fn test_disk_store_survives_reopen_and_drops_expired_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dht").join("entries.log");
+        let publisher = DIDManager::new(vec![]);
+
+        {
+            let mut store = DiskStore::open(&path).unwrap();
+            store.insert(entry(&publisher, "service:kept", 3600)).unwrap();
+            store.insert(entry(&publisher, "service:removed", 3600)).unwrap();
+            store.remove("service:removed").unwrap();
+            let mut lapsed = entry(&publisher, "service:lapsed", 1);
+            lapsed.timestamp = 0;
+            store.insert(lapsed).unwrap();
+        }
+
+        let store = DiskStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        let kept = store.get("service:kept").unwrap();
+        kept.verify().unwrap();
+        assert!(store.get("service:removed").is_none());
+        assert!(store.get("service:lapsed").is_none());
+    }
+}
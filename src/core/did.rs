@@ -0,0 +1,531 @@
+//! DID documents and their resolution. A node publishes its document to the
+//! DHT under `did_doc:<did>`, signed with the key the document lists, so any
+//! peer can look up the key behind a DID before checking its signatures.
+//!
+//! A DID outlives its keys. Each `DidUpdate` is signed by the DID's current key
+//! and can add a new one, revoke old ones and replace the endpoints; documents
+//! carry the whole chain, which resolvers replay from the key the DID was
+//! derived from.
+
+use crate::core::data_structures::*;
+use crate::core::credentials::VerifiableCredential;
+use crate::core::dht::{DHTEntry, DHT};
+use crate::core::identity::DIDManager;
+use crate::core::signing::{EscrowApproval, Signable};
+use crate::network::PeerVerifier;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+/// How long a resolved document is trusted before it is looked up again.
+const DEFAULT_CACHE_TTL: u64 = 300;
+const UPDATE_DOMAIN: &[u8] = b"duxnet-did-update-v1";
+
+pub fn did_document_key(did: &str) -> String {
+    format!("did_doc:{}", did)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationKey {
+    /// `<did>#key-<n>`.
+    pub id: String,
+    /// Ed25519 public key.
+    pub public_key: Vec<u8>,
+    #[serde(default)]
+    pub added_at: u64,
+    /// From this time on, signatures by this key are rejected, whenever they
+    /// claim to have been made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<u64>,
+}
+
+impl VerificationKey {
+    pub fn is_valid_at(&self, time: u64) -> bool {
+        self.revoked_at.is_none_or(|revoked_at| time < revoked_at)
+    }
+}
+
+/// A change to a DID document, signed by the key that was current before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidUpdate {
+    pub did: String,
+    /// 1 for the DID's first update, and one more for each after it.
+    pub sequence: u64,
+    /// Hex SHA-256 of the previous update's signature; empty for the first.
+    pub previous: String,
+    /// Becomes the DID's current key.
+    pub new_key: Option<Vec<u8>>,
+    pub revoked_keys: Vec<Vec<u8>>,
+    pub endpoints: Option<Vec<String>>,
+    pub timestamp: u64,
+    pub signer_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl DidUpdate {
+    /// Digest of every field except the signature, length-prefixed like DHT records.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(UPDATE_DOMAIN);
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        };
+        field(self.did.as_bytes());
+        field(&self.sequence.to_be_bytes());
+        field(self.previous.as_bytes());
+        field(self.new_key.as_deref().unwrap_or_default());
+        field(&(self.revoked_keys.len() as u64).to_be_bytes());
+        for key in &self.revoked_keys {
+            field(key);
+        }
+        match &self.endpoints {
+            Some(endpoints) => {
+                field(&(endpoints.len() as u64).to_be_bytes());
+                for endpoint in endpoints {
+                    field(endpoint.as_bytes());
+                }
+            }
+            None => field(&[]),
+        }
+        field(&self.timestamp.to_be_bytes());
+        field(&self.signer_key);
+        hasher.finalize().to_vec()
+    }
+
+    /// What the next update links back to.
+    pub fn digest(&self) -> String {
+        hex::encode(Sha256::digest(&self.signature))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidDocument {
+    pub id: String,
+    /// Keys the DID has signed with, oldest first. The first is the one the DID
+    /// was derived from, the last is the current one.
+    pub public_keys: Vec<VerificationKey>,
+    pub endpoints: Vec<String>,
+    pub created: u64,
+    pub updated: u64,
+    #[serde(default)]
+    pub updates: Vec<DidUpdate>,
+}
+
+impl DidDocument {
+    /// The document of a DID that has never been updated.
+    pub fn inception(did: &str, public_key: Vec<u8>, endpoints: Vec<String>, created: u64) -> Self {
+        DidDocument {
+            id: did.to_string(),
+            public_keys: vec![VerificationKey {
+                id: format!("{}#key-1", did),
+                public_key,
+                added_at: created,
+                revoked_at: None,
+            }],
+            endpoints,
+            created,
+            updated: created,
+            updates: Vec::new(),
+        }
+    }
+
+    /// The document a peer presents as its DID and update chain, e.g. in the
+    /// handshake. `did.public_key` must be the key the chain ends at.
+    pub fn presented(did: &DID, updates: &[DidUpdate]) -> Result<Self> {
+        let (inception_key, created) = match updates.first() {
+            Some(first) => (first.signer_key.clone(), did.created_at.min(first.timestamp)),
+            None => (did.public_key.clone(), did.created_at),
+        };
+        let mut document = Self::inception(&did.id, inception_key, did.endpoints.clone(), created);
+        for update in updates {
+            document.apply(update)?;
+        }
+        document.validate()?;
+        if document.current_key().public_key != did.public_key {
+            return Err(anyhow::anyhow!("DID {} does not match its public key", did.id));
+        }
+        Ok(document)
+    }
+
+    pub fn for_identity(identity: &DIDManager) -> Self {
+        let did = &identity.did;
+        let inception_key = match identity.updates.first() {
+            Some(first) => first.signer_key.clone(),
+            None => identity.get_public_key(),
+        };
+        let mut document = Self::inception(&did.id, inception_key, Vec::new(), did.created_at);
+        for update in &identity.updates {
+            document.apply(update).expect("an identity's own updates apply");
+        }
+        document.endpoints = did.endpoints.clone();
+        document.updated = get_current_timestamp();
+        document
+    }
+
+    /// The key the next update must be signed with.
+    pub fn current_key(&self) -> &VerificationKey {
+        self.public_keys.last().expect("documents list at least one key")
+    }
+
+    /// Checks `update` is the next link in this document's chain and applies it.
+    pub fn apply(&mut self, update: &DidUpdate) -> Result<()> {
+        let expected_previous = self.updates.last().map(DidUpdate::digest).unwrap_or_default();
+        if update.did != self.id || update.sequence != self.updates.len() as u64 + 1 || update.previous != expected_previous {
+            return Err(anyhow::anyhow!("Update {} does not continue the chain of {}", update.sequence, self.id));
+        }
+        if update.signer_key != self.current_key().public_key {
+            return Err(anyhow::anyhow!("Update {} of {} is not signed by its current key", update.sequence, self.id));
+        }
+        if !DIDManager::verify_with_public_key(&update.signer_key, &update.signature, &update.signing_payload()) {
+            return Err(anyhow::anyhow!("Update {} of {} has an invalid signature", update.sequence, self.id));
+        }
+        if self.updates.last().is_some_and(|last| update.timestamp < last.timestamp) {
+            return Err(anyhow::anyhow!("Update {} of {} predates the one before it", update.sequence, self.id));
+        }
+        if update.new_key.is_none() && update.revoked_keys.contains(&update.signer_key) {
+            return Err(anyhow::anyhow!("Update {} of {} revokes its current key without a replacement", update.sequence, self.id));
+        }
+        if update.new_key.as_ref().is_some_and(|new_key| self.key(new_key).is_some()) {
+            return Err(anyhow::anyhow!("Update {} of {} adds a key it already had", update.sequence, self.id));
+        }
+        if let Some(revoked) = update.revoked_keys.iter().find(|revoked| self.key(revoked).is_none()) {
+            return Err(anyhow::anyhow!(
+                "Update {} of {} revokes {}, a key it never had",
+                update.sequence,
+                self.id,
+                hex::encode(revoked)
+            ));
+        }
+
+        for key in self.public_keys.iter_mut().filter(|key| update.revoked_keys.contains(&key.public_key)) {
+            key.revoked_at.get_or_insert(update.timestamp);
+        }
+        if let Some(new_key) = &update.new_key {
+            self.public_keys.push(VerificationKey {
+                id: format!("{}#key-{}", self.id, self.public_keys.len() + 1),
+                public_key: new_key.clone(),
+                added_at: update.timestamp,
+                revoked_at: None,
+            });
+        }
+        if let Some(endpoints) = &update.endpoints {
+            self.endpoints = endpoints.clone();
+        }
+        self.updated = update.timestamp;
+        self.updates.push(update.clone());
+        Ok(())
+    }
+
+    /// Checks the document describes the DID it claims to: its update chain,
+    /// replayed from the key the DID was derived from, yields the keys it lists.
+    pub fn validate(&self) -> Result<()> {
+        let inception = self
+            .public_keys
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("DID document for {} lists no keys", self.id))?;
+        if DIDManager::did_from_public_key(&inception.public_key) != self.id {
+            return Err(anyhow::anyhow!("DID document for {} lists another DID's key", self.id));
+        }
+        if self.updated < self.created {
+            return Err(anyhow::anyhow!("DID document for {} was updated before it was created", self.id));
+        }
+        let mut replayed = Self::inception(&self.id, inception.public_key.clone(), Vec::new(), self.created);
+        for update in &self.updates {
+            replayed.apply(update)?;
+        }
+        if replayed.public_keys != self.public_keys {
+            return Err(anyhow::anyhow!("DID document for {} lists keys its update chain does not", self.id));
+        }
+        Ok(())
+    }
+
+    /// Parses the document in a `did_doc:` record. The record must be signed by
+    /// the document's current key.
+    pub fn from_record(entry: &DHTEntry) -> Result<Self> {
+        let document: DidDocument = serde_json::from_slice(&entry.value)
+            .map_err(|e| anyhow::anyhow!("DHT record {} is not a DID document: {}", entry.key, e))?;
+        if entry.key != did_document_key(&document.id) {
+            return Err(anyhow::anyhow!("DHT record {} holds the DID document of {}", entry.key, document.id));
+        }
+        document.validate()?;
+        if entry.publisher_key != document.current_key().public_key {
+            return Err(anyhow::anyhow!("DID document for {} was published by {}", document.id, entry.publisher_did));
+        }
+        Ok(document)
+    }
+
+    /// Whether the document in `entry` continues the update chain of the one
+    /// in `existing`. Such a record may replace `existing` though it is
+    /// published under a different key.
+    pub fn supersedes(entry: &DHTEntry, existing: &DHTEntry) -> bool {
+        match (Self::from_record(entry), Self::from_record(existing)) {
+            (Ok(new), Ok(old)) => {
+                new.id == old.id
+                    && new.updates.len() > old.updates.len()
+                    && new.updates[..old.updates.len()] == old.updates[..]
+            }
+            _ => false,
+        }
+    }
+
+    pub fn key(&self, public_key: &[u8]) -> Option<&VerificationKey> {
+        self.public_keys.iter().find(|key| key.public_key == public_key)
+    }
+
+    /// Whether a key of the document made `signature` over `value` at
+    /// `signed_at`, and had not been revoked by `received_at`. Keys added by an
+    /// update only sign from then on; the key the DID was derived from has
+    /// signed for it all along.
+    pub fn verify<T: Signable>(&self, value: &T, signature: &[u8], signed_at: u64, received_at: u64) -> bool {
+        self.public_keys.iter().enumerate().any(|(index, key)| {
+            (index == 0 || key.added_at <= signed_at)
+                && key.is_valid_at(signed_at.max(received_at))
+                && DIDManager::verify_signed(&key.public_key, value, signature)
+        })
+    }
+}
+
+struct CachedDocument {
+    document: DidDocument,
+    fetched_at: u64,
+}
+
+/// Looks DID documents up in the DHT and keeps them for `cache_ttl` seconds.
+#[derive(Clone)]
+pub struct DidResolver {
+    dht: DHT,
+    cache: Arc<RwLock<HashMap<String, CachedDocument>>>,
+    cache_ttl: u64,
+}
+
+impl DidResolver {
+    pub fn new(dht: DHT) -> Self {
+        DidResolver { dht, cache: Arc::new(RwLock::new(HashMap::new())), cache_ttl: DEFAULT_CACHE_TTL }
+    }
+
+    pub async fn resolve(&self, did: &str) -> Result<DidDocument> {
+        let now = get_current_timestamp();
+        if let Some(cached) = self.cache.read().await.get(did) {
+            if now < cached.fetched_at + self.cache_ttl {
+                return Ok(cached.document.clone());
+            }
+        }
+
+        let entry = self
+            .dht
+            .get_entry(&did_document_key(did))
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No DID document found for {}", did))?;
+        let document = DidDocument::from_record(&entry)?;
+        debug!("Resolved DID document for {}", did);
+        self.cache
+            .write()
+            .await
+            .insert(did.to_string(), CachedDocument { document: document.clone(), fetched_at: now });
+        Ok(document)
+    }
+
+    /// Checks that `signer_did` made `signature` over `value` with a key it
+    /// has not revoked.
+    pub async fn verify<T: Signable>(&self, signer_did: &str, value: &T, signature: &[u8]) -> Result<()> {
+        self.verify_at(signer_did, value, signature, get_current_timestamp()).await
+    }
+
+    /// Like `verify`, for a signature that claims to be made at `signed_at`:
+    /// the key must have been added by then. The signer picks `signed_at`, so
+    /// it cannot revive a revoked key; signatures by keys revoked before now
+    /// are rejected however they are dated.
+    pub async fn verify_at<T: Signable>(&self, signer_did: &str, value: &T, signature: &[u8], signed_at: u64) -> Result<()> {
+        let document = self.resolve(signer_did).await?;
+        if !document.verify(value, signature, signed_at, get_current_timestamp()) {
+            return Err(anyhow::anyhow!("Signature does not match any valid key of {}", signer_did));
+        }
+        Ok(())
+    }
+
+    pub async fn verify_attestation(&self, attestation: &ReputationAttestation) -> Result<()> {
+        self.verify_at(&attestation.attester_did, attestation, &attestation.signature, attestation.timestamp).await
+    }
+
+    /// Checks a credential was signed by its issuer, has not expired and is not
+    /// on the issuer's revocation list.
+    pub async fn verify_credential(&self, credential: &VerifiableCredential) -> Result<()> {
+        if credential.is_expired(get_current_timestamp()) {
+            return Err(anyhow::anyhow!("Credential {} has expired", credential.id));
+        }
+        self.verify_at(&credential.issuer, credential, &credential.proof, credential.issued_at)
+            .await
+            .map_err(|e| anyhow::anyhow!("Credential {} is not signed by its issuer: {}", credential.id, e))?;
+        if self.is_revoked(&credential.issuer, &credential.id).await? {
+            return Err(anyhow::anyhow!("Credential {} was revoked by {}", credential.id, credential.issuer));
+        }
+        Ok(())
+    }
+
+    /// Whether `issuer`'s published revocation list names `credential_id`.
+    /// A list that is unreadable or not signed by the issuer is an error, not
+    /// a sign that nothing was revoked.
+    pub async fn is_revoked(&self, issuer: &str, credential_id: &str) -> Result<bool> {
+        let Some(list) = self.dht.get_revocation_list(issuer).await? else {
+            return Ok(false);
+        };
+        if list.issuer != issuer {
+            return Err(anyhow::anyhow!("The revocation list published for {} is {}'s", issuer, list.issuer));
+        }
+        self.verify(issuer, &list, &list.signature)
+            .await
+            .map_err(|e| anyhow::anyhow!("The revocation list published for {} is not signed by it: {}", issuer, e))?;
+        Ok(list.revoked.contains(credential_id))
+    }
+
+    pub async fn verify_escrow_signature(
+        &self,
+        escrow_id: &str,
+        state: &EscrowState,
+        signer_did: &str,
+        signature: &[u8],
+    ) -> Result<()> {
+        self.verify(signer_did, &EscrowApproval { escrow_id, state }, signature).await
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerVerifier for DidResolver {
+    /// Turns away peers presenting a chain the DHT already holds a later link
+    /// of: they hold a key the DID has since moved off. Only records held
+    /// locally are consulted, so connecting peers cannot make us search.
+    async fn verify_peer(&self, presented: &DidDocument) -> Result<()> {
+        let Some(entry) = self.dht.get_entry_local(&did_document_key(&presented.id)).await else {
+            return Ok(());
+        };
+        let known = DidDocument::from_record(&entry)?;
+        if presented.updates.len() < known.updates.len() || presented.updates[..known.updates.len()] != known.updates[..] {
+            return Err(anyhow::anyhow!("{} presented an outdated DID document", presented.id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dht_for(identity: &DIDManager) -> DHT {
+        DHT::new(NodeId(identity.did.id.clone())).with_identity(identity.clone())
+    }
+
+    #[tokio::test]
+    async fn test_signatures_verify_against_the_resolved_key() {
+        let attester = DIDManager::new(vec!["tcp://127.0.0.1:9000".to_string()]);
+        let dht = dht_for(&attester);
+        dht.publish_did_document(&DidDocument::for_identity(&attester)).await.unwrap();
+        let resolver = DidResolver::new(dht.clone());
+
+        let document = resolver.resolve(&attester.did.id).await.unwrap();
+        assert_eq!(document.endpoints, attester.did.endpoints);
+
+        let mut attestation = attester.create_attestation("did:duxnet:target".to_string(), 0.9, "task".to_string());
+        resolver.verify_attestation(&attestation).await.unwrap();
+        attestation.score = 1.0;
+        assert!(resolver.verify_attestation(&attestation).await.is_err());
+
+        let signature = attester.sign(&EscrowApproval { escrow_id: "escrow-1", state: &EscrowState::Funded });
+        resolver.verify_escrow_signature("escrow-1", &EscrowState::Funded, &attester.did.id, &signature).await.unwrap();
+        assert!(resolver.verify_escrow_signature("escrow-1", &EscrowState::Refunded, &attester.did.id, &signature).await.is_err());
+
+        let stranger = DIDManager::new(vec![]);
+        let forged = stranger.create_attestation("did:duxnet:target".to_string(), 0.9, "task".to_string());
+        assert!(resolver.verify_attestation(&forged).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_documents_published_by_someone_else_are_rejected() {
+        let victim = DIDManager::new(vec![]);
+        let forger = DIDManager::new(vec![]);
+        let dht = dht_for(&forger);
+        let mut document = DidDocument::for_identity(&victim);
+        document.public_keys.push(VerificationKey {
+            id: format!("{}#key-2", victim.did.id),
+            public_key: forger.get_public_key(),
+            added_at: document.created,
+            revoked_at: None,
+        });
+        let entry = DHTEntry::signed(&forger, did_document_key(&victim.did.id), serde_json::to_vec(&document).unwrap(), 3600, 1);
+        assert!(DidDocument::from_record(&entry).is_err());
+        assert!(dht.store_entry_local(entry.clone()).await.is_err());
+
+        // Even a copy that slipped into the store does not resolve.
+        dht.entries.write().await.insert(entry).unwrap();
+        assert!(DidResolver::new(dht).resolve(&victim.did.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rotated_keys_follow_the_update_chain() {
+        let mut identity = DIDManager::new(vec![]);
+        let did = identity.did.id.clone();
+        let old_key = identity.clone();
+        let dht = dht_for(&identity);
+        dht.publish_did_document(&identity.document()).await.unwrap();
+        let mut before = old_key.create_attestation("did:duxnet:target".to_string(), 0.5, "task".to_string());
+        before.timestamp -= 10;
+        before.signature = old_key.sign(&before);
+        DidResolver::new(dht.clone()).verify_attestation(&before).await.unwrap();
+
+        identity.rotate_key(true).unwrap();
+        identity.update(None, vec![], Some(vec!["tcp://10.0.0.2:9000".to_string()])).unwrap();
+        assert_eq!(identity.did.id, did);
+        let rotated = dht.clone().with_identity(identity.clone());
+        rotated.publish_did_document(&identity.document()).await.unwrap();
+
+        let resolver = DidResolver::new(dht.clone());
+        let document = resolver.resolve(&did).await.unwrap();
+        assert_eq!(document.public_keys.len(), 2);
+        assert!(document.public_keys[0].revoked_at.is_some());
+        assert_eq!(document.endpoints, vec!["tcp://10.0.0.2:9000".to_string()]);
+
+        // Once revoked, the old key is turned away however its signatures
+        // are dated: the signer picks the date.
+        assert!(resolver.verify_attestation(&before).await.is_err());
+        let after = old_key.create_attestation("did:duxnet:target".to_string(), 0.5, "task".to_string());
+        assert!(resolver.verify_attestation(&after).await.is_err());
+        let mut current = identity.create_attestation("did:duxnet:target".to_string(), 0.5, "task".to_string());
+        resolver.verify_attestation(&current).await.unwrap();
+        // Nor does the new key sign for the time before it was added.
+        current.timestamp = document.public_keys[1].added_at - 10;
+        current.signature = identity.sign(&current);
+        assert!(resolver.verify_attestation(&current).await.is_err());
+
+        // Whoever holds the revoked key cannot roll the document back.
+        assert!(dht.publish_did_document(&old_key.document()).await.is_err());
+
+        // Peers still presenting the chain before the rotation are turned away.
+        resolver.verify_peer(&identity.document()).await.unwrap();
+        assert!(resolver.verify_peer(&old_key.document()).await.is_err());
+
+        let restored = DIDManager::import_rotated_key(identity.export_private_key(), identity.updates.clone(), vec![]).unwrap();
+        assert_eq!(restored.did.id, did);
+        assert!(DIDManager::import_rotated_key(old_key.export_private_key(), identity.updates.clone(), vec![]).is_err());
+
+        let mut tampered = identity.document();
+        tampered.updates[1].endpoints = Some(vec!["tcp://evil:1".to_string()]);
+        assert!(tampered.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolved_documents_are_cached() {
+        let identity = DIDManager::new(vec![]);
+        let dht = dht_for(&identity);
+        dht.publish_did_document(&DidDocument::for_identity(&identity)).await.unwrap();
+        let resolver = DidResolver::new(dht.clone());
+        resolver.resolve(&identity.did.id).await.unwrap();
+
+        dht.remove(&did_document_key(&identity.did.id)).await.unwrap();
+        resolver.resolve(&identity.did.id).await.unwrap();
+        assert!(DidResolver::new(dht).resolve(&identity.did.id).await.is_err());
+    }
+}
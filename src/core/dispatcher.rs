@@ -0,0 +1,419 @@
+use crate::core::data_structures::*;
+use crate::core::dht::DHT;
+use crate::core::did::DidResolver;
+use crate::core::escrow::EscrowManager;
+use crate::core::identities::IdentityRegistry;
+use crate::core::messaging::MessagingSystem;
+use crate::core::reputation::ReputationSystem;
+use crate::core::tasks::TaskEngine;
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Routes inbound network messages to the subsystem that owns them and builds
+/// the reply, if the message expects one.
+#[derive(Clone)]
+pub struct MessageDispatcher {
+    local_did: String,
+    dht: DHT,
+    task_engine: TaskEngine,
+    escrow_manager: EscrowManager,
+    messaging_system: Arc<MessagingSystem>,
+    reputation_system: ReputationSystem,
+    /// Resolves senders' keys to check message signatures against.
+    resolver: DidResolver,
+    /// When set, direct messages to any active local identity are delivered
+    /// to that identity's inbox.
+    identities: Option<IdentityRegistry>,
+}
+
+impl MessageDispatcher {
+    pub fn new(
+        local_did: String,
+        dht: DHT,
+        task_engine: TaskEngine,
+        escrow_manager: EscrowManager,
+        messaging_system: Arc<MessagingSystem>,
+        reputation_system: ReputationSystem,
+        resolver: DidResolver,
+    ) -> Self {
+        MessageDispatcher {
+            local_did,
+            dht,
+            task_engine,
+            escrow_manager,
+            messaging_system,
+            reputation_system,
+            resolver,
+            identities: None,
+        }
+    }
+
+    pub fn with_identities(mut self, identities: IdentityRegistry) -> Self {
+        self.identities = Some(identities);
+        self
+    }
+
+    /// Handles one message from `peer_id` and returns the reply to send back.
+    pub async fn dispatch(&self, peer_id: &str, message: NetworkMessage) -> Result<Option<NetworkMessage>> {
+        match message {
+            // Service discovery
+            NetworkMessage::ServiceAnnouncement(record) => {
+                self.dht.cache_service(record).await?;
+                Ok(None)
+            }
+            NetworkMessage::ServiceQuery(query) => {
+                let services = self.dht.find_services(&query).await;
+                Ok(Some(NetworkMessage::ServiceResponse(services)))
+            }
+            NetworkMessage::ServiceResponse(services) => {
+                // Listings carry no publisher signature, so they are not cached;
+                // signed records arrive through announcements and DHT lookups.
+                debug!("Peer {} listed {} services", peer_id, services.len());
+                Ok(None)
+            }
+
+            // Task management
+            NetworkMessage::TaskSubmission(task) => {
+                if task.submitter_did != peer_id {
+                    return Err(anyhow::anyhow!("{} cannot submit task {} for {}", peer_id, task.id.0, task.submitter_did));
+                }
+                self.task_engine.submit_task(task).await?;
+                Ok(None)
+            }
+            NetworkMessage::TaskAcceptance(task_id, processor_did) => {
+                if processor_did != peer_id {
+                    return Err(anyhow::anyhow!("{} cannot accept task {} for {}", peer_id, task_id.0, processor_did));
+                }
+                if self.task_engine.accept_task(&task_id, processor_did).await.is_none() {
+                    debug!("Ignoring acceptance of unknown task {} from {}", task_id.0, peer_id);
+                }
+                Ok(None)
+            }
+            NetworkMessage::TaskCompletion(result) => {
+                if result.processor_did != peer_id {
+                    return Err(anyhow::anyhow!("{} cannot report the result of task {}", peer_id, result.task_id.0));
+                }
+                self.task_engine.complete_task(result).await?;
+                Ok(None)
+            }
+
+            // Escrow management. Only the parties to a contract may move it on.
+            NetworkMessage::EscrowCreation(contract) => {
+                if !contract.is_party(peer_id) {
+                    return Err(anyhow::anyhow!("{} is not a party to escrow {}", peer_id, contract.id));
+                }
+                self.escrow_manager.import_contract(contract).await?;
+                Ok(None)
+            }
+            NetworkMessage::EscrowSignature(escrow_id, signer_did, signature) => {
+                self.contract_for_party(&escrow_id, peer_id).await?;
+                self.escrow_manager.add_signature(&escrow_id, &signer_did, signature).await?;
+                Ok(None)
+            }
+            NetworkMessage::EscrowStateUpdate(escrow_id, state) => {
+                let contract = self.contract_for_party(&escrow_id, peer_id).await?;
+                if contract.state == EscrowState::Disputed && !contract.arbiters.iter().any(|arbiter| arbiter == peer_id) {
+                    return Err(anyhow::anyhow!("Only an arbiter can settle the dispute over escrow {}", escrow_id));
+                }
+                self.escrow_manager.update_state(&escrow_id, state).await?;
+                Ok(None)
+            }
+
+            // Reputation
+            NetworkMessage::ReputationAttestation(attestation) => {
+                self.reputation_system.add_attestation(attestation).await?;
+                Ok(None)
+            }
+            NetworkMessage::ReputationQuery(target_did) => {
+                let score = self.reputation_system.get_reputation(&target_did).await;
+                Ok(Some(NetworkMessage::ReputationResponse(target_did, score)))
+            }
+            NetworkMessage::ReputationResponse(target_did, score) => {
+                debug!("Peer {} reports reputation {} for {}", peer_id, score, target_did);
+                Ok(None)
+            }
+
+            // Messaging
+            NetworkMessage::DirectMessage(message) => {
+                let inbox = match &self.identities {
+                    _ if message.to_did == self.local_did => Some(self.messaging_system.clone()),
+                    Some(identities) => identities.select(Some(&message.to_did)).await.ok().map(|identity| identity.messaging),
+                    None => None,
+                };
+                let Some(inbox) = inbox else {
+                    debug!("Ignoring message {} addressed to {}", message.id, message.to_did);
+                    return Ok(None);
+                };
+                self.resolver
+                    .verify(&message.from_did, &message, &message.signature)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Message {} is not signed by {}: {}", message.id, message.from_did, e))?;
+                let message_id = message.id.clone();
+                inbox.receive_message(message).await?;
+                Ok(Some(NetworkMessage::MessageAck(message_id)))
+            }
+            NetworkMessage::MessageAck(message_id) | NetworkMessage::MessageDelivery(message_id) => {
+                debug!("Peer {} acknowledged message {}", peer_id, message_id);
+                Ok(None)
+            }
+
+            NetworkMessage::Ping => Ok(Some(NetworkMessage::Pong)),
+            NetworkMessage::Pong => Ok(None),
+
+            // DHT RPCs
+            NetworkMessage::DhtFindNode(key) => {
+                Ok(Some(NetworkMessage::DhtNodes(self.closest_contacts(&key, peer_id).await)))
+            }
+            NetworkMessage::DhtFindValue(key) => match self.dht.get_entry_local(&key).await {
+                Some(entry) => Ok(Some(NetworkMessage::DhtValue(entry))),
+                None => Ok(Some(NetworkMessage::DhtNodes(self.closest_contacts(&key, peer_id).await))),
+            },
+            NetworkMessage::DhtStore(entry) => {
+                let accepted = match self.dht.store_entry_local(entry).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        debug!("Rejected STORE from {}: {}", peer_id, e);
+                        false
+                    }
+                };
+                Ok(Some(NetworkMessage::DhtStoreAck(accepted)))
+            }
+            NetworkMessage::DhtNodes(_) | NetworkMessage::DhtValue(_) | NetworkMessage::DhtStoreAck(_) => {
+                debug!("Ignoring unsolicited DHT reply from {}", peer_id);
+                Ok(None)
+            }
+
+            // `peer_id` only relayed the envelope, so it is neither judged by the
+            // payload nor answered.
+            NetworkMessage::Gossip(envelope) => {
+                self.dispatch_gossip(envelope.payload).await?;
+                Ok(None)
+            }
+
+            // Request/response wrappers are unwrapped by the network layer
+            // before they get here.
+            NetworkMessage::Request(..) | NetworkMessage::Response(..) => {
+                debug!("Ignoring unexpected network-layer message from {}", peer_id);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Handles a gossiped payload, which carries its author's signature.
+    async fn dispatch_gossip(&self, payload: GossipPayload) -> Result<()> {
+        match payload {
+            GossipPayload::ServiceAnnouncement(record) => self.dht.cache_service(record).await,
+            GossipPayload::ReputationAttestation(attestation) => self.reputation_system.add_attestation(attestation).await,
+        }
+    }
+
+    /// The contract `escrow_id`, provided `peer_id` is one of its parties.
+    async fn contract_for_party(&self, escrow_id: &str, peer_id: &str) -> Result<EscrowContract> {
+        let contract = self
+            .escrow_manager
+            .get_contract(escrow_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Escrow contract not found: {}", escrow_id))?;
+        if !contract.is_party(peer_id) {
+            return Err(anyhow::anyhow!("{} is not a party to escrow {}", peer_id, escrow_id));
+        }
+        Ok(contract)
+    }
+
+    /// Contacts to hand out in FIND_NODE/FIND_VALUE replies; the requester already knows itself.
+    async fn closest_contacts(&self, key: &str, requester: &str) -> Vec<PeerContact> {
+        let mut contacts = self.dht.closest_contacts(key, self.dht.k_bucket_size + 1).await;
+        contacts.retain(|contact| contact.did != requester);
+        contacts.truncate(self.dht.k_bucket_size);
+        contacts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::dht::tests::service;
+    use crate::core::dht::DHTEntry;
+    use crate::core::did::DidResolver;
+    use crate::core::identity::DIDManager;
+    use crate::core::signing::EscrowApproval;
+
+    fn dispatcher() -> (MessageDispatcher, DIDManager) {
+        let identity = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(identity.did.id.clone()));
+        let dispatcher = MessageDispatcher::new(
+            identity.did.id.clone(),
+            dht.clone(),
+            TaskEngine::new(),
+            EscrowManager::new(DidResolver::new(dht.clone())),
+            Arc::new(MessagingSystem::new(identity.clone())),
+            ReputationSystem::new(DidResolver::new(dht.clone())),
+            DidResolver::new(dht),
+        );
+        (dispatcher, identity)
+    }
+
+    #[tokio::test]
+    async fn test_queries_get_replies() {
+        let (dispatcher, _) = dispatcher();
+        let provider = DIDManager::new(vec![]);
+
+        let reply = dispatcher.dispatch("peer", NetworkMessage::Ping).await.unwrap();
+        assert!(matches!(reply, Some(NetworkMessage::Pong)));
+
+        let service = service("svc-1", &provider.did.id);
+        let value = serde_json::to_vec(&service).unwrap();
+        let record = DHTEntry::signed(&provider, "service:svc-1".to_string(), value.clone(), 3600, 1);
+        assert!(dispatcher.dispatch("peer", NetworkMessage::ServiceAnnouncement(record)).await.unwrap().is_none());
+        // Nobody but the provider can announce its services.
+        let forged = DHTEntry::signed(&DIDManager::new(vec![]), "service:svc-1".to_string(), value, 3600, 2);
+        assert!(dispatcher.dispatch("peer", NetworkMessage::ServiceAnnouncement(forged)).await.is_err());
+        let reply = dispatcher.dispatch("peer", NetworkMessage::ServiceQuery("gpu".to_string())).await.unwrap();
+        assert!(matches!(reply, Some(NetworkMessage::ServiceResponse(services)) if services.len() == 1));
+
+        let attester = DIDManager::new(vec![]);
+        dispatcher.dht.clone().with_identity(attester.clone()).publish_did_document(&attester.document()).await.unwrap();
+        let attestation = attester.create_attestation("did:duxnet:b".to_string(), 4.0, "task_completed".to_string());
+        let unsigned = ReputationAttestation { signature: vec![], ..attestation.clone() };
+        assert!(dispatcher.dispatch("peer", NetworkMessage::ReputationAttestation(unsigned)).await.is_err());
+        dispatcher.dispatch("peer", NetworkMessage::ReputationAttestation(attestation)).await.unwrap();
+        let reply = dispatcher.dispatch("peer", NetworkMessage::ReputationQuery("did:duxnet:b".to_string())).await.unwrap();
+        assert!(matches!(reply, Some(NetworkMessage::ReputationResponse(did, score)) if did == "did:duxnet:b" && score > 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_relayed_gossip_is_checked_against_its_author_and_not_answered() {
+        let (dispatcher, _) = dispatcher();
+        let attester = DIDManager::new(vec![]);
+        dispatcher.dht.clone().with_identity(attester.clone()).publish_did_document(&attester.document()).await.unwrap();
+        let gossip = |attestation| {
+            NetworkMessage::Gossip(GossipEnvelope {
+                message_id: "gossip-1".to_string(),
+                topic: "reputation".to_string(),
+                origin_did: attester.did.id.clone(),
+                hops: 1,
+                max_hops: 3,
+                payload: GossipPayload::ReputationAttestation(attestation),
+            })
+        };
+
+        let attestation = attester.create_attestation("did:duxnet:b".to_string(), 4.0, "task_completed".to_string());
+        let unsigned = ReputationAttestation { signature: vec![], ..attestation.clone() };
+        assert!(dispatcher.dispatch("relay", gossip(unsigned)).await.is_err());
+        assert!(dispatcher.dispatch("relay", gossip(attestation)).await.unwrap().is_none());
+        assert!(dispatcher.reputation_system.get_reputation("did:duxnet:b").await > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_direct_message_is_stored_and_acked() {
+        let (dispatcher, identity) = dispatcher();
+        let sender = DIDManager::new(vec![]);
+        dispatcher.dht.clone().with_identity(sender.clone()).publish_did_document(&sender.document()).await.unwrap();
+        let mut message = Message {
+            id: "msg-1".to_string(),
+            from_did: sender.did.id.clone(),
+            to_did: identity.did.id.clone(),
+            content: "hello".to_string(),
+            message_type: MessageType::Text,
+            timestamp: 1,
+            signature: vec![],
+            is_read: false,
+            reply_to: None,
+        };
+        assert!(dispatcher.dispatch("peer", NetworkMessage::DirectMessage(message.clone())).await.is_err());
+        assert!(dispatcher.messaging_system.get_messages(&sender.did.id).await.is_empty());
+
+        message.signature = sender.sign(&message);
+        let reply = dispatcher.dispatch("peer", NetworkMessage::DirectMessage(message.clone())).await.unwrap();
+        assert!(matches!(reply, Some(NetworkMessage::MessageAck(id)) if id == "msg-1"));
+        assert_eq!(dispatcher.messaging_system.get_messages(&sender.did.id).await.len(), 1);
+
+        let misrouted = Message { id: "msg-2".to_string(), to_did: "did:duxnet:other".to_string(), ..message };
+        assert!(dispatcher.dispatch("peer", NetworkMessage::DirectMessage(misrouted)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tasks_and_escrow_reach_their_subsystems() {
+        let (dispatcher, _) = dispatcher();
+        let task = Task {
+            id: TaskId("task-1".to_string()),
+            escrow_id: "escrow-1".to_string(),
+            service_id: ServiceId("svc-1".to_string()),
+            submitter_did: "peer".to_string(),
+            payload: vec![],
+            requirements: TaskRequirements { cpu_cores: 1, memory_mb: 64, timeout_seconds: 5 },
+            created_at: 1,
+        };
+        let impersonated = Task { submitter_did: "did:duxnet:other".to_string(), ..task.clone() };
+        assert!(dispatcher.dispatch("peer", NetworkMessage::TaskSubmission(impersonated)).await.is_err());
+        dispatcher.dispatch("peer", NetworkMessage::TaskSubmission(task)).await.unwrap();
+        assert_eq!(dispatcher.task_engine.get_pending_tasks().await.len(), 1);
+
+        let buyer = DIDManager::new(vec![]);
+        let seller = DIDManager::new(vec![]);
+        for party in [&buyer, &seller] {
+            dispatcher.dht.clone().with_identity(party.clone()).publish_did_document(&party.document()).await.unwrap();
+        }
+        let (buyer_did, seller_did) = (buyer.did.id.as_str(), seller.did.id.as_str());
+        let contract = EscrowContract {
+            id: "escrow-1".to_string(),
+            buyer_did: buyer_did.to_string(),
+            seller_did: seller_did.to_string(),
+            arbiters: vec![],
+            amount: 10,
+            state: EscrowState::Created,
+            multisig_address: "multisig_escrow-1".to_string(),
+            signatures: Default::default(),
+            created_at: 1,
+        };
+        // Only the parties can open a contract, and only a fresh one.
+        assert!(dispatcher.dispatch("stranger", NetworkMessage::EscrowCreation(contract.clone())).await.is_err());
+        let funded = EscrowContract { state: EscrowState::Funded, ..contract.clone() };
+        assert!(dispatcher.dispatch(buyer_did, NetworkMessage::EscrowCreation(funded)).await.is_err());
+        dispatcher.dispatch(buyer_did, NetworkMessage::EscrowCreation(contract)).await.unwrap();
+
+        let update = |state| NetworkMessage::EscrowStateUpdate("escrow-1".to_string(), state);
+        assert!(dispatcher.dispatch(buyer_did, update(EscrowState::InProgress)).await.is_err());
+        // The buyer cannot sign for the seller, nor approve a step the contract is not at.
+        let forged = buyer.sign(&EscrowApproval { escrow_id: "escrow-1", state: &EscrowState::Funded });
+        let message = NetworkMessage::EscrowSignature("escrow-1".to_string(), seller_did.to_string(), forged);
+        assert!(dispatcher.dispatch(buyer_did, message).await.is_err());
+        let early = buyer.sign(&EscrowApproval { escrow_id: "escrow-1", state: &EscrowState::Completed });
+        let message = NetworkMessage::EscrowSignature("escrow-1".to_string(), buyer_did.to_string(), early);
+        assert!(dispatcher.dispatch(buyer_did, message).await.is_err());
+        assert!(dispatcher.escrow_manager.get_contract("escrow-1").await.unwrap().signatures.is_empty());
+        for party in [&buyer, &seller] {
+            let signature = party.sign(&EscrowApproval { escrow_id: "escrow-1", state: &EscrowState::Funded });
+            let message = NetworkMessage::EscrowSignature("escrow-1".to_string(), party.did.id.clone(), signature);
+            assert!(dispatcher.dispatch("stranger", message.clone()).await.is_err());
+            dispatcher.dispatch(&party.did.id, message).await.unwrap();
+        }
+        assert!(dispatcher.dispatch(seller_did, update(EscrowState::Completed)).await.is_err());
+        assert!(dispatcher.dispatch("stranger", update(EscrowState::InProgress)).await.is_err());
+        dispatcher.dispatch(seller_did, update(EscrowState::InProgress)).await.unwrap();
+        let contract = dispatcher.escrow_manager.get_contract("escrow-1").await.unwrap();
+        assert!(matches!(contract.state, EscrowState::InProgress));
+
+        // Results are only taken from the node that accepted the task.
+        let task_id = TaskId("task-1".to_string());
+        assert!(dispatcher
+            .dispatch("stranger", NetworkMessage::TaskAcceptance(task_id.clone(), seller_did.to_string()))
+            .await
+            .is_err());
+        dispatcher.dispatch(seller_did, NetworkMessage::TaskAcceptance(task_id.clone(), seller_did.to_string())).await.unwrap();
+        let result = TaskResult {
+            task_id,
+            processor_did: buyer_did.to_string(),
+            result: vec![1],
+            proof: vec![],
+            completed_at: 2,
+            escrow_id: "escrow-1".to_string(),
+        };
+        assert!(dispatcher.dispatch(buyer_did, NetworkMessage::TaskCompletion(result.clone())).await.is_err());
+        let result = TaskResult { processor_did: seller_did.to_string(), ..result };
+        assert!(dispatcher.dispatch(buyer_did, NetworkMessage::TaskCompletion(result.clone())).await.is_err());
+        dispatcher.dispatch(seller_did, NetworkMessage::TaskCompletion(result)).await.unwrap();
+        assert_eq!(dispatcher.task_engine.completed_tasks.read().await.len(), 1);
+    }
+}
@@ -1,21 +1,68 @@
 use crate::core::data_structures::*;
+use crate::core::did::DidResolver;
+use crate::core::storage::{Collection, Persisted};
 use anyhow::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
+impl EscrowContract {
+    /// Whether `did` is the buyer, the seller or an arbiter.
+    pub fn is_party(&self, did: &str) -> bool {
+        self.buyer_did == did || self.seller_did == did || self.arbiters.iter().any(|arbiter| arbiter == did)
+    }
+}
+
+/// The state the signatures on a contract in `state` approve: funding while
+/// it is being set up, completion once the work is under way. Contracts in
+/// dispute or refunded take no signatures.
+pub fn approved_state(state: &EscrowState) -> Option<EscrowState> {
+    match state {
+        EscrowState::Created | EscrowState::Funded => Some(EscrowState::Funded),
+        EscrowState::InProgress | EscrowState::Completed => Some(EscrowState::Completed),
+        EscrowState::Disputed | EscrowState::Refunded => None,
+    }
+}
+
+/// Moves `update_state` may make. Funding and completion take the parties'
+/// signatures instead; see `add_signature`.
+fn is_allowed_update(from: &EscrowState, to: &EscrowState) -> bool {
+    matches!(
+        (from, to),
+        (EscrowState::Funded, EscrowState::InProgress)
+            | (EscrowState::Funded | EscrowState::InProgress, EscrowState::Disputed)
+            | (EscrowState::Disputed, EscrowState::Completed | EscrowState::Refunded)
+    )
+}
+
 #[derive(Clone)]
 pub struct EscrowManager {
     pub contracts: Arc<RwLock<HashMap<String, EscrowContract>>>,
     pub threshold: usize,
+    store: Persisted<EscrowContract>,
+    /// Resolves signers' keys to check their approvals against.
+    resolver: DidResolver,
 }
 
 impl EscrowManager {
-    pub fn new() -> Self {
+    pub fn new(resolver: DidResolver) -> Self {
         EscrowManager {
             contracts: Arc::new(RwLock::new(HashMap::new())),
             threshold: 2, // 2 out of 3 multisig by default
+            store: Persisted::memory(),
+            resolver,
+        }
+    }
+
+    /// Loads the contracts kept in `collection` and writes every change back to it.
+    pub fn with_storage(resolver: DidResolver, collection: Collection<EscrowContract>) -> Self {
+        let contracts = collection.iter().map(|(id, contract)| (id.clone(), contract.clone())).collect();
+        EscrowManager {
+            contracts: Arc::new(RwLock::new(contracts)),
+            threshold: 2,
+            store: Persisted::new(collection),
+            resolver,
         }
     }
 
@@ -37,16 +84,56 @@ impl EscrowManager {
         };
         
         let mut contracts = self.contracts.write().await;
+        self.store.save(&escrow_id, &contract).await?;
         contracts.insert(escrow_id.clone(), contract);
         
         info!("Created escrow contract: {}", escrow_id);
         Ok(escrow_id)
     }
 
+    /// Records a contract created by another node. Only new, unsigned contracts
+    /// are taken; the rest of their life plays out through `add_signature` and
+    /// `update_state`. A contract we already track is left alone so a stale
+    /// copy cannot roll back its state or signatures.
+    pub async fn import_contract(&self, contract: EscrowContract) -> Result<bool> {
+        if contract.state != EscrowState::Created || !contract.signatures.is_empty() {
+            return Err(anyhow::anyhow!("Escrow {} is already under way; only new contracts can be imported", contract.id));
+        }
+        let mut contracts = self.contracts.write().await;
+        if contracts.contains_key(&contract.id) {
+            return Ok(false);
+        }
+        debug!("Imported escrow contract {} from the network", contract.id);
+        self.store.save(&contract.id, &contract).await?;
+        contracts.insert(contract.id.clone(), contract);
+        Ok(true)
+    }
+
+    /// Records a party's approval of the contract's next step (see
+    /// `approved_state`), and takes that step once `threshold` parties approve.
+    /// The signature must be the signer's over that approval.
     pub async fn add_signature(&self, escrow_id: &str, signer_did: &str, 
                                signature: Vec<u8>) -> Result<()> {
+        let contract = self
+            .get_contract(escrow_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Escrow contract not found: {}", escrow_id))?;
+        if !contract.is_party(signer_did) {
+            return Err(anyhow::anyhow!("{} is not a party to escrow {}", signer_did, escrow_id));
+        }
+        let approved = approved_state(&contract.state)
+            .ok_or_else(|| anyhow::anyhow!("Escrow {} is {:?} and takes no signatures", escrow_id, contract.state))?;
+        self.resolver
+            .verify_escrow_signature(escrow_id, &approved, signer_did, &signature)
+            .await
+            .map_err(|e| anyhow::anyhow!("Bad signature from {} on escrow {}: {}", signer_did, escrow_id, e))?;
+
         let mut contracts = self.contracts.write().await;
         if let Some(contract) = contracts.get_mut(escrow_id) {
+            // The contract may have moved on while the signer's key was resolved.
+            if approved_state(&contract.state) != Some(approved) {
+                return Err(anyhow::anyhow!("Escrow {} moved on before {}'s signature was checked", escrow_id, signer_did));
+            }
             contract.signatures.insert(signer_did.to_string(), signature);
             
             // Check if we have enough signatures to proceed
@@ -64,6 +151,7 @@ impl EscrowManager {
                 }
             }
             
+            self.store.save(escrow_id, contract).await?;
             debug!("Added signature to escrow {} from {}", escrow_id, signer_did);
             Ok(())
         } else {
@@ -76,10 +164,18 @@ impl EscrowManager {
         contracts.get(escrow_id).cloned()
     }
 
+    /// Moves the contract on without the parties' signatures: starting the
+    /// work, raising a dispute and settling one. Approvals of the step before
+    /// no longer apply, so they are dropped.
     pub async fn update_state(&self, escrow_id: &str, new_state: EscrowState) -> Result<()> {
         let mut contracts = self.contracts.write().await;
         if let Some(contract) = contracts.get_mut(escrow_id) {
+            if !is_allowed_update(&contract.state, &new_state) {
+                return Err(anyhow::anyhow!("Escrow {} cannot move from {:?} to {:?}", escrow_id, contract.state, new_state));
+            }
             contract.state = new_state.clone();
+            contract.signatures.clear();
+            self.store.save(escrow_id, contract).await?;
             info!("Updated escrow {} state to {:?}", escrow_id, new_state);
             Ok(())
         } else {
@@ -87,41 +183,6 @@ impl EscrowManager {
         }
     }
 
-    pub async fn get_contracts_for_did(&self, did: &str) -> Vec<EscrowContract> {
-        let contracts = self.contracts.read().await;
-        contracts
-            .values()
-            .filter(|contract| {
-                contract.buyer_did == did || 
-                contract.seller_did == did || 
-                contract.arbiters.contains(&did.to_string())
-            })
-            .cloned()
-            .collect()
-    }
-
-    pub async fn get_pending_contracts(&self) -> Vec<EscrowContract> {
-        let contracts = self.contracts.read().await;
-        contracts
-            .values()
-            .filter(|contract| {
-                matches!(contract.state, EscrowState::Created | EscrowState::Funded | EscrowState::InProgress)
-            })
-            .cloned()
-            .collect()
-    }
-
-    pub async fn get_completed_contracts(&self) -> Vec<EscrowContract> {
-        let contracts = self.contracts.read().await;
-        contracts
-            .values()
-            .filter(|contract| {
-                matches!(contract.state, EscrowState::Completed | EscrowState::Refunded)
-            })
-            .cloned()
-            .collect()
-    }
-
     pub async fn get_stats(&self) -> EscrowStats {
         let contracts = self.contracts.read().await;
         
@@ -0,0 +1,236 @@
+//! The identities one node acts as. The node's own DID is the primary
+//! identity; more can be created at runtime, e.g. separate buyer and provider
+//! personas. Each has its own signing key, wallet and message inbox. Nodes
+//! with a recovery phrase derive each new identity's key from it, at the next
+//! index of `persona_path`. Archived identities keep their keys and messages
+//! but can no longer be selected.
+
+use crate::core::data_structures::get_current_timestamp;
+use crate::core::identity::DIDManager;
+use crate::core::keystore::{Keystore, Password};
+use crate::core::messaging::MessagingSystem;
+use crate::core::mnemonic::{persona_path, Mnemonic};
+use crate::core::storage::{Collection, DataDir};
+use crate::wallet::Wallet;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing::info;
+
+/// The label the node's own identity is listed under.
+pub const PRIMARY_LABEL: &str = "primary";
+
+/// One DID this node acts as, with everything kept per identity.
+#[derive(Clone)]
+pub struct LocalIdentity {
+    pub did_manager: DIDManager,
+    pub wallet: Arc<RwLock<Wallet>>,
+    pub messaging: Arc<MessagingSystem>,
+    pub label: String,
+    pub created_at: u64,
+    pub archived: bool,
+}
+
+impl LocalIdentity {
+    pub fn did(&self) -> &str {
+        &self.did_manager.did.id
+    }
+}
+
+/// A local identity as listed by the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentitySummary {
+    pub did: String,
+    pub label: String,
+    pub created_at: u64,
+    pub primary: bool,
+    pub archived: bool,
+    pub reputation: f64,
+}
+
+/// A created identity, with its signing key encrypted under the keystore
+/// password like the node's own key.
+#[derive(Clone, Serialize, Deserialize)]
+struct IdentityRecord {
+    did: String,
+    label: String,
+    created_at: u64,
+    archived: bool,
+    /// Where on `persona_path` the key was derived; `None` for a random key.
+    #[serde(default)]
+    index: Option<u32>,
+    keystore: Keystore,
+}
+
+#[derive(Clone)]
+struct IdentityStorage {
+    data_dir: DataDir,
+    password: Password,
+    records: Arc<Mutex<Collection<IdentityRecord>>>,
+}
+
+#[derive(Clone)]
+pub struct IdentityRegistry {
+    primary: String,
+    identities: Arc<RwLock<HashMap<String, LocalIdentity>>>,
+    storage: Option<IdentityStorage>,
+    mnemonic: Option<Mnemonic>,
+    /// The `persona_path` index the next identity's key is derived at.
+    next_index: Arc<Mutex<u32>>,
+}
+
+impl IdentityRegistry {
+    pub fn new(primary: LocalIdentity) -> Self {
+        IdentityRegistry {
+            primary: primary.did().to_string(),
+            identities: Arc::new(RwLock::new(HashMap::from([(primary.did().to_string(), primary)]))),
+            storage: None,
+            mnemonic: None,
+            next_index: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Loads the identities created in `data_dir` and stores new ones there.
+    /// Their keys are unlocked with `password`.
+    pub fn with_storage(primary: LocalIdentity, data_dir: &DataDir, password: Password) -> Result<Self> {
+        let endpoints = primary.did_manager.did.endpoints.clone();
+        let collection = data_dir.collection::<IdentityRecord>("identities")?;
+        let mut identities = HashMap::new();
+        for record in collection.values() {
+            let secret_key = record.keystore.decrypt(&password, Vec::new())?.export_private_key();
+            let did_manager = DIDManager::import_private_key(secret_key, endpoints.clone())?;
+            if did_manager.did.id != record.did {
+                return Err(anyhow::anyhow!("Identity {} is stored with the key of {}", record.did, did_manager.did.id));
+            }
+            let identity = open_identity(data_dir, did_manager, record)?;
+            identities.insert(record.did.clone(), identity);
+        }
+        if !identities.is_empty() {
+            info!("Loaded {} local identities besides {}", identities.len(), primary.did());
+        }
+
+        let next_index = collection.values().filter_map(|record| record.index).max().map_or(0, |index| index + 1);
+
+        let primary_did = primary.did().to_string();
+        identities.insert(primary_did.clone(), primary);
+        Ok(IdentityRegistry {
+            primary: primary_did,
+            identities: Arc::new(RwLock::new(identities)),
+            storage: Some(IdentityStorage {
+                data_dir: data_dir.clone(),
+                password,
+                records: Arc::new(Mutex::new(collection)),
+            }),
+            mnemonic: None,
+            next_index: Arc::new(Mutex::new(next_index)),
+        })
+    }
+
+    /// Derives the keys of identities created from now on from `mnemonic`,
+    /// so the recovery phrase recovers them too.
+    pub fn with_mnemonic(mut self, mnemonic: Mnemonic) -> Self {
+        self.mnemonic = Some(mnemonic);
+        self
+    }
+
+    pub fn primary_did(&self) -> &str {
+        &self.primary
+    }
+
+    /// Creates an identity with a fresh key, wallet and inbox.
+    pub async fn create(&self, label: String) -> Result<LocalIdentity> {
+        let endpoints = self.get(&self.primary).await.map(|p| p.did_manager.did.endpoints).unwrap_or_default();
+        let mut next_index = self.next_index.lock().await;
+        let (did_manager, index) = match &self.mnemonic {
+            Some(mnemonic) => {
+                let secret_key = mnemonic.derive_key(&persona_path(*next_index)).to_vec();
+                (DIDManager::import_private_key(secret_key, endpoints)?, Some(*next_index))
+            }
+            None => (DIDManager::new(endpoints), None),
+        };
+        let created_at = get_current_timestamp();
+
+        let identity = match &self.storage {
+            Some(storage) => {
+                let keystore = Keystore::encrypt(&did_manager, &storage.password)?;
+                let record =
+                    IdentityRecord { did: did_manager.did.id.clone(), label, created_at, archived: false, index, keystore };
+                storage.records.lock().await.insert(record.did.clone(), record.clone())?;
+                open_identity(&storage.data_dir, did_manager, &record)?
+            }
+            None => LocalIdentity {
+                wallet: Arc::new(RwLock::new(Wallet::new(did_manager.did.id.clone())?)),
+                messaging: Arc::new(MessagingSystem::new(did_manager.clone())),
+                did_manager,
+                label,
+                created_at,
+                archived: false,
+            },
+        };
+        if index.is_some() {
+            *next_index += 1;
+        }
+        drop(next_index);
+        info!("Created local identity {} ({})", identity.did(), identity.label);
+        self.identities.write().await.insert(identity.did().to_string(), identity.clone());
+        Ok(identity)
+    }
+
+    /// Any identity this node holds, archived or not.
+    pub async fn get(&self, did: &str) -> Option<LocalIdentity> {
+        self.identities.read().await.get(did).cloned()
+    }
+
+    /// The identity to act as: `did` if given, otherwise the primary one.
+    /// Archived identities cannot be selected.
+    pub async fn select(&self, did: Option<&str>) -> Result<LocalIdentity> {
+        let did = did.unwrap_or(&self.primary);
+        match self.get(did).await {
+            Some(identity) if identity.archived => Err(anyhow::anyhow!("Identity {} is archived", did)),
+            Some(identity) => Ok(identity),
+            None => Err(anyhow::anyhow!("{} is not a local identity", did)),
+        }
+    }
+
+    /// Every identity, the primary first and the rest oldest first, then by label.
+    pub async fn list(&self) -> Vec<LocalIdentity> {
+        let mut identities: Vec<LocalIdentity> = self.identities.read().await.values().cloned().collect();
+        identities.sort_by(|a, b| {
+            (a.did() != self.primary, a.created_at, &a.label).cmp(&(b.did() != self.primary, b.created_at, &b.label))
+        });
+        identities
+    }
+
+    pub async fn archive(&self, did: &str) -> Result<()> {
+        if did == self.primary {
+            return Err(anyhow::anyhow!("The node's primary identity cannot be archived"));
+        }
+        let mut identities = self.identities.write().await;
+        let identity = identities
+            .get_mut(did)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a local identity", did))?;
+        if let Some(storage) = &self.storage {
+            let mut records = storage.records.lock().await;
+            if let Some(record) = records.get(did).cloned() {
+                records.insert(did.to_string(), IdentityRecord { archived: true, ..record })?;
+            }
+        }
+        identity.archived = true;
+        info!("Archived local identity {}", did);
+        Ok(())
+    }
+}
+
+fn open_identity(data_dir: &DataDir, did_manager: DIDManager, record: &IdentityRecord) -> Result<LocalIdentity> {
+    let messages = data_dir.collection(&format!("messages-{}", record.did.replace(':', "_")))?;
+    Ok(LocalIdentity {
+        wallet: Arc::new(RwLock::new(Wallet::new(record.did.clone())?)),
+        messaging: Arc::new(MessagingSystem::with_storage(did_manager.clone(), messages)),
+        did_manager,
+        label: record.label.clone(),
+        created_at: record.created_at,
+        archived: record.archived,
+    })
+}
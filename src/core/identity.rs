@@ -1,14 +1,21 @@
 use crate::core::data_structures::*;
+use crate::core::credentials::{RevocationList, VerifiableCredential, BASE_CREDENTIAL_TYPE};
+use crate::core::did::{DidDocument, DidUpdate};
+use crate::core::signing::Signable;
+use crate::core::mnemonic::{identity_path, Mnemonic};
 use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
 use ed25519_dalek::{SigningKey, Signer, Verifier};
 use rand::rngs::OsRng;
-use tracing::{debug, info};
+use tracing::info;
 
 #[derive(Clone)]
 pub struct DIDManager {
     pub secret_key: Vec<u8>, // Store only the secret key bytes
     pub keypair: SigningKey,
     pub did: DID,
+    /// Signed changes to the DID since it was created, oldest first.
+    pub updates: Vec<DidUpdate>,
 }
 
 impl DIDManager {
@@ -19,7 +26,7 @@ impl DIDManager {
         rng.fill_bytes(&mut secret_bytes);
         let keypair = SigningKey::from_bytes(&secret_bytes);
         let public_key = keypair.verifying_key().to_bytes().to_vec();
-        let did_id = format!("did:duxnet:{}", hex::encode(&public_key[..16]));
+        let did_id = Self::did_from_public_key(&public_key);
         let did = DID {
             id: did_id,
             public_key,
@@ -27,7 +34,13 @@ impl DIDManager {
             created_at: get_current_timestamp(),
         };
         info!("Created new DID: {}", did.id);
-        DIDManager { secret_key: keypair.to_bytes().to_vec(), keypair, did }
+        DIDManager { secret_key: keypair.to_bytes().to_vec(), keypair, did, updates: Vec::new() }
+    }
+
+    /// The identity whose signing key `mnemonic` derives at `identity_path(0)`.
+    pub fn from_mnemonic(mnemonic: &Mnemonic, endpoints: Vec<String>) -> Self {
+        let secret_key = mnemonic.derive_key(&identity_path(0)).to_vec();
+        Self::import_private_key(secret_key, endpoints).expect("derived keys are 32 bytes")
     }
 
     pub fn sign_message(&self, message: &[u8]) -> Vec<u8> {
@@ -35,60 +48,93 @@ impl DIDManager {
         signature.to_bytes().to_vec()
     }
 
-    pub fn verify_signature(&self, sig_bytes: &[u8], message: &[u8]) -> Result<bool, ed25519_dalek::SignatureError> {
-        use std::convert::TryInto;
-        let sig: [u8; 64] = sig_bytes.try_into().map_err(|_| ed25519_dalek::SignatureError::new())?;
-        let sig = ed25519_dalek::Signature::from_bytes(&sig);
-        Ok(self.keypair.verify(message, &sig).is_ok())
+    /// DuxNet DIDs are derived from the first 16 bytes of the Ed25519 public key.
+    pub fn did_from_public_key(public_key: &[u8]) -> String {
+        format!("did:duxnet:{}", hex::encode(&public_key[..16.min(public_key.len())]))
+    }
+
+    /// Verifies a signature made by an arbitrary Ed25519 public key.
+    pub fn verify_with_public_key(public_key: &[u8], sig_bytes: &[u8], message: &[u8]) -> bool {
+        let Ok(key_bytes) = <[u8; 32]>::try_from(public_key) else {
+            return false;
+        };
+        let Ok(sig_bytes) = <[u8; 64]>::try_from(sig_bytes) else {
+            return false;
+        };
+        match ed25519_dalek::VerifyingKey::from_bytes(&key_bytes) {
+            Ok(key) => key.verify(message, &ed25519_dalek::Signature::from_bytes(&sig_bytes)).is_ok(),
+            Err(_) => false,
+        }
     }
 
-    pub fn get_did(&self) -> &DID {
-        &self.did
+    /// This identity's DID document, to publish with `DHT::publish_did_document`.
+    pub fn document(&self) -> DidDocument {
+        DidDocument::for_identity(self)
     }
 
     pub fn get_public_key(&self) -> Vec<u8> {
         self.keypair.verifying_key().to_bytes().to_vec()
     }
 
+    /// Signs the canonical payload of `value`.
+    pub fn sign<T: Signable>(&self, value: &T) -> Vec<u8> {
+        self.sign_message(&value.signing_payload())
+    }
+
+    /// Checks a signature over the canonical payload of `value`. To check a
+    /// DID's signature, use `DidResolver::verify`, which knows its current keys.
+    pub fn verify_signed<T: Signable>(public_key: &[u8], value: &T, signature: &[u8]) -> bool {
+        Self::verify_with_public_key(public_key, signature, &value.signing_payload())
+    }
+
     pub fn create_attestation(&self, target_did: String, score: f64, interaction_type: String) -> ReputationAttestation {
-        let timestamp = get_current_timestamp();
-        let message = format!("{}:{}:{}:{}", self.did.id, target_did, score, interaction_type);
-        let signature = self.sign_message(message.as_bytes());
-        
-        ReputationAttestation {
+        let mut attestation = ReputationAttestation {
             attester_did: self.did.id.clone(),
             target_did,
             score,
             interaction_type,
-            timestamp,
-            signature,
-        }
-    }
-
-    pub fn verify_attestation(&self, attestation: &ReputationAttestation) -> bool {
-        let message = format!("{}:{}:{}:{}", 
-            attestation.attester_did, 
-            attestation.target_did, 
-            attestation.score, 
-            attestation.interaction_type
-        );
-        
-        // For now, we'll verify against our own public key
-        // In a real implementation, you'd need to resolve the attester's DID
-        self.verify_signature(
-            &attestation.signature,
-            message.as_bytes()
-        ).unwrap_or(false)
+            timestamp: get_current_timestamp(),
+            signature: Vec::new(),
+            interaction: None,
+        };
+        attestation.signature = self.sign(&attestation);
+        attestation
     }
 
-    pub fn sign_escrow_contract(&self, escrow_id: &str, state: &EscrowState) -> Vec<u8> {
-        let message = format!("{}:{}", escrow_id, serde_json::to_string(state).unwrap());
-        self.sign_message(message.as_bytes())
+    /// Issues a credential of `credential_type` making `claims` about `subject`.
+    pub fn issue_credential(
+        &self,
+        subject: &str,
+        credential_type: &str,
+        claims: BTreeMap<String, String>,
+        expires_at: Option<u64>,
+    ) -> VerifiableCredential {
+        let mut credential = VerifiableCredential {
+            id: format!("urn:uuid:{}", uuid::Uuid::new_v4()),
+            types: vec![BASE_CREDENTIAL_TYPE.to_string(), credential_type.to_string()],
+            issuer: self.did.id.clone(),
+            subject: subject.to_string(),
+            claims,
+            issued_at: get_current_timestamp(),
+            expires_at,
+            proof: Vec::new(),
+        };
+        credential.proof = self.sign(&credential);
+        info!("Issued {} {} to {}", credential_type, credential.id, subject);
+        credential
     }
 
-    pub fn verify_escrow_signature(&self, escrow_id: &str, state: &EscrowState, signature: &[u8], public_key: &[u8]) -> bool {
-        let message = format!("{}:{}", escrow_id, serde_json::to_string(state).unwrap());
-        self.verify_signature(signature, message.as_bytes()).unwrap_or(false)
+    /// Signs the full list of credentials this DID has revoked, to publish with
+    /// `DHT::publish_revocation_list`.
+    pub fn sign_revocation_list(&self, revoked: BTreeSet<String>) -> RevocationList {
+        let mut list = RevocationList {
+            issuer: self.did.id.clone(),
+            revoked,
+            updated: get_current_timestamp(),
+            signature: Vec::new(),
+        };
+        list.signature = self.sign(&list);
+        list
     }
 
     pub fn export_private_key(&self) -> Vec<u8> {
@@ -96,10 +142,13 @@ impl DIDManager {
     }
 
     pub fn import_private_key(secret_key_bytes: Vec<u8>, endpoints: Vec<String>) -> Result<Self> {
-        let secret_key: [u8; 32] = secret_key_bytes.clone().try_into().unwrap();
+        let secret_key: [u8; 32] = secret_key_bytes
+            .clone()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Ed25519 secret keys are 32 bytes"))?;
         let keypair = SigningKey::from_bytes(&secret_key);
         let public_key = keypair.verifying_key().to_bytes().to_vec();
-        let did_id = format!("did:duxnet:{}", hex::encode(&public_key[..16]));
+        let did_id = Self::did_from_public_key(&public_key);
         let did = DID {
             id: did_id,
             public_key,
@@ -107,23 +156,86 @@ impl DIDManager {
             created_at: get_current_timestamp(),
         };
         info!("Imported DID: {}", did.id);
-        Ok(DIDManager { secret_key: secret_key_bytes, keypair, did })
+        Ok(DIDManager { secret_key: secret_key_bytes, keypair, did, updates: Vec::new() })
     }
 
-    pub fn add_endpoint(&mut self, endpoint: String) {
-        if !self.did.endpoints.contains(&endpoint) {
-            self.did.endpoints.push(endpoint);
-            debug!("Added endpoint to DID: {}", self.did.id);
+    /// Rebuilds an identity whose key has been rotated: `secret_key` must be
+    /// the current key of the DID `updates` belong to.
+    pub fn import_rotated_key(secret_key_bytes: Vec<u8>, updates: Vec<DidUpdate>, endpoints: Vec<String>) -> Result<Self> {
+        let mut identity = Self::import_private_key(secret_key_bytes, endpoints)?;
+        let Some(first) = updates.first() else {
+            return Ok(identity);
+        };
+        let did = Self::did_from_public_key(&first.signer_key);
+        let created = identity.did.created_at.min(first.timestamp);
+        let mut document = DidDocument::inception(&did, first.signer_key.clone(), Vec::new(), created);
+        for update in &updates {
+            document.apply(update)?;
         }
+        if document.current_key().public_key != identity.get_public_key() {
+            return Err(anyhow::anyhow!("This key is not the current key of {}", did));
+        }
+        info!("Imported rotated key of {}", did);
+        identity.did.id = did;
+        identity.did.created_at = created;
+        identity.updates = updates;
+        Ok(identity)
     }
 
-    pub fn remove_endpoint(&mut self, endpoint: &str) {
-        self.did.endpoints.retain(|e| e != endpoint);
-        debug!("Removed endpoint from DID: {}", self.did.id);
+    /// Signs an update to this DID with its current key and applies it:
+    /// `new_key` becomes the signing key, `revoked_keys` stop validating
+    /// signatures made from now on, and `endpoints` replace the published ones.
+    /// Publish the result with `DHT::publish_did_document(&self.document())`.
+    pub fn update(
+        &mut self,
+        new_key: Option<SigningKey>,
+        revoked_keys: Vec<Vec<u8>>,
+        endpoints: Option<Vec<String>>,
+    ) -> Result<DidUpdate> {
+        let mut update = DidUpdate {
+            did: self.did.id.clone(),
+            sequence: self.updates.len() as u64 + 1,
+            previous: self.updates.last().map(DidUpdate::digest).unwrap_or_default(),
+            new_key: new_key.as_ref().map(|key| key.verifying_key().to_bytes().to_vec()),
+            revoked_keys,
+            endpoints,
+            timestamp: get_current_timestamp(),
+            signer_key: self.get_public_key(),
+            signature: Vec::new(),
+        };
+        update.signature = self.sign_message(&update.signing_payload());
+        self.document().apply(&update)?;
+
+        if let Some(new_key) = new_key {
+            self.secret_key = new_key.to_bytes().to_vec();
+            self.did.public_key = new_key.verifying_key().to_bytes().to_vec();
+            self.keypair = new_key;
+        }
+        if let Some(endpoints) = &update.endpoints {
+            self.did.endpoints = endpoints.clone();
+        }
+        self.updates.push(update.clone());
+        info!("Applied update {} to DID {}", update.sequence, self.did.id);
+        Ok(update)
     }
 
-    pub fn update_endpoints(&mut self, endpoints: Vec<String>) {
-        self.did.endpoints = endpoints;
-        debug!("Updated endpoints for DID: {}", self.did.id);
+    /// Moves the DID to a fresh key, revoking the current one if it may be compromised.
+    pub fn rotate_key(&mut self, revoke_current: bool) -> Result<DidUpdate> {
+        let mut secret_bytes = [0u8; 32];
+        use rand::RngCore;
+        OsRng.fill_bytes(&mut secret_bytes);
+        self.rotate_to(SigningKey::from_bytes(&secret_bytes), revoke_current)
+    }
+
+    /// Moves the DID to `new_key`, e.g. the next key a recovery phrase derives.
+    pub fn rotate_to(&mut self, new_key: SigningKey, revoke_current: bool) -> Result<DidUpdate> {
+        let revoked_keys = if revoke_current { vec![self.get_public_key()] } else { Vec::new() };
+        self.update(Some(new_key), revoked_keys, None)
     }
+
+    /// How many times this DID's key has changed.
+    pub fn key_rotations(&self) -> u32 {
+        self.updates.iter().filter(|update| update.new_key.is_some()).count() as u32
+    }
+
 } 
\ No newline at end of file
@@ -0,0 +1,348 @@
+//! Password-protected storage for a node's keys: either its signing key or the
+//! recovery phrase every key is derived from. The secret is encrypted with
+//! XChaCha20-Poly1305 under a key derived from the password with Argon2id.
+
+use crate::core::did::DidUpdate;
+use crate::core::identity::DIDManager;
+use crate::core::mnemonic::{identity_path, Mnemonic};
+use crate::core::storage::write_atomically;
+use anyhow::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+pub const KEYSTORE_VERSION: u32 = 1;
+const KDF_ALGORITHM: &str = "argon2id";
+const CIPHER: &str = "xchacha20poly1305";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// A keystore password. Kept out of `Debug` output so configs can be logged.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Password(String);
+
+impl Password {
+    pub fn new(password: impl Into<String>) -> Self {
+        Password(password.into())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Password(***)")
+    }
+}
+
+/// Argon2id cost settings. The defaults take a noticeable fraction of a second
+/// and 64 MiB, which is the point: every password guess costs as much.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfCost {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfCost {
+    fn default() -> Self {
+        KdfCost { memory_kib: 64 * 1024, iterations: 3, parallelism: 1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    #[serde(flatten)]
+    pub cost: KdfCost,
+    /// Hex encoded.
+    pub salt: String,
+}
+
+/// What a keystore's ciphertext holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeystoreContents {
+    /// The DID's Ed25519 secret key.
+    #[default]
+    SigningKey,
+    /// The entropy of the recovery phrase the DID's key is derived from.
+    Mnemonic,
+}
+
+/// A keystore's decrypted secret.
+#[derive(Debug, Clone)]
+pub enum KeyMaterial {
+    SigningKey(Vec<u8>),
+    Mnemonic(Mnemonic),
+}
+
+impl KeyMaterial {
+    /// The identity this material holds the current key of, once `updates`
+    /// have been applied to its DID.
+    pub fn identity(&self, updates: &[DidUpdate], endpoints: Vec<String>) -> Result<DIDManager> {
+        let secret_key = match self {
+            KeyMaterial::SigningKey(secret_key) => secret_key.clone(),
+            KeyMaterial::Mnemonic(mnemonic) => {
+                let rotations = updates.iter().filter(|update| update.new_key.is_some()).count() as u32;
+                mnemonic.derive_key(&identity_path(rotations)).to_vec()
+            }
+        };
+        DIDManager::import_rotated_key(secret_key, updates.to_vec(), endpoints)
+    }
+
+    /// Moves `identity` to a new key: the next one the recovery phrase derives,
+    /// so the phrase keeps recovering it, or else a random one. Returns the
+    /// material that holds the new key.
+    pub fn rotate(&self, identity: &mut DIDManager, revoke_current: bool) -> Result<(KeyMaterial, DidUpdate)> {
+        match self {
+            KeyMaterial::Mnemonic(mnemonic) => {
+                let new_key = SigningKey::from_bytes(&mnemonic.derive_key(&identity_path(identity.key_rotations() + 1)));
+                let update = identity.rotate_to(new_key, revoke_current)?;
+                Ok((self.clone(), update))
+            }
+            KeyMaterial::SigningKey(_) => {
+                let update = identity.rotate_key(revoke_current)?;
+                Ok((KeyMaterial::SigningKey(identity.export_private_key()), update))
+            }
+        }
+    }
+
+    pub fn mnemonic(&self) -> Option<&Mnemonic> {
+        match self {
+            KeyMaterial::Mnemonic(mnemonic) => Some(mnemonic),
+            KeyMaterial::SigningKey(_) => None,
+        }
+    }
+
+    fn contents(&self) -> (KeystoreContents, Vec<u8>) {
+        match self {
+            KeyMaterial::SigningKey(secret_key) => (KeystoreContents::SigningKey, secret_key.clone()),
+            KeyMaterial::Mnemonic(mnemonic) => (KeystoreContents::Mnemonic, mnemonic.entropy()),
+        }
+    }
+}
+
+/// An encrypted identity, as stored on disk and handed out by exports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    /// DID of the key inside; bound to the ciphertext, so it cannot be relabelled.
+    pub did: String,
+    #[serde(default)]
+    pub contents: KeystoreContents,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    /// Hex encoded.
+    pub nonce: String,
+    /// Hex encoded secret key and authentication tag.
+    pub ciphertext: String,
+    /// Signed changes to the DID, oldest first, ending at the key inside.
+    /// Each is signed by the key it replaces, so they need no encryption.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updates: Vec<DidUpdate>,
+}
+
+impl Keystore {
+    /// Encrypts `identity`'s secret key under `password` with the default KDF cost.
+    pub fn encrypt(identity: &DIDManager, password: &Password) -> Result<Self> {
+        Self::encrypt_with_cost(identity, password, KdfCost::default())
+    }
+
+    pub fn encrypt_with_cost(identity: &DIDManager, password: &Password, cost: KdfCost) -> Result<Self> {
+        Self::seal(&KeyMaterial::SigningKey(identity.export_private_key()), identity.updates.clone(), password, cost)
+    }
+
+    /// Encrypts a recovery phrase under `password` with the default KDF cost.
+    pub fn encrypt_mnemonic(mnemonic: &Mnemonic, password: &Password) -> Result<Self> {
+        Self::seal(&KeyMaterial::Mnemonic(mnemonic.clone()), Vec::new(), password, KdfCost::default())
+    }
+
+    /// Moves the DID inside to a new key, as `KeyMaterial::rotate` does, and
+    /// returns the keystore that holds it along with the signed update.
+    pub fn rotate_key(&self, password: &Password, revoke_current: bool) -> Result<(Self, DidUpdate)> {
+        let material = self.unlock(password)?;
+        let mut identity = material.identity(&self.updates, Vec::new())?;
+        let (material, update) = material.rotate(&mut identity, revoke_current)?;
+        Ok((Self::seal(&material, identity.updates, password, self.kdf.cost)?, update))
+    }
+
+    fn seal(material: &KeyMaterial, updates: Vec<DidUpdate>, password: &Password, cost: KdfCost) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = derive_key(password, &salt, &cost)?;
+        let did = material.identity(&updates, Vec::new())?.did.id;
+        let (contents, secret) = material.contents();
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &secret, aad: &associated_data(&did, contents) })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the keystore"))?;
+
+        Ok(Keystore {
+            version: KEYSTORE_VERSION,
+            did,
+            contents,
+            kdf: KdfParams { algorithm: KDF_ALGORITHM.to_string(), cost, salt: hex::encode(salt) },
+            cipher: CIPHER.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+            updates,
+        })
+    }
+
+    /// Recovers the identity. A wrong password and a tampered file both fail
+    /// the same way.
+    pub fn decrypt(&self, password: &Password, endpoints: Vec<String>) -> Result<DIDManager> {
+        self.unlock(password)?.identity(&self.updates, endpoints)
+    }
+
+    /// Decrypts the keystore's secret and checks it belongs to `did`.
+    pub fn unlock(&self, password: &Password) -> Result<KeyMaterial> {
+        if self.version != KEYSTORE_VERSION || self.kdf.algorithm != KDF_ALGORITHM || self.cipher != CIPHER {
+            return Err(anyhow::anyhow!(
+                "Unsupported keystore: version {}, {} with {}",
+                self.version,
+                self.kdf.algorithm,
+                self.cipher
+            ));
+        }
+        let salt = hex::decode(&self.kdf.salt)?;
+        let nonce = hex::decode(&self.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(anyhow::anyhow!("Keystore nonce must be {} bytes", NONCE_LEN));
+        }
+        let ciphertext = hex::decode(&self.ciphertext)?;
+
+        let key = derive_key(password, &salt, &self.kdf.cost)?;
+        let secret = XChaCha20Poly1305::new(&key.into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload { msg: &ciphertext, aad: &associated_data(&self.did, self.contents) },
+            )
+            .map_err(|_| anyhow::anyhow!("Wrong password, or the keystore for {} is corrupted", self.did))?;
+
+        let material = match self.contents {
+            KeystoreContents::SigningKey => KeyMaterial::SigningKey(secret),
+            KeystoreContents::Mnemonic => KeyMaterial::Mnemonic(Mnemonic::from_entropy(&secret)?),
+        };
+        let did = material.identity(&self.updates, Vec::new())?.did.id;
+        if did != self.did {
+            return Err(anyhow::anyhow!("Keystore for {} holds the key of {}", self.did, did));
+        }
+        Ok(material)
+    }
+
+    /// Re-encrypts the same secret under `new_password`, with a fresh salt and nonce.
+    pub fn change_password(&self, old_password: &Password, new_password: &Password) -> Result<Self> {
+        Self::seal(&self.unlock(old_password)?, self.updates.clone(), new_password, self.kdf.cost)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path)?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| anyhow::anyhow!("Unreadable keystore {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomically(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+fn derive_key(password: &Password, salt: &[u8], cost: &KdfCost) -> Result<[u8; 32]> {
+    let params = Params::new(cost.memory_kib, cost.iterations, cost.parallelism, Some(32))
+        .map_err(|e| anyhow::anyhow!("Invalid keystore KDF parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive the keystore key: {}", e))?;
+    Ok(key)
+}
+
+fn associated_data(did: &str, contents: KeystoreContents) -> Vec<u8> {
+    match contents {
+        KeystoreContents::SigningKey => format!("duxnet-keystore-v{}:{}", KEYSTORE_VERSION, did).into_bytes(),
+        KeystoreContents::Mnemonic => format!("duxnet-keystore-v{}:{}:mnemonic", KEYSTORE_VERSION, did).into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough for tests; real keystores use `KdfCost::default()`.
+    const TEST_COST: KdfCost = KdfCost { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    #[test]
+    fn test_keystore_round_trip_and_password_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("identity.keystore");
+        let identity = DIDManager::new(vec![]);
+        let password = Password::new("correct horse");
+
+        Keystore::encrypt_with_cost(&identity, &password, TEST_COST).unwrap().save(&path).unwrap();
+        let keystore = Keystore::load(&path).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&hex::encode(identity.export_private_key())));
+        let unlocked = keystore.decrypt(&password, vec![]).unwrap();
+        assert_eq!(unlocked.did.id, identity.did.id);
+        assert!(keystore.decrypt(&Password::new("wrong"), vec![]).is_err());
+
+        let changed = keystore.change_password(&password, &Password::new("battery staple")).unwrap();
+        assert_ne!(changed.kdf.salt, keystore.kdf.salt);
+        assert!(changed.decrypt(&password, vec![]).is_err());
+        assert_eq!(changed.decrypt(&Password::new("battery staple"), vec![]).unwrap().did.id, identity.did.id);
+        assert!(keystore.change_password(&Password::new("wrong"), &password).is_err());
+    }
+
+    #[test]
+    fn test_rotated_keys_are_kept_with_their_update_chain() {
+        let password = Password::new("pw");
+        let mnemonic = Mnemonic::generate();
+        let original = DIDManager::from_mnemonic(&mnemonic, vec![]);
+        let keystore = Keystore::seal(&KeyMaterial::Mnemonic(mnemonic.clone()), vec![], &password, TEST_COST).unwrap();
+
+        let (keystore, _) = keystore.rotate_key(&password, false).unwrap();
+        let (keystore, update) = keystore.rotate_key(&password, true).unwrap();
+        let rotated = keystore.decrypt(&password, vec![]).unwrap();
+        assert_eq!(rotated.did.id, original.did.id);
+        assert_eq!(rotated.updates.len(), 2);
+        assert_eq!(update.new_key, Some(rotated.get_public_key()));
+        // The phrase still derives the current key.
+        assert_eq!(rotated.export_private_key(), mnemonic.derive_key(&identity_path(2)).to_vec());
+        assert!(keystore.rotate_key(&Password::new("wrong"), false).is_err());
+
+        let keystore = Keystore::encrypt_with_cost(&DIDManager::new(vec![]), &password, TEST_COST).unwrap();
+        let (rotated, _) = keystore.rotate_key(&password, true).unwrap();
+        let identity = rotated.decrypt(&password, vec![]).unwrap();
+        assert_eq!(identity.did.id, keystore.did);
+        assert_ne!(identity.get_public_key(), keystore.decrypt(&password, vec![]).unwrap().get_public_key());
+        // The chain cannot be dropped to pass the new key off as another DID.
+        assert!(Keystore { updates: vec![], ..rotated }.decrypt(&password, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_tampered_keystore_is_rejected() {
+        let identity = DIDManager::new(vec![]);
+        let password = Password::new("pw");
+        let keystore = Keystore::encrypt_with_cost(&identity, &password, TEST_COST).unwrap();
+
+        let relabelled = Keystore { did: DIDManager::new(vec![]).did.id, ..keystore.clone() };
+        assert!(relabelled.decrypt(&password, vec![]).is_err());
+
+        let mut ciphertext = hex::decode(&keystore.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let flipped = Keystore { ciphertext: hex::encode(ciphertext), ..keystore.clone() };
+        assert!(flipped.decrypt(&password, vec![]).is_err());
+
+        assert_eq!(format!("{:?}", password), "Password(***)");
+    }
+}
@@ -0,0 +1,308 @@
+//! Iterative Kademlia lookups. Each round queries the `ALPHA` closest peers not yet
+//! asked, merges the contacts they return, and stops once the `k` closest known
+//! peers have all answered (or a value turns up).
+
+use crate::core::data_structures::{get_current_timestamp, PeerContact};
+use crate::core::dht::{DhtTransport, FindValueResult, DHTEntry, DHT};
+use crate::core::routing_table::{routing_key, xor_distance, RoutingKey};
+use futures::future::join_all;
+use std::collections::{BTreeMap, HashSet};
+use tracing::debug;
+
+/// Number of peers queried in parallel per round.
+pub const ALPHA: usize = 3;
+
+#[derive(PartialEq)]
+enum QueryState {
+    Pending,
+    Responded,
+}
+
+struct Shortlist {
+    target: RoutingKey,
+    local_did: String,
+    peers: BTreeMap<RoutingKey, (PeerContact, QueryState)>,
+    failed: HashSet<String>,
+}
+
+impl Shortlist {
+    fn new(key: &str, local_did: &str, seeds: Vec<PeerContact>) -> Self {
+        let mut shortlist = Shortlist {
+            target: routing_key(key),
+            local_did: local_did.to_string(),
+            peers: BTreeMap::new(),
+            failed: HashSet::new(),
+        };
+        shortlist.extend(seeds);
+        shortlist
+    }
+
+    fn extend(&mut self, contacts: Vec<PeerContact>) {
+        for contact in contacts {
+            if contact.did == self.local_did || self.failed.contains(&contact.did) {
+                continue;
+            }
+            let distance = xor_distance(&routing_key(&contact.did), &self.target);
+            self.peers.entry(distance).or_insert((contact, QueryState::Pending));
+        }
+    }
+
+    /// Up to `ALPHA` unqueried peers among the `k` closest, or none once the
+    /// `k` closest have all responded.
+    fn next_round(&self, k: usize) -> Vec<PeerContact> {
+        self.peers
+            .values()
+            .take(k)
+            .filter(|(_, state)| *state == QueryState::Pending)
+            .take(ALPHA)
+            .map(|(contact, _)| contact.clone())
+            .collect()
+    }
+
+    fn mark_responded(&mut self, contact: &PeerContact) {
+        let distance = xor_distance(&routing_key(&contact.did), &self.target);
+        if let Some((_, state)) = self.peers.get_mut(&distance) {
+            *state = QueryState::Responded;
+        }
+    }
+
+    fn mark_failed(&mut self, contact: &PeerContact) {
+        self.peers.remove(&xor_distance(&routing_key(&contact.did), &self.target));
+        self.failed.insert(contact.did.clone());
+    }
+
+    fn closest_responded(&self, k: usize) -> Vec<PeerContact> {
+        self.peers
+            .values()
+            .filter(|(_, state)| *state == QueryState::Responded)
+            .take(k)
+            .map(|(contact, _)| contact.clone())
+            .collect()
+    }
+}
+
+/// The `k` closest reachable peers to `key`, found by iterative FIND_NODE.
+pub async fn find_node(dht: &DHT, transport: &dyn DhtTransport, key: &str) -> Vec<PeerContact> {
+    let k = dht.k_bucket_size;
+    let mut shortlist = Shortlist::new(key, &dht.node_id.0, dht.closest_contacts(key, k).await);
+
+    loop {
+        let round = shortlist.next_round(k);
+        if round.is_empty() {
+            break;
+        }
+
+        let replies = join_all(round.iter().map(|contact| transport.find_node(contact, key))).await;
+        for (contact, reply) in round.iter().zip(replies) {
+            match reply {
+                Ok(contacts) => {
+                    shortlist.mark_responded(contact);
+                    remember(dht, contact).await;
+                    shortlist.extend(contacts);
+                }
+                Err(e) => {
+                    debug!("FIND_NODE to {} failed: {}", contact.did, e);
+                    shortlist.mark_failed(contact);
+                }
+            }
+        }
+    }
+
+    shortlist.closest_responded(k)
+}
+
+/// Iterative FIND_VALUE: returns the first unexpired copy of `key` any peer holds.
+pub async fn find_value(dht: &DHT, transport: &dyn DhtTransport, key: &str) -> Option<DHTEntry> {
+    let k = dht.k_bucket_size;
+    let mut shortlist = Shortlist::new(key, &dht.node_id.0, dht.closest_contacts(key, k).await);
+
+    loop {
+        let round = shortlist.next_round(k);
+        if round.is_empty() {
+            debug!("FIND_VALUE for {} found nothing", key);
+            return None;
+        }
+
+        let replies = join_all(round.iter().map(|contact| transport.find_value(contact, key))).await;
+        for (contact, reply) in round.iter().zip(replies) {
+            match reply {
+                Ok(FindValueResult::Found(entry)) => {
+                    if is_usable(dht, &entry, key).await {
+                        remember(dht, contact).await;
+                        return Some(entry);
+                    }
+                    debug!("Peer {} returned an expired, mismatched or forged entry for {}", contact.did, key);
+                    shortlist.mark_responded(contact);
+                }
+                Ok(FindValueResult::Closer(contacts)) => {
+                    shortlist.mark_responded(contact);
+                    remember(dht, contact).await;
+                    shortlist.extend(contacts);
+                }
+                Err(e) => {
+                    debug!("FIND_VALUE to {} failed: {}", contact.did, e);
+                    shortlist.mark_failed(contact);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a record a peer returned is one we would store ourselves: signed,
+/// unexpired, and published by whoever its key belongs to.
+async fn is_usable(dht: &DHT, entry: &DHTEntry, key: &str) -> bool {
+    entry.key == key
+        && !entry.is_expired(get_current_timestamp())
+        && entry.verify().is_ok()
+        && dht.check_key_binding(entry).await.is_ok()
+}
+
+/// Sends STORE for `entry` to every peer in `targets` in parallel and returns
+/// the peers that answered. A peer that refuses already holds a version it
+/// prefers, so it counts as answered too.
+pub async fn replicate(transport: &dyn DhtTransport, targets: &[PeerContact], entry: &DHTEntry) -> Vec<String> {
+    let replies = join_all(targets.iter().map(|contact| transport.store(contact, entry))).await;
+    targets
+        .iter()
+        .zip(replies)
+        .filter_map(|(contact, reply)| match reply {
+            Ok(_) => Some(contact.did.clone()),
+            Err(e) => {
+                debug!("STORE of {} to {} failed: {}", entry.key, contact.did, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Peers that answer a lookup go into the routing table like any other peer we hear from.
+async fn remember(dht: &DHT, contact: &PeerContact) {
+    if let Err(e) = dht.add_contact(contact.clone()).await {
+        debug!("Failed to add contact {}: {}", contact.did, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_structures::NodeId;
+    use crate::core::identity::DIDManager;
+    use anyhow::Result;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// An in-process network: every "peer" is a DHT that answers RPCs directly.
+    #[derive(Clone, Default)]
+    struct SimulatedNetwork {
+        nodes: Arc<std::sync::Mutex<HashMap<String, DHT>>>,
+    }
+
+    impl SimulatedNetwork {
+        fn node(&self, did: &str) -> Result<DHT> {
+            self.nodes
+                .lock()
+                .unwrap()
+                .get(did)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("{} is unreachable", did))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DhtTransport for SimulatedNetwork {
+        async fn ping(&self, peer_id: &str) -> bool {
+            self.node(peer_id).is_ok()
+        }
+
+        async fn find_node(&self, contact: &PeerContact, key: &str) -> Result<Vec<PeerContact>> {
+            let node = self.node(&contact.did)?;
+            Ok(node.closest_contacts(key, node.k_bucket_size).await)
+        }
+
+        async fn find_value(&self, contact: &PeerContact, key: &str) -> Result<FindValueResult> {
+            let node = self.node(&contact.did)?;
+            Ok(match node.get_entry_local(key).await {
+                Some(entry) => FindValueResult::Found(entry),
+                None => FindValueResult::Closer(node.closest_contacts(key, node.k_bucket_size).await),
+            })
+        }
+
+        async fn store(&self, contact: &PeerContact, entry: &DHTEntry) -> Result<bool> {
+            self.node(&contact.did)?.store_entry_local(entry.clone()).await?;
+            Ok(true)
+        }
+    }
+
+    fn contact(did: &str) -> PeerContact {
+        PeerContact { did: did.to_string(), endpoint: format!("tcp://{}", did) }
+    }
+
+    /// A chain of nodes where each one only knows its neighbours, so reaching
+    /// anything beyond the next hop needs an iterative lookup.
+    async fn chain(length: usize) -> (SimulatedNetwork, Vec<DHT>) {
+        let network = SimulatedNetwork::default();
+        let nodes: Vec<DHT> = (0..length)
+            .map(|i| {
+                DHT::new(NodeId(format!("node-{}", i)))
+                    .with_identity(DIDManager::new(vec![]))
+                    .with_transport(Arc::new(network.clone()))
+            })
+            .collect();
+        for (i, node) in nodes.iter().enumerate() {
+            network.nodes.lock().unwrap().insert(node.node_id.0.clone(), node.clone());
+            if i > 0 {
+                node.add_contact(contact(&nodes[i - 1].node_id.0)).await.unwrap();
+                nodes[i - 1].add_contact(contact(&node.node_id.0)).await.unwrap();
+            }
+        }
+        (network, nodes)
+    }
+
+    #[tokio::test]
+    async fn test_value_stored_at_one_end_is_found_at_the_other() {
+        let (_, nodes) = chain(6).await;
+
+        nodes[0].store("escrow:e1".to_string(), b"contract".to_vec(), 3600).await.unwrap();
+        assert_eq!(nodes[5].get("escrow:e1").await, Some(b"contract".to_vec()));
+        // The value is now cached by the node that asked.
+        assert!(nodes[5].get_entry_local("escrow:e1").await.is_some());
+        assert_eq!(nodes[3].get("escrow:missing").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_store_replicates_to_closest_nodes() {
+        let (_, nodes) = chain(8).await;
+
+        nodes[0].store("escrow:e1".to_string(), b"contract".to_vec(), 3600).await.unwrap();
+        let replicas = join_all(nodes.iter().map(|node| node.get_entry_local("escrow:e1")))
+            .await
+            .into_iter()
+            .filter(Option::is_some)
+            .count();
+        // k = 20 exceeds the network size, so every node holds a copy.
+        assert_eq!(replicas, nodes.len());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_skips_unreachable_peers() {
+        let (network, nodes) = chain(5).await;
+        let record = DHTEntry::signed(&DIDManager::new(vec![]), "escrow:e1".to_string(), b"contract".to_vec(), 3600, 1);
+        nodes[4].store_entry_local(record).await.unwrap();
+        // node-4 is known through node-3 only; take node-2 off the network.
+        network.nodes.lock().unwrap().remove("node-2");
+        nodes[0].add_contact(contact("node-3")).await.unwrap();
+
+        assert_eq!(nodes[0].get("escrow:e1").await, Some(b"contract".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_ignores_records_squatting_another_did() {
+        let (_, nodes) = chain(3).await;
+        let key = "active_did:did:duxnet:victim".to_string();
+        let squatted = DHTEntry::signed(&DIDManager::new(vec![]), key.clone(), b"online".to_vec(), 3600, 1);
+        nodes[2].entries.write().await.insert(squatted).unwrap();
+
+        assert_eq!(nodes[0].get(&key).await, None);
+        assert!(nodes[0].get_entry_local(&key).await.is_none());
+    }
+}
@@ -1,10 +1,11 @@
 use crate::core::data_structures::*;
 use crate::core::identity::DIDManager;
+use crate::core::storage::{Collection, Persisted};
 use anyhow::Result;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tracing::{debug, info};
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 
@@ -12,12 +13,7 @@ pub struct MessagingSystem {
     messages: Arc<RwLock<HashMap<String, Message>>>,
     conversations: Arc<RwLock<HashMap<String, Conversation>>>,
     did_manager: DIDManager,
-    message_handlers: Arc<RwLock<Vec<Box<dyn MessageHandler + Send + Sync>>>>,
-}
-
-#[async_trait::async_trait]
-pub trait MessageHandler: Send + Sync {
-    async fn handle_message(&self, message: &Message) -> Result<()>;
+    store: Persisted<Message>,
 }
 
 impl MessagingSystem {
@@ -26,7 +22,26 @@ impl MessagingSystem {
             messages: Arc::new(RwLock::new(HashMap::new())),
             conversations: Arc::new(RwLock::new(HashMap::new())),
             did_manager,
-            message_handlers: Arc::new(RwLock::new(Vec::new())),
+            store: Persisted::memory(),
+        }
+    }
+
+    /// Loads the messages kept in `collection`, rebuilding the conversations
+    /// from them, and writes every change back to it.
+    pub fn with_storage(did_manager: DIDManager, collection: Collection<Message>) -> Self {
+        let mut history: Vec<&Message> = collection.values().collect();
+        history.sort_by_key(|message| message.timestamp);
+        let mut conversations = HashMap::new();
+        for message in &history {
+            record_in_conversation(&mut conversations, &did_manager.did.id, message);
+        }
+        let messages = history.into_iter().map(|message| (message.id.clone(), message.clone())).collect();
+
+        Self {
+            messages: Arc::new(RwLock::new(messages)),
+            conversations: Arc::new(RwLock::new(conversations)),
+            did_manager,
+            store: Persisted::new(collection),
         }
     }
 
@@ -34,42 +49,29 @@ impl MessagingSystem {
         let message_id = Uuid::new_v4().to_string();
         let timestamp = get_current_timestamp();
         
-        // Create message content for signing
-        let content_to_sign = format!("{}:{}:{}:{}:{}", 
-            message_id, 
-            self.did_manager.did.id, 
-            request.to_did, 
-            request.content, 
-            timestamp
-        );
-        
-        // Sign the message
-        let signature = self.did_manager.sign_message(content_to_sign.as_bytes());
-        
-        let message = Message {
+        let mut message = Message {
             id: message_id.clone(),
             from_did: self.did_manager.did.id.clone(),
             to_did: request.to_did.clone(),
             content: request.content,
             message_type: request.message_type,
             timestamp,
-            signature,
+            signature: Vec::new(),
             is_read: false,
             reply_to: request.reply_to,
         };
+        message.signature = self.did_manager.sign(&message);
         
         // Store the message
         {
             let mut messages = self.messages.write().await;
+            self.store.save(&message_id, &message).await?;
             messages.insert(message_id.clone(), message.clone());
         }
         
         // Update conversation
         self.update_conversation(&message).await?;
         
-        // Notify handlers
-        self.notify_handlers(&message).await?;
-        
         info!("Sent message {} to {}", message_id, request.to_did);
         
         Ok(MessageResponse {
@@ -79,31 +81,21 @@ impl MessagingSystem {
         })
     }
 
+    /// Stores a message from another node. Its signature is checked by the
+    /// `MessageDispatcher` before it gets here.
     pub async fn receive_message(&self, message: Message) -> Result<()> {
-        // Verify signature
-        let content_to_verify = format!("{}:{}:{}:{}:{}", 
-            message.id, 
-            message.from_did, 
-            message.to_did, 
-            message.content, 
-            message.timestamp
-        );
-        
-        // For now, we'll accept all messages (in production, verify the signature)
         debug!("Received message {} from {}", message.id, message.from_did);
         
         // Store the message
         {
             let mut messages = self.messages.write().await;
+            self.store.save(&message.id, &message).await?;
             messages.insert(message.id.clone(), message.clone());
         }
         
         // Update conversation
         self.update_conversation(&message).await?;
         
-        // Notify handlers
-        self.notify_handlers(&message).await?;
-        
         Ok(())
     }
 
@@ -119,7 +111,7 @@ impl MessagingSystem {
         }
         
         // Sort by timestamp
-        conversation_messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        conversation_messages.sort_by_key(|message| message.timestamp);
         conversation_messages
     }
 
@@ -132,6 +124,7 @@ impl MessagingSystem {
         let mut messages = self.messages.write().await;
         if let Some(message) = messages.get_mut(message_id) {
             message.is_read = true;
+            self.store.save(message_id, message).await?;
             debug!("Marked message {} as read", message_id);
         }
         Ok(())
@@ -140,6 +133,8 @@ impl MessagingSystem {
     pub async fn delete_message(&self, message_id: &str) -> Result<()> {
         let mut messages = self.messages.write().await;
         if let Some(message) = messages.remove(message_id) {
+            self.store.delete(message_id).await?;
+            drop(messages);
             // Update conversation
             self.recalculate_conversation(&message.from_did).await?;
             debug!("Deleted message {}", message_id);
@@ -148,28 +143,8 @@ impl MessagingSystem {
     }
 
     async fn update_conversation(&self, message: &Message) -> Result<()> {
-        let peer_did = if message.from_did == self.did_manager.did.id {
-            &message.to_did
-        } else {
-            &message.from_did
-        };
-        
         let mut conversations = self.conversations.write().await;
-        let conversation = conversations.entry(peer_did.clone()).or_insert_with(|| Conversation {
-            peer_did: peer_did.clone(),
-            last_message: None,
-            unread_count: 0,
-            message_count: 0,
-        });
-        
-        conversation.last_message = Some(message.clone());
-        conversation.message_count += 1;
-        
-        // Update unread count if message is from peer
-        if message.from_did != self.did_manager.did.id && !message.is_read {
-            conversation.unread_count += 1;
-        }
-        
+        record_in_conversation(&mut conversations, &self.did_manager.did.id, message);
         Ok(())
     }
 
@@ -194,21 +169,6 @@ impl MessagingSystem {
         Ok(())
     }
 
-    async fn notify_handlers(&self, message: &Message) -> Result<()> {
-        let handlers = self.message_handlers.read().await;
-        for handler in handlers.iter() {
-            if let Err(e) = handler.handle_message(message).await {
-                warn!("Message handler error: {}", e);
-            }
-        }
-        Ok(())
-    }
-
-    pub async fn add_message_handler(&self, handler: Box<dyn MessageHandler + Send + Sync>) {
-        let mut handlers = self.message_handlers.write().await;
-        handlers.push(handler);
-    }
-
     pub async fn get_message_stats(&self) -> MessageStats {
         let messages = self.messages.read().await;
         let conversations = self.conversations.read().await;
@@ -227,6 +187,29 @@ impl MessagingSystem {
     }
 }
 
+fn record_in_conversation(conversations: &mut HashMap<String, Conversation>, local_did: &str, message: &Message) {
+    let peer_did = if message.from_did == local_did {
+        &message.to_did
+    } else {
+        &message.from_did
+    };
+
+    let conversation = conversations.entry(peer_did.clone()).or_insert_with(|| Conversation {
+        peer_did: peer_did.clone(),
+        last_message: None,
+        unread_count: 0,
+        message_count: 0,
+    });
+
+    conversation.last_message = Some(message.clone());
+    conversation.message_count += 1;
+
+    // Update unread count if message is from peer
+    if message.from_did != local_did && !message.is_read {
+        conversation.unread_count += 1;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageStats {
     pub total_messages: usize,
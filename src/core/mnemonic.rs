@@ -0,0 +1,179 @@
+//! Recovery phrases and the keys derived from them. One BIP39 mnemonic seeds
+//! every key a node holds: its DID signing key and one wallet key per currency,
+//! each at its own SLIP-0010 (hardened Ed25519) derivation path.
+
+use crate::wallet::Currency;
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha512;
+use std::fmt;
+
+/// Coin type used for DuxNet's own paths. Not registered with SLIP-0044.
+pub const DUXNET_COIN_TYPE: u32 = 7337;
+const PURPOSE: u32 = 44;
+const HARDENED: u32 = 0x8000_0000;
+const PERSONA_ACCOUNT: u32 = 2;
+const ENTROPY_BYTES: usize = 32;
+
+/// A BIP39 mnemonic: 24 words from the English wordlist, the last carrying a checksum.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic(bip39::Mnemonic);
+
+impl Mnemonic {
+    pub fn generate() -> Self {
+        let mut entropy = [0u8; ENTROPY_BYTES];
+        OsRng.fill_bytes(&mut entropy);
+        Mnemonic(bip39::Mnemonic::from_entropy(&entropy).expect("32 bytes is a valid BIP39 entropy length"))
+    }
+
+    /// Parses a phrase, rejecting unknown words and bad checksums.
+    pub fn parse(phrase: &str) -> Result<Self> {
+        let words: Vec<&str> = phrase.split_whitespace().collect();
+        bip39::Mnemonic::parse_normalized(&words.join(" ").to_lowercase())
+            .map(Mnemonic)
+            .map_err(|e| anyhow::anyhow!("Invalid recovery phrase: {}", e))
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self> {
+        bip39::Mnemonic::from_entropy(entropy)
+            .map(Mnemonic)
+            .map_err(|e| anyhow::anyhow!("Invalid recovery phrase entropy: {}", e))
+    }
+
+    pub fn entropy(&self) -> Vec<u8> {
+        self.0.to_entropy()
+    }
+
+    pub fn phrase(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// The 64-byte BIP39 seed keys are derived from; no passphrase.
+    pub fn seed(&self) -> [u8; 64] {
+        self.0.to_seed("")
+    }
+
+    /// The secret key at `path`, e.g. `identity_path(0)`.
+    pub fn derive_key(&self, path: &[u32]) -> [u8; 32] {
+        derive_ed25519(&self.seed(), path)
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mnemonic(***)")
+    }
+}
+
+/// m/44'/7337'/0'/n': the DID signing key after `n` key rotations.
+pub fn identity_path(rotations: u32) -> [u32; 4] {
+    [PURPOSE, DUXNET_COIN_TYPE, 0, rotations]
+}
+
+/// m/44'/7337'/2'/n': the signing key of the node's n-th created identity,
+/// in an account of its own, clear of the node's identity and DUX keys.
+pub fn persona_path(index: u32) -> [u32; 4] {
+    [PURPOSE, DUXNET_COIN_TYPE, PERSONA_ACCOUNT, index]
+}
+
+/// m/44'/coin'/0'/0'/0' with the currency's SLIP-0044 coin type. DUX lives under
+/// DuxNet's coin type in account 1, clear of the identity key.
+pub fn wallet_path(currency: Currency) -> [u32; 5] {
+    let (coin_type, account) = match currency {
+        Currency::BTC => (0, 0),
+        Currency::LTC => (2, 0),
+        Currency::DOGE => (3, 0),
+        // USDC is an ERC-20 token and shares Ethereum's keys.
+        Currency::ETH | Currency::USDC => (60, 0),
+        Currency::XMR => (128, 0),
+        Currency::DUX => (DUXNET_COIN_TYPE, 1),
+    };
+    [PURPOSE, coin_type, account, 0, 0]
+}
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// SLIP-0010 derivation for Ed25519. Ed25519 only supports hardened children,
+/// so every index in `path` is hardened.
+pub fn derive_ed25519(seed: &[u8], path: &[u32]) -> [u8; 32] {
+    let (mut key, mut chain_code) = split(hmac_sha512(b"ed25519 seed", &[seed]));
+    for index in path {
+        let hardened = (index | HARDENED).to_be_bytes();
+        (key, chain_code) = split(hmac_sha512(&chain_code, &[&[0u8], &key, &hardened]));
+    }
+    key
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn split(output: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut key = [0u8; 32];
+    let mut chain_code = [0u8; 32];
+    key.copy_from_slice(&output[..32]);
+    chain_code.copy_from_slice(&output[32..]);
+    (key, chain_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bip39_vectors_and_checksum() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let mnemonic = Mnemonic::parse(phrase).unwrap();
+        assert_eq!(mnemonic.entropy(), vec![0u8; 16]);
+        assert_eq!(
+            hex::encode(mnemonic.0.to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        // Whitespace and case do not matter, the checksum word does.
+        assert_eq!(Mnemonic::parse(&format!("  {} ", phrase.to_uppercase())).unwrap(), mnemonic);
+        assert!(Mnemonic::parse(&phrase.replace("about", "abandon")).is_err());
+        assert!(Mnemonic::parse("abandon abandon notaword").is_err());
+
+        let generated = Mnemonic::generate();
+        assert_eq!(generated.phrase().split(' ').count(), 24);
+        assert_eq!(Mnemonic::from_entropy(&generated.entropy()).unwrap(), generated);
+    }
+
+    #[test]
+    fn test_slip10_ed25519_vectors() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert_eq!(
+            hex::encode(derive_ed25519(&seed, &[])),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(derive_ed25519(&seed, &[0])),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+        assert_eq!(
+            hex::encode(derive_ed25519(&seed, &[0, 1, 2, 2, 1000000000])),
+            "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793"
+        );
+    }
+
+    #[test]
+    fn test_wallet_keys_are_separate_from_the_identity_key() {
+        let mnemonic = Mnemonic::generate();
+        let wallet = crate::wallet::Wallet::from_mnemonic(&mnemonic).unwrap();
+        let identity_key = mnemonic.derive_key(&identity_path(0)).to_vec();
+
+        assert_eq!(wallet.currency_keys[&Currency::ETH], wallet.currency_keys[&Currency::USDC]);
+        assert_ne!(wallet.currency_keys[&Currency::BTC], wallet.currency_keys[&Currency::ETH]);
+        assert!(wallet.currency_keys.values().all(|key| *key != identity_key));
+        assert_eq!(wallet.secret_key, wallet.currency_keys[&Currency::DUX]);
+
+        let restored = crate::wallet::Wallet::restore_wallet(&wallet.backup_wallet().unwrap()).unwrap();
+        assert_eq!(restored.get_all_addresses(), wallet.get_all_addresses());
+    }
+}
@@ -1,70 +1,187 @@
 pub mod data_structures;
 pub mod dht;
+pub mod dht_store;
+pub mod quota;
+pub mod snapshot;
+pub mod storage;
+pub mod keystore;
+pub mod mnemonic;
+pub mod catalogue;
+pub mod routing_table;
+pub mod lookup;
 pub mod identity;
+pub mod identities;
+pub mod credentials;
+pub mod signing;
+pub mod did;
 pub mod reputation;
+pub mod trust;
 pub mod escrow;
 pub mod tasks;
 pub mod community_fund;
 pub mod messaging;
+pub mod dispatcher;
+pub mod config;
 
 use anyhow::Result;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, error};
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn, error};
 
 use data_structures::*;
 use dht::DHT;
+use dht_store::DiskStore;
 use identity::DIDManager;
-use reputation::ReputationSystem;
+use identities::{IdentityRegistry, IdentitySummary, LocalIdentity, PRIMARY_LABEL};
+use credentials::VerifiableCredential;
+use did::{DidResolver, DidUpdate};
+use keystore::{KeyMaterial, Keystore, Password};
+use mnemonic::Mnemonic;
+use storage::DataDir;
+use reputation::{LocalInteractions, ReputationSystem, ScoringStrategy};
 use escrow::EscrowManager;
 use tasks::TaskEngine;
 use community_fund::CommunityFundManager;
 use messaging::MessagingSystem;
-use crate::network::P2PNetwork;
+use dispatcher::MessageDispatcher;
+use config::NodeConfig;
+use crate::network::{InboundMessage, P2PNetwork};
+use crate::network::discovery::LanDiscovery;
+
+/// How long to wait before dialing unreachable bootstrap peers again.
+const BOOTSTRAP_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// How often the DHT sweeps, republishes and re-replicates its records.
+const DHT_MAINTENANCE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct DuxNetNode {
     pub node_id: NodeId,
+    pub config: NodeConfig,
     pub did_manager: DIDManager,
     pub dht: DHT,
+    pub did_resolver: DidResolver,
     pub reputation_system: ReputationSystem,
     pub escrow_manager: EscrowManager,
     pub task_engine: TaskEngine,
     pub community_fund_manager: Arc<CommunityFundManager>,
-    pub messaging_system: Arc<MessagingSystem>,
     pub network: Arc<P2PNetwork>,
+    pub dispatcher: MessageDispatcher,
     pub wallet: Arc<RwLock<crate::wallet::Wallet>>,
+    /// Every identity the node acts as, starting with `did_manager`'s.
+    pub identities: IdentityRegistry,
     pub is_running: Arc<RwLock<bool>>,
+    data_dir: Option<DataDir>,
+    background_tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
 }
 
 impl DuxNetNode {
-    pub async fn new(port: u16) -> Result<Self> {
+    pub async fn with_config(config: NodeConfig) -> Result<Self> {
+        let port = config.p2p_port;
         let node_id = NodeId(uuid::Uuid::new_v4().to_string());
-        let endpoints = vec![format!("tcp://127.0.0.1:{}", port)];
+        let endpoints = vec![config.advertised_endpoint.clone().unwrap_or_else(|| format!("tcp://127.0.0.1:{}", port))];
         
-        let did_manager = DIDManager::new(endpoints);
-        let dht = DHT::new(node_id.clone());
-        let reputation_system = ReputationSystem::new();
-        let escrow_manager = EscrowManager::new();
+        let data_dir = config.data_dir.as_ref().map(DataDir::open).transpose()?;
+        let key_material = match &data_dir {
+            Some(data_dir) => Some(load_identity(data_dir, keystore_password(&config)?)?),
+            None => None,
+        };
+        let did_manager = match &key_material {
+            Some((key_material, updates)) => key_material.identity(updates, endpoints)?,
+            None => DIDManager::new(endpoints),
+        };
+        let network = Arc::new(P2PNetwork::new(port, did_manager.clone()).await?);
+        let dht = match &data_dir {
+            Some(data_dir) => DHT::with_store(NodeId(did_manager.did.id.clone()), Box::new(DiskStore::open(data_dir.path("dht.log"))?)),
+            None => DHT::new(NodeId(did_manager.did.id.clone())),
+        };
+        let dht = dht
+            .with_identity(did_manager.clone())
+            .with_quotas(config.dht_quotas.clone())
+            .with_transport(network.clone());
+        let did_resolver = DidResolver::new(dht.clone());
+        network.set_peer_verifier(Arc::new(did_resolver.clone())).await;
+        let (reputation_system, escrow_manager, task_engine, messaging_system) = match &data_dir {
+            Some(data_dir) => {
+                info!("Loading node state from {}", data_dir.root().display());
+                (
+                    ReputationSystem::with_storage(did_resolver.clone(), data_dir.collection("reputation")?),
+                    EscrowManager::with_storage(did_resolver.clone(), data_dir.collection("escrows")?),
+                    TaskEngine::with_storage(data_dir.collection("tasks")?),
+                    MessagingSystem::with_storage(did_manager.clone(), data_dir.collection("messages")?),
+                )
+            }
+            None => (
+                ReputationSystem::new(did_resolver.clone()),
+                EscrowManager::new(did_resolver.clone()),
+                TaskEngine::new(),
+                MessagingSystem::new(did_manager.clone()),
+            ),
+        };
+        let mut reputation_scoring = config.reputation_scoring.clone();
+        if let ScoringStrategy::EigenTrust(eigentrust) = &mut reputation_scoring {
+            eigentrust.pre_trusted.push(did_manager.did.id.clone());
+        }
         let community_fund_manager = Arc::new(CommunityFundManager::new(Arc::new(dht.clone())));
-        let task_engine = TaskEngine::new().with_community_fund_manager(community_fund_manager.clone());
-        let messaging_system = Arc::new(MessagingSystem::new(did_manager.clone()));
-        let network = Arc::new(P2PNetwork::new(port).await?);
-        let wallet = Arc::new(RwLock::new(crate::wallet::Wallet::new(did_manager.did.id.clone())?));
+        community_fund_manager.load_all_fund_states().await?;
+        let task_engine = task_engine.with_community_fund_manager(community_fund_manager.clone());
+        let reputation_system = reputation_system
+            .with_strategy(reputation_scoring)
+            .with_interactions(Arc::new(LocalInteractions::new(escrow_manager.clone(), task_engine.clone(), did_resolver.clone())))
+            .with_unbacked_policy(config.unbacked_attestations);
+        reputation_system.rescore().await;
+        let messaging_system = Arc::new(messaging_system);
+        let wallet = match key_material.as_ref().and_then(|(key_material, _)| key_material.mnemonic()) {
+            Some(mnemonic) => crate::wallet::Wallet::from_mnemonic(mnemonic)?,
+            None => crate::wallet::Wallet::new(did_manager.did.id.clone())?,
+        };
+        let wallet = Arc::new(RwLock::new(wallet));
+        let primary = LocalIdentity {
+            did_manager: did_manager.clone(),
+            wallet: wallet.clone(),
+            messaging: messaging_system.clone(),
+            label: PRIMARY_LABEL.to_string(),
+            created_at: did_manager.did.created_at,
+            archived: false,
+        };
+        let identities = match &data_dir {
+            Some(data_dir) => IdentityRegistry::with_storage(primary, data_dir, keystore_password(&config)?.clone())?,
+            None => IdentityRegistry::new(primary),
+        };
+        let identities = match key_material.as_ref().and_then(|(key_material, _)| key_material.mnemonic()) {
+            Some(mnemonic) => identities.with_mnemonic(mnemonic.clone()),
+            None => identities,
+        };
+        let dispatcher = MessageDispatcher::new(
+            did_manager.did.id.clone(),
+            dht.clone(),
+            task_engine.clone(),
+            escrow_manager.clone(),
+            messaging_system.clone(),
+            reputation_system.clone(),
+            did_resolver.clone(),
+        )
+        .with_identities(identities.clone());
         let is_running = Arc::new(RwLock::new(false));
         
         Ok(DuxNetNode {
             node_id,
+            config,
             did_manager,
             dht,
+            did_resolver,
             reputation_system,
             escrow_manager,
             task_engine,
             community_fund_manager,
-            messaging_system,
             network,
+            dispatcher,
             wallet,
+            identities,
             is_running,
+            data_dir,
+            background_tasks: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
@@ -80,13 +197,115 @@ impl DuxNetNode {
             *running = true;
         }
         
+        self.start_peer_discovery().await?;
+        self.start_dht_maintenance().await;
+
+        if let Err(e) = self.dht.store_active_did(&self.did_manager.did.id).await {
+            warn!("Failed to announce our DID in the DHT: {}", e);
+        }
+        if let Err(e) = self.dht.publish_did_document(&self.did_manager.document()).await {
+            warn!("Failed to publish our DID document: {}", e);
+        }
+        for identity in self.identities.list().await {
+            if identity.did() != self.identities.primary_did() && !identity.archived {
+                self.publish_identity(&identity).await;
+            }
+        }
+        
         // Start the main event loop
         self.event_loop().await?;
         
         Ok(())
     }
 
+    /// Dials the configured bootstrap peers and, when enabled, starts LAN discovery.
+    /// Every peer reached this way is added to the DHT.
+    async fn start_peer_discovery(&self) -> Result<()> {
+        let mut tasks = self.background_tasks.lock().await;
+
+        if !self.config.bootstrap_peers.is_empty() {
+            let node = self.clone();
+            tasks.push(tokio::spawn(async move {
+                while *node.is_running.read().await {
+                    for endpoint in &node.config.bootstrap_peers {
+                        node.dial_peer(endpoint).await;
+                    }
+                    tokio::time::sleep(BOOTSTRAP_RETRY_INTERVAL).await;
+                }
+            }));
+        }
+
+        if self.config.lan_discovery {
+            let p2p_port = match self.network.local_addr().await {
+                Some(address) => address.port(),
+                None => self.config.p2p_port,
+            };
+            let discovery = LanDiscovery::bind(self.config.discovery_port, self.did_manager.did.id.clone(), p2p_port)?;
+            let (discovered_tx, mut discovered_rx) = mpsc::channel(64);
+            tasks.push(tokio::spawn(discovery.run(discovered_tx)));
+
+            let node = self.clone();
+            tasks.push(tokio::spawn(async move {
+                while let Some(peer) = discovered_rx.recv().await {
+                    if !node.network.get_peers().await.contains(&peer.did) {
+                        node.dial_peer(&peer.endpoint).await;
+                    }
+                }
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the DHT healthy in the background. Owned records are republished
+    /// when they would expire before the pass after next. Reputation is
+    /// rescored on the same tick.
+    async fn start_dht_maintenance(&self) {
+        let node = self.clone();
+        self.background_tasks.lock().await.push(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(DHT_MAINTENANCE_INTERVAL);
+            ticker.tick().await;
+            while *node.is_running.read().await {
+                ticker.tick().await;
+                match node.dht.run_maintenance(2 * DHT_MAINTENANCE_INTERVAL.as_secs()).await {
+                    Ok(report) => debug!("DHT maintenance: {:?}", report),
+                    Err(e) => warn!("DHT maintenance failed: {}", e),
+                }
+                for identity in node.identities.list().await {
+                    if identity.did() == node.identities.primary_did() || identity.archived {
+                        continue;
+                    }
+                    let dht = node.identity_dht(&identity);
+                    if let Err(e) = dht.republish_owned(2 * DHT_MAINTENANCE_INTERVAL.as_secs()).await {
+                        warn!("Failed to republish the records of {}: {}", identity.did(), e);
+                    }
+                }
+                node.reputation_system.rescore().await;
+            }
+        }));
+    }
+
+    async fn dial_peer(&self, endpoint: &str) {
+        match self.network.connect(endpoint).await {
+            Ok(peer_id) => self.observe_peer(&peer_id).await,
+            Err(e) => debug!("Could not reach peer {}: {}", endpoint, e),
+        }
+    }
+
+    /// Adds a peer we just heard from to the DHT routing table, with its endpoint
+    /// when it accepts connections.
+    async fn observe_peer(&self, peer_id: &str) {
+        let result = match self.network.peer_contact(peer_id).await {
+            Some(contact) => self.dht.add_contact(contact).await,
+            None => self.dht.add_peer(peer_id.to_string()).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to add peer {} to the DHT: {}", peer_id, e);
+        }
+    }
+
     async fn event_loop(&self) -> Result<()> {
+        let mut ticker = tokio::time::interval(tokio::time::Duration::from_millis(100));
         loop {
             // Check if we should stop
             {
@@ -96,23 +315,47 @@ impl DuxNetNode {
                 }
             }
             
-            // Process network events
-            if let Err(e) = self.network.process_events().await {
-                error!("Network event processing error: {}", e);
-            }
-            
-            // Process pending tasks
-            if let Err(e) = self.task_engine.process_pending_tasks().await {
-                error!("Task processing error: {}", e);
+            tokio::select! {
+                // Handle network events as they arrive so peers waiting on a
+                // request do not also wait for the next tick
+                event = self.network.next_event() => {
+                    if let Some(event) = event {
+                        self.handle_network_event(event).await;
+                    }
+                }
+                _ = ticker.tick() => {
+                    // Process pending tasks
+                    if let Err(e) = self.task_engine.process_pending_tasks().await {
+                        error!("Task processing error: {}", e);
+                    }
+                }
             }
-            
-            // Sleep briefly to prevent busy waiting
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
         }
         
         Ok(())
     }
 
+    async fn handle_network_event(&self, event: InboundMessage) {
+        debug!("Received {:?} from {}", event.message, event.peer_id);
+        self.observe_peer(&event.peer_id).await;
+
+        let reply = match self.dispatcher.dispatch(&event.peer_id, event.message).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to handle message from {}: {}", event.peer_id, e);
+                return;
+            }
+        };
+        let sent = match event.request_id {
+            Some(request_id) => self.network.respond(&event.peer_id, request_id, reply).await,
+            None => self.network.send_to(&event.peer_id, reply).await,
+        };
+        if let Err(e) = sent {
+            warn!("Failed to reply to {}: {}", event.peer_id, e);
+        }
+    }
+
     pub async fn stop(&self) -> Result<()> {
         info!("Stopping DuxNet node: {}", self.node_id.0);
         
@@ -122,6 +365,10 @@ impl DuxNetNode {
             *running = false;
         }
         
+        for task in self.background_tasks.lock().await.drain(..) {
+            task.abort();
+        }
+        
         // Stop the network
         self.network.stop().await?;
         
@@ -130,41 +377,71 @@ impl DuxNetNode {
 
     // Service management
     pub async fn register_service(&self, name: String, description: String, 
-                                  price: u64) -> Result<ServiceId> {
+                                  price: u64, tags: Vec<String>) -> Result<ServiceId> {
+        let primary = self.identities.select(None).await?;
+        self.register_service_with_credentials(&primary, name, description, price, tags, Vec::new()).await
+    }
+
+    /// Registers a service `identity` provides, presenting `credentials`, each
+    /// of which must be a valid credential issued to that identity.
+    pub async fn register_service_with_credentials(&self, identity: &LocalIdentity, name: String, description: String, price: u64,
+                                                   tags: Vec<String>, credentials: Vec<VerifiableCredential>) -> Result<ServiceId> {
+        for credential in &credentials {
+            if credential.subject != identity.did() {
+                return Err(anyhow::anyhow!("Credential {} was issued to {}, not {}", credential.id, credential.subject, identity.did()));
+            }
+            self.did_resolver.verify_credential(credential).await?;
+        }
         let service_id = ServiceId(uuid::Uuid::new_v4().to_string());
         let service = ServiceMetadata {
             id: service_id.clone(),
-            provider_did: self.did_manager.did.id.clone(),
+            provider_did: identity.did().to_string(),
             name,
             description,
-            endpoint: self.did_manager.did.endpoints[0].clone(),
+            endpoint: identity.did_manager.did.endpoints[0].clone(),
             price,
-            reputation_score: self.reputation_system.get_reputation(&self.did_manager.did.id).await,
+            reputation_score: self.reputation_system.get_reputation(identity.did()).await,
             last_updated: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            tags,
+            credentials,
         };
         
-        self.dht.announce_service(&service).await?;
+        let record = self.identity_dht(identity).announce_service(&service).await?;
+        if let Err(e) = self.network.publish_message("services", GossipPayload::ServiceAnnouncement(record)).await {
+            warn!("Failed to gossip service {}: {}", service_id.0, e);
+        }
         info!("Registered service: {}", service_id.0);
         Ok(service_id)
     }
 
-    pub async fn find_services(&self, query: &str) -> Vec<ServiceMetadata> {
-        self.dht.find_services(query).await
+    pub async fn search_services(&self, query: &str, sort: ServiceSort, limit: Option<usize>) -> Vec<ServiceMetadata> {
+        self.dht.search_services(query, sort, limit).await
     }
 
     // Escrow management
-    pub async fn create_escrow_for_service(&self, service_id: &ServiceId, 
+    /// Opens an escrow in which `identity` buys `service_id` from `seller_did`,
+    /// which must be the DID that published the service.
+    pub async fn create_escrow_for_service(&self, identity: &LocalIdentity, service_id: &ServiceId,
                                            seller_did: String, amount: u64) -> Result<String> {
+        let entry = self
+            .dht
+            .get_entry(&format!("service:{}", service_id.0))
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Service not found: {}", service_id.0))?;
+        let service: ServiceMetadata = serde_json::from_slice(&entry.value)?;
+        if service.provider_did != seller_did {
+            return Err(anyhow::anyhow!("{} does not provide service {}", seller_did, service_id.0));
+        }
         let arbiters = vec![
             "did:duxnet:arbiter1".to_string(),
             "did:duxnet:arbiter2".to_string(),
         ];
         
         let escrow_id = self.escrow_manager.create_escrow(
-            self.did_manager.did.id.clone(),
+            identity.did().to_string(),
             seller_did,
             arbiters,
             amount
@@ -175,13 +452,14 @@ impl DuxNetNode {
     }
 
     // Task management
-    pub async fn submit_task(&self, service_id: ServiceId, payload: Vec<u8>, 
+    pub async fn submit_task(&self, identity: &LocalIdentity, service_id: ServiceId, payload: Vec<u8>,
                              requirements: TaskRequirements) -> Result<TaskId> {
         let task_id = TaskId(uuid::Uuid::new_v4().to_string());
         let task = Task {
             id: task_id.clone(),
             escrow_id: "".to_string(), // Will be set when escrow is created
             service_id,
+            submitter_did: identity.did().to_string(),
             payload,
             requirements,
             created_at: std::time::SystemTime::now()
@@ -191,7 +469,7 @@ impl DuxNetNode {
         };
         
         self.task_engine.submit_task(task).await?;
-        info!("Submitted task: {}", task_id.0);
+        info!("Submitted task {} as {}", task_id.0, identity.did());
         Ok(task_id)
     }
 
@@ -201,7 +479,10 @@ impl DuxNetNode {
     }
 
     pub async fn add_reputation_attestation(&self, attestation: ReputationAttestation) -> Result<()> {
-        self.reputation_system.add_attestation(attestation).await?;
+        self.reputation_system.add_attestation(attestation.clone()).await?;
+        if let Err(e) = self.network.publish_message("reputation", GossipPayload::ReputationAttestation(attestation)).await {
+            warn!("Failed to gossip reputation attestation: {}", e);
+        }
         Ok(())
     }
 
@@ -221,6 +502,113 @@ impl DuxNetNode {
         self.dht.get_aoi_key(&service_id).await
     }
 
+    // Verifiable credentials
+    pub fn issue_credential(&self, subject: &str, credential_type: &str, claims: BTreeMap<String, String>,
+                            expires_at: Option<u64>) -> VerifiableCredential {
+        self.did_manager.issue_credential(subject, credential_type, claims, expires_at)
+    }
+
+    /// Adds `credential_id` to this node's revocation list and republishes it.
+    pub async fn revoke_credential(&self, credential_id: &str) -> Result<()> {
+        let mut revoked = self
+            .dht
+            .get_revocation_list(&self.did_manager.did.id)
+            .await?
+            .map(|list| list.revoked)
+            .unwrap_or_default();
+        revoked.insert(credential_id.to_string());
+        self.dht.publish_revocation_list(&self.did_manager.sign_revocation_list(revoked)).await?;
+        info!("Revoked credential {}", credential_id);
+        Ok(())
+    }
+
+    // Identity keystore
+    fn keystore_path(&self) -> Result<std::path::PathBuf> {
+        let path = self
+            .data_dir
+            .as_ref()
+            .map(|data_dir| data_dir.path(KEYSTORE_FILE))
+            .filter(|path| path.exists());
+        path.ok_or_else(|| anyhow::anyhow!("This node has no keystore; start it with a data directory and a keystore password"))
+    }
+
+    /// Re-encrypts the node's keystore under `new_password`.
+    pub async fn change_keystore_password(&self, old_password: &Password, new_password: &Password) -> Result<()> {
+        let path = self.keystore_path()?;
+        let keystore = Keystore::load(&path)?.change_password(old_password, new_password)?;
+        keystore.save(&path)?;
+        info!("Changed the keystore password for {}", keystore.did);
+        Ok(())
+    }
+
+    /// Moves the node's DID to a new key, revoking the current one if it may be
+    /// compromised. The keystore stores the new key with the signed update; the
+    /// node signs with it, and publishes its updated DID document, from its
+    /// next start.
+    pub async fn rotate_key(&self, password: &Password, revoke_current: bool) -> Result<DidUpdate> {
+        let path = self.keystore_path()?;
+        let (keystore, update) = Keystore::load(&path)?.rotate_key(password, revoke_current)?;
+        keystore.save(&path)?;
+        info!("Rotated the key of {}; the node uses it from its next start", keystore.did);
+        Ok(update)
+    }
+
+    /// The encrypted keystore, for backing up the node's identity.
+    pub async fn export_keystore(&self) -> Result<Keystore> {
+        Keystore::load(&self.keystore_path()?)
+    }
+
+    /// The recovery phrase the node's identity and wallet keys are derived from.
+    /// Nodes whose keystore predates recovery phrases hold a bare key and have none.
+    pub async fn recovery_phrase(&self, password: &Password) -> Result<String> {
+        let keystore = Keystore::load(&self.keystore_path()?)?;
+        match keystore.unlock(password)?.mnemonic() {
+            Some(mnemonic) => Ok(mnemonic.phrase()),
+            None => Err(anyhow::anyhow!("The keystore for {} holds a bare key, not a recovery phrase", keystore.did)),
+        }
+    }
+
+    // Local identities
+    pub async fn create_identity(&self, label: String) -> Result<LocalIdentity> {
+        let identity = self.identities.create(label).await?;
+        if *self.is_running.read().await {
+            self.publish_identity(&identity).await;
+        }
+        Ok(identity)
+    }
+
+    pub async fn list_identities(&self) -> Vec<IdentitySummary> {
+        let mut summaries = Vec::new();
+        for identity in self.identities.list().await {
+            summaries.push(IdentitySummary {
+                primary: identity.did() == self.identities.primary_did(),
+                reputation: self.reputation_system.get_reputation(identity.did()).await,
+                did: identity.did().to_string(),
+                label: identity.label,
+                created_at: identity.created_at,
+                archived: identity.archived,
+            });
+        }
+        summaries
+    }
+
+    pub async fn archive_identity(&self, did: &str) -> Result<()> {
+        self.identities.archive(did).await
+    }
+
+    /// The DHT as seen by `identity`: records published through it are signed
+    /// with that identity's key.
+    fn identity_dht(&self, identity: &LocalIdentity) -> DHT {
+        self.dht.clone().with_identity(identity.did_manager.clone())
+    }
+
+    /// Publishes an identity's DID document so peers can verify what it signs.
+    async fn publish_identity(&self, identity: &LocalIdentity) {
+        if let Err(e) = self.identity_dht(identity).publish_did_document(&identity.did_manager.document()).await {
+            warn!("Failed to publish the DID document of {}: {}", identity.did(), e);
+        }
+    }
+
     // Community Fund Management
     pub async fn get_community_fund_stats(&self) -> Result<CommunityFundStats> {
         self.community_fund_manager.get_stats().await
@@ -237,4 +625,224 @@ impl DuxNetNode {
     pub async fn add_tax_to_community_fund(&self, currency: crate::wallet::Currency, tax_amount: u64) -> Result<()> {
         self.community_fund_manager.add_tax_to_fund(currency, tax_amount).await
     }
-} 
\ No newline at end of file
+}
+
+const KEYSTORE_FILE: &str = "identity.keystore";
+/// Unencrypted signing key left by nodes that ran without a keystore password.
+const PLAIN_KEY_FILE: &str = "identity.key";
+
+/// The password the keys in `config.data_dir` are encrypted under. Keys are
+/// never written there unencrypted, so a data directory needs one.
+fn keystore_password(config: &NodeConfig) -> Result<&Password> {
+    config.keystore_password.as_ref().ok_or_else(|| {
+        anyhow::anyhow!(
+            "A data directory needs a keystore password (DUXNET_KEYSTORE_PASSWORD): the node's keys are only \
+             stored encrypted. Run without a data directory to keep them in memory instead"
+        )
+    })
+}
+
+/// The node's keys live in the data directory so its DID, and everything
+/// addressed to it, stays the same across restarts. They are kept in an
+/// encrypted keystore: a new node gets a recovery phrase its DID and wallet
+/// keys are derived from, and a key left unencrypted by an older node is moved in.
+fn load_identity(data_dir: &DataDir, password: &Password) -> Result<(KeyMaterial, Vec<DidUpdate>)> {
+    let keystore_path = data_dir.path(KEYSTORE_FILE);
+    let plain_path = data_dir.path(PLAIN_KEY_FILE);
+
+    if keystore_path.exists() {
+        let keystore = Keystore::load(&keystore_path)?;
+        let key_material = keystore.unlock(password)?;
+        info!("Unlocked node identity {}", keystore.did);
+        return Ok((key_material, keystore.updates));
+    }
+
+    if plain_path.exists() {
+        let did_manager = DIDManager::import_private_key(std::fs::read(&plain_path)?, Vec::new())?;
+        Keystore::encrypt(&did_manager, password)?.save(&keystore_path)?;
+        std::fs::remove_file(&plain_path)?;
+        info!("Moved node identity {} into an encrypted keystore", did_manager.did.id);
+        return Ok((KeyMaterial::SigningKey(did_manager.export_private_key()), Vec::new()));
+    }
+
+    let mnemonic = Mnemonic::generate();
+    let keystore = Keystore::encrypt_mnemonic(&mnemonic, password)?;
+    keystore.save(&keystore_path)?;
+    info!("Created node identity {} from a new recovery phrase in {}", keystore.did, keystore_path.display());
+    Ok((KeyMaterial::Mnemonic(mnemonic), Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::signing::EscrowApproval;
+
+    #[tokio::test]
+    async fn test_node_state_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = NodeConfig {
+            p2p_port: 0,
+            data_dir: Some(dir.path().to_path_buf()),
+            keystore_password: Some(Password::new("hunter2")),
+            ..NodeConfig::default()
+        };
+        // Keys are never kept unencrypted.
+        let unprotected = NodeConfig { keystore_password: None, ..config.clone() };
+        assert!(DuxNetNode::with_config(unprotected).await.is_err());
+
+        let node = DuxNetNode::with_config(config.clone()).await.unwrap();
+        let seller = DIDManager::new(vec![]);
+        node.dht.publish_did_document(&node.did_manager.document()).await.unwrap();
+        node.dht.clone().with_identity(seller.clone()).publish_did_document(&seller.document()).await.unwrap();
+        let escrow_id = node
+            .escrow_manager
+            .create_escrow(node.did_manager.did.id.clone(), seller.did.id.clone(), vec![], 25)
+            .await
+            .unwrap();
+        for party in [&node.did_manager, &seller] {
+            let signature = party.sign(&EscrowApproval { escrow_id: &escrow_id, state: &EscrowState::Funded });
+            node.escrow_manager.add_signature(&escrow_id, &party.did.id, signature).await.unwrap();
+        }
+        let attestation = node.did_manager.create_attestation("did:duxnet:seller".to_string(), 4.0, "task_completed".to_string());
+        node.reputation_system.add_attestation(attestation).await.unwrap();
+        let request = MessageRequest {
+            to_did: "did:duxnet:peer".to_string(),
+            content: "hello".to_string(),
+            message_type: MessageType::Text,
+            reply_to: None,
+        };
+        node.identities.select(None).await.unwrap().messaging.send_message(request).await.unwrap();
+        let did = node.did_manager.did.id.clone();
+        let rotation = node.rotate_key(&Password::new("hunter2"), false).await.unwrap();
+        drop(node);
+
+        let node = DuxNetNode::with_config(config).await.unwrap();
+        assert_eq!(node.did_manager.did.id, did);
+        assert_eq!(Some(node.did_manager.get_public_key()), rotation.new_key);
+        assert_eq!(node.did_manager.updates, vec![rotation]);
+        let contract = node.escrow_manager.get_contract(&escrow_id).await.unwrap();
+        assert!(matches!(contract.state, EscrowState::Funded));
+        assert_eq!(contract.signatures.len(), 2);
+        assert_eq!(node.reputation_system.get_reputation("did:duxnet:seller").await, 4.0);
+        let primary = node.identities.select(None).await.unwrap();
+        assert_eq!(primary.messaging.get_messages("did:duxnet:peer").await.len(), 1);
+        assert_eq!(primary.messaging.get_conversations().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_identity_moves_into_an_encrypted_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = DataDir::open(dir.path()).unwrap();
+        let did = |(material, updates): (KeyMaterial, Vec<DidUpdate>)| material.identity(&updates, vec![]).unwrap().did.id;
+        let plain = DIDManager::new(vec![]);
+        storage::write_atomically(&data_dir.path(PLAIN_KEY_FILE), &plain.export_private_key()).unwrap();
+
+        let password = Password::new("hunter2");
+        let migrated = load_identity(&data_dir, &password).unwrap();
+        assert!(migrated.0.mnemonic().is_none());
+        assert_eq!(did(migrated), plain.did.id);
+        assert!(!data_dir.path(PLAIN_KEY_FILE).exists());
+
+        assert!(load_identity(&data_dir, &Password::new("wrong")).is_err());
+        assert_eq!(did(load_identity(&data_dir, &password).unwrap()), plain.did.id);
+    }
+
+    #[tokio::test]
+    async fn test_new_node_derives_identity_and_wallet_from_one_phrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = Password::new("hunter2");
+        let config = NodeConfig {
+            p2p_port: 0,
+            data_dir: Some(dir.path().to_path_buf()),
+            keystore_password: Some(password.clone()),
+            ..NodeConfig::default()
+        };
+        let node = DuxNetNode::with_config(config).await.unwrap();
+        let phrase = node.recovery_phrase(&password).await.unwrap();
+
+        let restored = crate::wallet::Wallet::restore_wallet(&phrase).unwrap();
+        let wallet = node.wallet.read().await;
+        assert_eq!(restored.did, node.did_manager.did.id);
+        assert_eq!(restored.get_all_addresses(), wallet.get_all_addresses());
+        let identity = DIDManager::from_mnemonic(&Mnemonic::parse(&phrase).unwrap(), vec![]);
+        assert_eq!(identity.did.id, node.did_manager.did.id);
+    }
+
+    #[tokio::test]
+    async fn test_identities_provide_and_buy_as_themselves() {
+        let node = DuxNetNode::with_config(NodeConfig { p2p_port: 0, ..NodeConfig::default() }).await.unwrap();
+        let provider = node.create_identity("provider".to_string()).await.unwrap();
+        let buyer = node.create_identity("buyer".to_string()).await.unwrap();
+
+        let service_id = node
+            .register_service_with_credentials(&provider, "GPU".to_string(), "Renders frames".to_string(), 10, vec![], vec![])
+            .await
+            .unwrap();
+        let record = node.dht.get_entry_local(&format!("service:{}", service_id.0)).await.unwrap();
+        assert_eq!(record.publisher_did, provider.did());
+
+        let escrow_id = node.create_escrow_for_service(&buyer, &service_id, provider.did().to_string(), 10).await.unwrap();
+        assert_eq!(node.escrow_manager.get_contract(&escrow_id).await.unwrap().buyer_did, buyer.did());
+        assert!(node.create_escrow_for_service(&buyer, &service_id, buyer.did().to_string(), 10).await.is_err());
+        let requirements = TaskRequirements { cpu_cores: 1, memory_mb: 64, timeout_seconds: 5 };
+        node.submit_task(&buyer, service_id, vec![], requirements).await.unwrap();
+        assert_eq!(node.task_engine.get_pending_tasks().await[0].submitter_did, buyer.did());
+    }
+
+    #[tokio::test]
+    async fn test_identities_keep_their_own_inbox_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = NodeConfig {
+            p2p_port: 0,
+            data_dir: Some(dir.path().to_path_buf()),
+            keystore_password: Some(Password::new("hunter2")),
+            ..NodeConfig::default()
+        };
+
+        let node = DuxNetNode::with_config(config.clone()).await.unwrap();
+        let buyer = node.create_identity("buyer".to_string()).await.unwrap();
+        let provider = node.create_identity("provider".to_string()).await.unwrap();
+        assert_ne!(buyer.did(), node.did_manager.did.id);
+        assert_ne!(buyer.wallet.read().await.get_all_addresses(), node.wallet.read().await.get_all_addresses());
+
+        let peer = DIDManager::new(vec![]);
+        node.dht.clone().with_identity(peer.clone()).publish_did_document(&peer.document()).await.unwrap();
+        let mut message = Message {
+            id: "msg-1".to_string(),
+            from_did: peer.did.id.clone(),
+            to_did: buyer.did().to_string(),
+            content: "hello".to_string(),
+            message_type: MessageType::Text,
+            timestamp: 1,
+            signature: vec![],
+            is_read: false,
+            reply_to: None,
+        };
+        message.signature = peer.sign(&message);
+        node.dispatcher.dispatch(&peer.did.id, NetworkMessage::DirectMessage(message)).await.unwrap();
+        assert_eq!(buyer.messaging.get_messages(&peer.did.id).await.len(), 1);
+        assert!(node.identities.select(None).await.unwrap().messaging.get_messages(&peer.did.id).await.is_empty());
+
+        node.archive_identity(provider.did()).await.unwrap();
+        assert!(node.archive_identity(&node.did_manager.did.id).await.is_err());
+        drop(node);
+
+        let node = DuxNetNode::with_config(config).await.unwrap();
+        let identities = node.list_identities().await;
+        let labels: Vec<(&str, bool)> = identities.iter().map(|i| (i.label.as_str(), i.archived)).collect();
+        assert_eq!(labels, vec![(PRIMARY_LABEL, false), ("buyer", false), ("provider", true)]);
+        assert!(identities[0].primary);
+
+        let buyer = node.identities.select(Some(buyer.did())).await.unwrap();
+        assert_eq!(buyer.messaging.get_messages(&peer.did.id).await.len(), 1);
+        assert!(node.identities.select(Some(provider.did())).await.is_err());
+        assert_eq!(node.identities.select(None).await.unwrap().did(), node.did_manager.did.id);
+
+        // Each identity's key comes from the recovery phrase, at its own index.
+        let phrase = Mnemonic::parse(&node.recovery_phrase(&Password::new("hunter2")).await.unwrap()).unwrap();
+        let seller = node.create_identity("seller".to_string()).await.unwrap();
+        for (identity, index) in [(&buyer, 0), (&seller, 2)] {
+            assert_eq!(identity.did_manager.export_private_key(), phrase.derive_key(&mnemonic::persona_path(index)).to_vec());
+        }
+    }
+}
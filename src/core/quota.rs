@@ -0,0 +1,279 @@
+//! Limits on how much of a node's DHT storage any one publisher may take up.
+
+use crate::core::dht::DHTEntry;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+const KIB: usize = 1024;
+const MIB: usize = 1024 * KIB;
+const DAY: u64 = 86400;
+
+/// Bounds on the records one publisher holds within some scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaLimits {
+    pub max_entries: usize,
+    /// Key and value bytes over all of the publisher's records.
+    pub max_bytes: usize,
+    pub max_value_size: usize,
+    pub max_ttl: u64,
+}
+
+/// Quotas applied to every record a DHT stores, its own included. `per_publisher`
+/// covers all of a publisher's records; `per_namespace` adds tighter limits on
+/// its records under a namespace, the part of a key up to and including the
+/// first `:` (e.g. `service:`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaPolicy {
+    pub per_publisher: QuotaLimits,
+    pub per_namespace: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaPolicy {
+    pub fn with_namespace(mut self, namespace: &str, limits: QuotaLimits) -> Self {
+        self.per_namespace.insert(namespace.to_string(), limits);
+        self
+    }
+}
+
+impl Default for QuotaPolicy {
+    fn default() -> Self {
+        let per_publisher = QuotaLimits { max_entries: 1024, max_bytes: 8 * MIB, max_value_size: 64 * KIB, max_ttl: 7 * DAY };
+        QuotaPolicy { per_publisher, per_namespace: HashMap::new() }
+            .with_namespace("service:", QuotaLimits { max_entries: 64, max_bytes: MIB, max_value_size: 16 * KIB, max_ttl: DAY })
+            .with_namespace("reputation:", QuotaLimits { max_entries: 512, max_bytes: MIB, max_value_size: 4 * KIB, max_ttl: 7 * DAY })
+            .with_namespace("active_did:", QuotaLimits { max_entries: 4, max_bytes: 4 * KIB, max_value_size: KIB, max_ttl: 2 * DAY })
+            .with_namespace("did_doc:", QuotaLimits { max_entries: 4, max_bytes: 64 * KIB, max_value_size: 16 * KIB, max_ttl: 2 * DAY })
+    }
+}
+
+/// Which limits a write ran into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaScope {
+    Publisher,
+    Namespace(String),
+}
+
+impl fmt::Display for QuotaScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaScope::Publisher => write!(f, "all keys"),
+            QuotaScope::Namespace(namespace) => write!(f, "{}*", namespace),
+        }
+    }
+}
+
+/// Why a write was refused for exceeding a quota. `DHT::store_entry_local`
+/// returns it inside its `anyhow::Error`, so callers can downcast to it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuotaError {
+    #[error("DHT record {key} has a {size}-byte value; {scope} allows {limit}")]
+    ValueTooLarge { key: String, scope: QuotaScope, size: usize, limit: usize },
+    #[error("DHT record {key} asks for a {ttl}s TTL; {scope} allows {limit}s")]
+    TtlTooLong { key: String, scope: QuotaScope, ttl: u64, limit: u64 },
+    #[error("{publisher} already holds {limit} DHT records under {scope}")]
+    TooManyEntries { publisher: String, scope: QuotaScope, limit: usize },
+    #[error("{publisher} would hold {bytes} bytes of DHT records under {scope}; the limit is {limit}")]
+    TooManyBytes { publisher: String, scope: QuotaScope, bytes: usize, limit: usize },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl Usage {
+    fn add(&mut self, size: usize) {
+        self.entries += 1;
+        self.bytes += size;
+    }
+
+    /// Saturates, since records put in the store directly were never added.
+    fn subtract(&mut self, size: usize) {
+        self.entries = self.entries.saturating_sub(1);
+        self.bytes = self.bytes.saturating_sub(size);
+    }
+}
+
+/// One publisher's share of the DHT, as reported in `DHTStats`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PublisherUsage {
+    pub publisher_did: String,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// Counts what each publisher holds so writes can be checked against a
+/// `QuotaPolicy` without scanning the store.
+#[derive(Debug, Default)]
+pub struct QuotaTracker {
+    publishers: HashMap<String, Usage>,
+    /// Keyed by publisher, then namespace.
+    namespaces: HashMap<(String, String), Usage>,
+    rejected: u64,
+}
+
+impl QuotaTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks that `entry` fits its publisher's quotas under `policy` once it
+    /// replaces `replaced`, the record currently stored under the same key.
+    pub fn check(&mut self, policy: &QuotaPolicy, entry: &DHTEntry, replaced: Option<&DHTEntry>) -> Result<(), QuotaError> {
+        let mut scopes = vec![(QuotaScope::Publisher, policy.per_publisher, self.publishers.get(&entry.publisher_did))];
+        if let Some(namespace) = namespace(&entry.key) {
+            if let Some(limits) = policy.per_namespace.get(namespace) {
+                let usage = self.namespaces.get(&(entry.publisher_did.clone(), namespace.to_string()));
+                scopes.push((QuotaScope::Namespace(namespace.to_string()), *limits, usage));
+            }
+        }
+        // A replaced record of the same publisher frees its share first.
+        let freed = replaced.filter(|old| old.publisher_did == entry.publisher_did).map(record_size);
+
+        let result = scopes.into_iter().try_for_each(|(scope, limits, usage)| {
+            check_limits(entry, scope, &limits, usage.copied().unwrap_or_default(), freed)
+        });
+        if result.is_err() {
+            self.rejected += 1;
+        }
+        result
+    }
+
+    pub fn add(&mut self, entry: &DHTEntry) {
+        let size = record_size(entry);
+        self.publishers.entry(entry.publisher_did.clone()).or_default().add(size);
+        if let Some(namespace) = namespace(&entry.key) {
+            self.namespaces
+                .entry((entry.publisher_did.clone(), namespace.to_string()))
+                .or_default()
+                .add(size);
+        }
+    }
+
+    pub fn remove(&mut self, entry: &DHTEntry) {
+        let size = record_size(entry);
+        if let Some(usage) = self.publishers.get_mut(&entry.publisher_did) {
+            usage.subtract(size);
+            if usage.entries == 0 {
+                self.publishers.remove(&entry.publisher_did);
+            }
+        }
+        if let Some(namespace) = namespace(&entry.key) {
+            let key = (entry.publisher_did.clone(), namespace.to_string());
+            if let Some(usage) = self.namespaces.get_mut(&key) {
+                usage.subtract(size);
+                if usage.entries == 0 {
+                    self.namespaces.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Writes refused so far.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Every publisher's usage, largest first.
+    pub fn usage(&self) -> Vec<PublisherUsage> {
+        let mut usage: Vec<PublisherUsage> = self
+            .publishers
+            .iter()
+            .map(|(did, usage)| PublisherUsage { publisher_did: did.clone(), entries: usage.entries, bytes: usage.bytes })
+            .collect();
+        usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.publisher_did.cmp(&b.publisher_did)));
+        usage
+    }
+}
+
+fn check_limits(entry: &DHTEntry, scope: QuotaScope, limits: &QuotaLimits, usage: Usage, freed: Option<usize>) -> Result<(), QuotaError> {
+    if entry.value.len() > limits.max_value_size {
+        return Err(QuotaError::ValueTooLarge {
+            key: entry.key.clone(),
+            scope,
+            size: entry.value.len(),
+            limit: limits.max_value_size,
+        });
+    }
+    if entry.ttl > limits.max_ttl {
+        return Err(QuotaError::TtlTooLong { key: entry.key.clone(), scope, ttl: entry.ttl, limit: limits.max_ttl });
+    }
+
+    let (entries, bytes) = match freed {
+        Some(size) => (usage.entries.saturating_sub(1), usage.bytes.saturating_sub(size)),
+        None => (usage.entries, usage.bytes),
+    };
+    if entries >= limits.max_entries {
+        return Err(QuotaError::TooManyEntries { publisher: entry.publisher_did.clone(), scope, limit: limits.max_entries });
+    }
+    let bytes = bytes.saturating_add(record_size(entry));
+    if bytes > limits.max_bytes {
+        return Err(QuotaError::TooManyBytes { publisher: entry.publisher_did.clone(), scope, bytes, limit: limits.max_bytes });
+    }
+    Ok(())
+}
+
+fn record_size(entry: &DHTEntry) -> usize {
+    entry.key.len() + entry.value.len()
+}
+
+fn namespace(key: &str) -> Option<&str> {
+    key.find(':').map(|end| &key[..=end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::identity::DIDManager;
+
+    fn entry(publisher: &DIDManager, key: &str, value: &[u8], ttl: u64) -> DHTEntry {
+        DHTEntry::signed(publisher, key.to_string(), value.to_vec(), ttl, 1)
+    }
+
+    #[test]
+    fn test_limits_per_publisher_and_namespace() {
+        let limits = QuotaLimits { max_entries: 3, max_bytes: 40, max_value_size: 16, max_ttl: 60 };
+        let policy = QuotaPolicy { per_publisher: limits, per_namespace: HashMap::new() }
+            .with_namespace("service:", QuotaLimits { max_entries: 1, ..limits });
+        let mut tracker = QuotaTracker::new();
+        let publisher = DIDManager::new(vec![]);
+        let other = DIDManager::new(vec![]);
+
+        let service = entry(&publisher, "service:a", b"v", 60);
+        tracker.check(&policy, &service, None).unwrap();
+        tracker.add(&service);
+        let second = entry(&publisher, "service:b", b"v", 60);
+        assert!(matches!(
+            tracker.check(&policy, &second, None),
+            Err(QuotaError::TooManyEntries { scope: QuotaScope::Namespace(_), limit: 1, .. })
+        ));
+        // Replacing the record it already holds is fine, and other publishers have their own quota.
+        tracker.check(&policy, &entry(&publisher, "service:a", b"v2", 60), Some(&service)).unwrap();
+        tracker.check(&policy, &entry(&other, "service:b", b"v", 60), None).unwrap();
+
+        assert!(matches!(
+            tracker.check(&policy, &entry(&publisher, "aoi:a", &[0; 17], 60), None),
+            Err(QuotaError::ValueTooLarge { scope: QuotaScope::Publisher, size: 17, .. })
+        ));
+        assert!(matches!(
+            tracker.check(&policy, &entry(&publisher, "aoi:a", b"v", 61), None),
+            Err(QuotaError::TtlTooLong { .. })
+        ));
+        tracker.add(&entry(&publisher, "aoi:a", &[0; 16], 60));
+        assert!(matches!(
+            tracker.check(&policy, &entry(&publisher, "aoi:b", &[0; 16], 60), None),
+            Err(QuotaError::TooManyBytes { bytes: 52, limit: 40, .. })
+        ));
+        assert_eq!(tracker.rejected(), 4);
+
+        tracker.remove(&service);
+        tracker.check(&policy, &second, None).unwrap();
+        assert_eq!(
+            tracker.usage(),
+            vec![PublisherUsage { publisher_did: publisher.did.id.clone(), entries: 1, bytes: 21 }]
+        );
+    }
+}
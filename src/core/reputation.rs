@@ -1,104 +1,342 @@
 use crate::core::data_structures::*;
+use crate::core::did::DidResolver;
+use crate::core::escrow::EscrowManager;
+use crate::core::storage::{Collection, Persisted};
+use crate::core::tasks::TaskEngine;
+use crate::core::trust::{self, EigenTrustConfig};
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::debug;
 
+/// Attestation scores run from `MIN_SCORE` to `MAX_SCORE` inclusive.
+pub const MIN_SCORE: f64 = 0.0;
+pub const MAX_SCORE: f64 = 5.0;
+/// How far ahead of our clock an attestation's timestamp may be.
+pub const MAX_CLOCK_SKEW: u64 = 300;
+
+/// How attestations about a DID are combined into its score.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoringStrategy {
+    /// The time-decayed average of every attestation, whoever made it.
+    Average,
+    /// The time-decayed average, each attestation also weighted by its
+    /// attester's global trust, so unknown attesters count for nothing.
+    EigenTrust(EigenTrustConfig),
+}
+
+impl Default for ScoringStrategy {
+    fn default() -> Self {
+        ScoringStrategy::EigenTrust(EigenTrustConfig::default())
+    }
+}
+
+/// What an attestation counts for when it does not name a settled
+/// interaction its attester and target both took part in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnbackedPolicy {
+    /// The same as a backed attestation.
+    Accept,
+    /// This fraction of a backed attestation.
+    Discount(f64),
+    /// Nothing: it is turned away.
+    Reject,
+}
+
+impl Default for UnbackedPolicy {
+    fn default() -> Self {
+        UnbackedPolicy::Discount(0.25)
+    }
+}
+
+impl UnbackedPolicy {
+    fn weight(&self) -> f64 {
+        match self {
+            UnbackedPolicy::Accept => 1.0,
+            UnbackedPolicy::Discount(fraction) => fraction.clamp(0.0, 1.0),
+            UnbackedPolicy::Reject => 0.0,
+        }
+    }
+}
+
+/// Looks up the escrows and tasks that attestations name.
+#[async_trait::async_trait]
+pub trait InteractionLedger: Send + Sync {
+    /// Everyone who took part in `interaction`, if it exists and has settled.
+    async fn settled_parties(&self, interaction: &InteractionRef) -> Option<Vec<String>>;
+}
+
+/// The escrows and tasks this node holds. An escrow has settled once it is
+/// `Completed` and carries both its buyer's and its seller's signed approval
+/// of that, between the two of them. A task has once it is completed and the
+/// escrow that paid for it has settled with the task's processor as seller,
+/// between the processor and the buyer.
+#[derive(Clone)]
+pub struct LocalInteractions {
+    escrows: EscrowManager,
+    tasks: TaskEngine,
+    /// Resolves the parties' keys to check their approvals against.
+    resolver: DidResolver,
+}
+
+impl LocalInteractions {
+    pub fn new(escrows: EscrowManager, tasks: TaskEngine, resolver: DidResolver) -> Self {
+        LocalInteractions { escrows, tasks, resolver }
+    }
+
+    /// The contract `escrow_id` if both parties have signed off on its completion.
+    async fn settled_escrow(&self, escrow_id: &str) -> Option<EscrowContract> {
+        let contract = self.escrows.get_contract(escrow_id).await?;
+        if contract.state != EscrowState::Completed {
+            return None;
+        }
+        for party in [&contract.buyer_did, &contract.seller_did] {
+            let signature = contract.signatures.get(party)?;
+            if let Err(e) = self.resolver.verify_escrow_signature(escrow_id, &EscrowState::Completed, party, signature).await {
+                debug!("Escrow {} is not settled by {}: {}", escrow_id, party, e);
+                return None;
+            }
+        }
+        Some(contract)
+    }
+}
+
+#[async_trait::async_trait]
+impl InteractionLedger for LocalInteractions {
+    async fn settled_parties(&self, interaction: &InteractionRef) -> Option<Vec<String>> {
+        match interaction {
+            InteractionRef::Escrow(escrow_id) => {
+                let contract = self.settled_escrow(escrow_id).await?;
+                Some(vec![contract.buyer_did, contract.seller_did])
+            }
+            InteractionRef::Task(task_id) => {
+                let result = self.tasks.completed_tasks.read().await.get(task_id).cloned()?;
+                let contract = self.settled_escrow(&result.escrow_id).await?;
+                (contract.seller_did == result.processor_did).then(|| vec![result.processor_did, contract.buyer_did])
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ReputationSystem {
     pub attestations: Arc<RwLock<HashMap<String, Vec<ReputationAttestation>>>>,
     pub scores: Arc<RwLock<HashMap<String, f64>>>,
+    store: Persisted<ReputationAttestation>,
+    /// Resolves attesters' keys to check attestation signatures against.
+    resolver: DidResolver,
+    strategy: ScoringStrategy,
+    interactions: Option<Arc<dyn InteractionLedger>>,
+    unbacked_policy: UnbackedPolicy,
+    /// Storage keys of the attestations found to be backed by a settled
+    /// interaction. Settling is final, so they stay backed; the rest are
+    /// checked again by `rescore`.
+    backed: Arc<RwLock<HashSet<String>>>,
+    /// Set when attestations were added since `scores` was last computed.
+    stale: Arc<AtomicBool>,
+    /// Held by `add_attestation` from its replay check to its insert, so that
+    /// `attestations` need not be locked while the attestation is written out.
+    adding: Arc<Mutex<()>>,
+}
+
+fn storage_key(target_did: &str, attester_did: &str, timestamp: u64) -> String {
+    format!("{}:{}:{}", target_did, attester_did, timestamp)
+}
+
+fn attestation_key(attestation: &ReputationAttestation) -> String {
+    storage_key(&attestation.target_did, &attestation.attester_did, attestation.timestamp)
 }
 
 impl ReputationSystem {
-    pub fn new() -> Self {
+    pub fn new(resolver: DidResolver) -> Self {
         ReputationSystem {
             attestations: Arc::new(RwLock::new(HashMap::new())),
             scores: Arc::new(RwLock::new(HashMap::new())),
+            store: Persisted::memory(),
+            resolver,
+            strategy: ScoringStrategy::default(),
+            interactions: None,
+            unbacked_policy: UnbackedPolicy::default(),
+            backed: Arc::new(RwLock::new(HashSet::new())),
+            stale: Arc::new(AtomicBool::new(false)),
+            adding: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Replaces the default `ScoringStrategy`.
+    pub fn with_strategy(mut self, strategy: ScoringStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Where the escrows and tasks attestations name are looked up. Without
+    /// one no attestation is backed.
+    pub fn with_interactions(mut self, interactions: Arc<dyn InteractionLedger>) -> Self {
+        self.interactions = Some(interactions);
+        self
+    }
+
+    /// Replaces the default `UnbackedPolicy`.
+    pub fn with_unbacked_policy(mut self, policy: UnbackedPolicy) -> Self {
+        self.unbacked_policy = policy;
+        self
+    }
+
+    /// Loads the attestations kept in `collection` and writes every change
+    /// back to it. They were checked when first added. Scores are computed by
+    /// the first `rescore`, once the system is fully configured.
+    pub fn with_storage(resolver: DidResolver, collection: Collection<ReputationAttestation>) -> Self {
+        let mut attestations: HashMap<String, Vec<ReputationAttestation>> = HashMap::new();
+        for attestation in collection.values() {
+            attestations.entry(attestation.target_did.clone()).or_default().push(attestation.clone());
+        }
+        for atts in attestations.values_mut() {
+            atts.sort_by_key(|att| att.timestamp);
+        }
+
+        ReputationSystem {
+            attestations: Arc::new(RwLock::new(attestations)),
+            scores: Arc::new(RwLock::new(HashMap::new())),
+            store: Persisted::new(collection),
+            ..Self::new(resolver)
         }
     }
 
+    /// Adds an attestation once it checks out: its score is in range, it is
+    /// not dated in the future, it is signed by a key the attester's DID
+    /// document held at the time, and the attester has not already attested
+    /// the same target at the same time. Under `UnbackedPolicy::Reject` it
+    /// must also name a settled interaction between attester and target.
     pub async fn add_attestation(&self, attestation: ReputationAttestation) -> Result<()> {
-        let mut attestations = self.attestations.write().await;
-        attestations
-            .entry(attestation.target_did.clone())
-            .or_insert_with(Vec::new)
-            .push(attestation.clone());
-        
-        self.recalculate_score(&attestation.target_did).await;
+        if !(MIN_SCORE..=MAX_SCORE).contains(&attestation.score) {
+            return Err(anyhow::anyhow!(
+                "Attestation score {} is outside {}..={}",
+                attestation.score,
+                MIN_SCORE,
+                MAX_SCORE
+            ));
+        }
+        if attestation.timestamp > get_current_timestamp() + MAX_CLOCK_SKEW {
+            return Err(anyhow::anyhow!("Attestation by {} is dated in the future", attestation.attester_did));
+        }
+        self.resolver
+            .verify_attestation(&attestation)
+            .await
+            .map_err(|e| anyhow::anyhow!("Attestation by {} is not signed by the attester: {}", attestation.attester_did, e))?;
+        let backed = self.is_backed(&attestation).await;
+        if !backed && self.unbacked_policy == UnbackedPolicy::Reject {
+            return Err(anyhow::anyhow!(
+                "Attestation by {} names no settled interaction with {}",
+                attestation.attester_did,
+                attestation.target_did
+            ));
+        }
+
+        {
+            let _adding = self.adding.lock().await;
+            let replayed = self.attestations.read().await.get(&attestation.target_did).is_some_and(|atts| {
+                atts.iter().any(|att| att.attester_did == attestation.attester_did && att.timestamp == attestation.timestamp)
+            });
+            if replayed {
+                return Err(anyhow::anyhow!(
+                    "{} already attested {} at {}",
+                    attestation.attester_did,
+                    attestation.target_did,
+                    attestation.timestamp
+                ));
+            }
+            let key = attestation_key(&attestation);
+            self.store.save(&key, &attestation).await?;
+            if backed {
+                self.backed.write().await.insert(key);
+            }
+            self.attestations
+                .write()
+                .await
+                .entry(attestation.target_did.clone())
+                .or_insert_with(Vec::new)
+                .push(attestation.clone());
+        }
+
+
+        // Scores are brought up to date when next read.
+        self.stale.store(true, Ordering::Release);
         debug!("Added reputation attestation for: {}", attestation.target_did);
         Ok(())
     }
 
     pub async fn get_reputation(&self, did: &str) -> f64 {
+        self.refresh().await;
         let scores = self.scores.read().await;
         scores.get(did).copied().unwrap_or(0.0)
     }
 
-    pub async fn get_attestations(&self, did: &str) -> Vec<ReputationAttestation> {
-        let attestations = self.attestations.read().await;
-        attestations.get(did).cloned().unwrap_or_default()
+    /// Whether `attestation` names a settled interaction that its attester
+    /// and target both took part in.
+    async fn is_backed(&self, attestation: &ReputationAttestation) -> bool {
+        let (Some(interaction), Some(interactions)) = (&attestation.interaction, &self.interactions) else {
+            return false;
+        };
+        if attestation.attester_did == attestation.target_did {
+            return false;
+        }
+        match interactions.settled_parties(interaction).await {
+            Some(parties) => parties.contains(&attestation.attester_did) && parties.contains(&attestation.target_did),
+            None => false,
+        }
     }
 
-    async fn recalculate_score(&self, did: &str) {
-        let attestations = self.attestations.read().await;
-        if let Some(atts) = attestations.get(did) {
-            let now = get_current_timestamp();
-            
-            let mut weighted_sum = 0.0;
-            let mut weight_sum = 0.0;
-            
-            for att in atts {
-                // Apply time decay (older attestations have less weight)
-                let age_days = (now - att.timestamp) / 86400;
-                let decay_factor = 0.95_f64.powi(age_days as i32);
-                let weight = decay_factor;
-                
-                weighted_sum += att.score * weight;
-                weight_sum += weight;
+    /// Rescores every DID: under EigenTrust one attestation can shift the
+    /// trust of everyone downstream of its target. Interactions that were
+    /// unsettled when attested are checked again first. Run periodically;
+    /// reads only recompute scores from the backing already known.
+    pub async fn rescore(&self) {
+        self.stale.store(false, Ordering::Release);
+        let unconfirmed: Vec<ReputationAttestation> = {
+            let attestations = self.attestations.read().await;
+            let backed = self.backed.read().await;
+            attestations
+                .values()
+                .flatten()
+                .filter(|att| att.interaction.is_some() && !backed.contains(&attestation_key(att)))
+                .cloned()
+                .collect()
+        };
+        for attestation in unconfirmed {
+            if self.is_backed(&attestation).await {
+                self.backed.write().await.insert(attestation_key(&attestation));
             }
-            
-            let score = if weight_sum > 0.0 {
-                weighted_sum / weight_sum
-            } else {
-                0.0
-            };
-            
-            let mut scores = self.scores.write().await;
-            scores.insert(did.to_string(), score);
-            
-            debug!("Recalculated reputation score for {}: {}", did, score);
         }
+        self.compute().await;
     }
 
-    pub async fn remove_attestation(&self, target_did: &str, attester_did: &str, timestamp: u64) -> Result<()> {
-        let mut attestations = self.attestations.write().await;
-        if let Some(atts) = attestations.get_mut(target_did) {
-            atts.retain(|att| {
-                !(att.attester_did == attester_did && att.timestamp == timestamp)
-            });
+    /// Recomputes the scores if attestations were added since they were last computed.
+    async fn refresh(&self) {
+        if self.stale.swap(false, Ordering::AcqRel) {
+            self.compute().await;
         }
-        
-        self.recalculate_score(target_did).await;
-        debug!("Removed attestation for: {}", target_did);
-        Ok(())
     }
 
-    pub async fn get_top_nodes(&self, limit: usize) -> Vec<(String, f64)> {
-        let scores = self.scores.read().await;
-        let mut sorted_scores: Vec<(String, f64)> = scores
-            .iter()
-            .map(|(did, score)| (did.clone(), *score))
-            .collect();
-        
-        sorted_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-        sorted_scores.truncate(limit);
-        
-        sorted_scores
+    async fn compute(&self) {
+        let attestations = self.attestations.read().await;
+        let backed = self.backed.read().await;
+        let unbacked_weight = self.unbacked_policy.weight();
+        let backing = |att: &ReputationAttestation| {
+            if backed.contains(&attestation_key(att)) {
+                1.0
+            } else {
+                unbacked_weight
+            }
+        };
+        let scores = compute_scores(&attestations, &self.strategy, get_current_timestamp(), &backing);
+        debug!("Recalculated reputation scores for {} DIDs", scores.len());
+        *self.scores.write().await = scores;
     }
 
     pub async fn get_stats(&self) -> ReputationStats {
+        self.refresh().await;
         let attestations = self.attestations.read().await;
         let scores = self.scores.read().await;
         
@@ -117,9 +355,258 @@ impl ReputationSystem {
     }
 }
 
+/// Scores every attested DID. `backing` is how much each attestation counts
+/// for by whether it is backed by a settled interaction.
+fn compute_scores(
+    attestations: &HashMap<String, Vec<ReputationAttestation>>,
+    strategy: &ScoringStrategy,
+    now: u64,
+    backing: &dyn Fn(&ReputationAttestation) -> f64,
+) -> HashMap<String, f64> {
+    match strategy {
+        ScoringStrategy::Average => attestations
+            .iter()
+            .map(|(did, atts)| (did.clone(), weighted_score(atts, now, backing)))
+            .collect(),
+        ScoringStrategy::EigenTrust(config) => {
+            let trust = trust::global_trust(&local_trust(attestations, now, backing), config);
+            let attester_trust =
+                |att: &ReputationAttestation| trust.get(&att.attester_did).copied().unwrap_or(0.0) * backing(att);
+            attestations
+                .iter()
+                .map(|(did, atts)| {
+                    let vouched: Vec<ReputationAttestation> =
+                        atts.iter().filter(|att| att.attester_did != *did).cloned().collect();
+                    (did.clone(), weighted_score(&vouched, now, attester_trust))
+                })
+                .collect()
+        }
+    }
+}
+
+/// How much each attester trusts each DID it attested: its time-decayed
+/// average score for it, as a fraction of `MAX_SCORE`, scaled down unless
+/// some attestation between the two is backed. Nobody vouches for themselves.
+fn local_trust(
+    attestations: &HashMap<String, Vec<ReputationAttestation>>,
+    now: u64,
+    backing: &dyn Fn(&ReputationAttestation) -> f64,
+) -> HashMap<String, HashMap<String, f64>> {
+    let mut by_attester: HashMap<(&str, &str), Vec<ReputationAttestation>> = HashMap::new();
+    for (target, atts) in attestations {
+        for att in atts.iter().filter(|att| att.attester_did != *target) {
+            by_attester.entry((att.attester_did.as_str(), target.as_str())).or_default().push(att.clone());
+        }
+    }
+    let mut local_trust: HashMap<String, HashMap<String, f64>> = HashMap::new();
+    for ((attester, target), atts) in by_attester {
+        let strongest = atts.iter().map(backing).fold(0.0, f64::max);
+        let trust = weighted_score(&atts, now, backing) / MAX_SCORE * strongest;
+        local_trust.entry(attester.to_string()).or_default().insert(target.to_string(), trust);
+    }
+    local_trust
+}
+
+/// Average of the attestation scores, each weighted by `weight` and down 5%
+/// per day of age.
+fn weighted_score(atts: &[ReputationAttestation], now: u64, weight: impl Fn(&ReputationAttestation) -> f64) -> f64 {
+    let mut weighted_sum = 0.0;
+    let mut weight_sum = 0.0;
+
+    for att in atts {
+        // Apply time decay (older attestations have less weight)
+        let age_days = now.saturating_sub(att.timestamp) / 86400;
+        let decay_factor = 0.95_f64.powi(age_days as i32);
+        let weight = decay_factor * weight(att);
+
+        weighted_sum += att.score * weight;
+        weight_sum += weight;
+    }
+
+    if weight_sum > 0.0 {
+        weighted_sum / weight_sum
+    } else {
+        0.0
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ReputationStats {
     pub total_nodes: usize,
     pub total_attestations: usize,
     pub average_score: f64,
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::dht::DHT;
+    use crate::core::identity::DIDManager;
+    use crate::core::signing::EscrowApproval;
+
+    #[tokio::test]
+    async fn test_only_valid_signed_attestations_count() {
+        let attester = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(attester.did.id.clone())).with_identity(attester.clone());
+        dht.publish_did_document(&attester.document()).await.unwrap();
+        let reputation = ReputationSystem::new(DidResolver::new(dht));
+        let attest = |score: f64| attester.create_attestation("did:duxnet:target".to_string(), score, "task_completed".to_string());
+
+        let attestation = attest(4.0);
+        reputation.add_attestation(attestation.clone()).await.unwrap();
+        // Scores are computed when read, not on every attestation.
+        assert!(reputation.scores.read().await.is_empty());
+        assert_eq!(reputation.get_reputation("did:duxnet:target").await, 4.0);
+        assert!(reputation.add_attestation(attestation.clone()).await.is_err(), "replayed");
+
+        assert!(reputation.add_attestation(attest(MAX_SCORE + 1.0)).await.is_err());
+        assert!(reputation.add_attestation(attest(f64::NAN)).await.is_err());
+
+        let mut future = attest(3.0);
+        future.timestamp = get_current_timestamp() + 2 * MAX_CLOCK_SKEW;
+        future.signature = attester.sign(&future);
+        assert!(reputation.add_attestation(future).await.is_err());
+
+        // Signed by someone other than the named attester, or not signed at all.
+        let impostor = DIDManager::new(vec![]);
+        let mut forged = ReputationAttestation { timestamp: attestation.timestamp + 1, ..attestation.clone() };
+        forged.signature = impostor.sign(&forged);
+        assert!(reputation.add_attestation(forged).await.is_err());
+        let unknown = impostor.create_attestation("did:duxnet:target".to_string(), 1.0, "task_completed".to_string());
+        assert!(reputation.add_attestation(unknown).await.is_err());
+
+        assert_eq!(reputation.attestations.read().await["did:duxnet:target"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sybil_attesters_do_not_move_eigentrust_scores() {
+        let seed = DIDManager::new(vec![]);
+        let dht = DHT::new(NodeId(seed.did.id.clone()));
+        let attesters: Vec<DIDManager> = (0..21).map(|_| DIDManager::new(vec![])).collect();
+        for attester in attesters.iter().chain([&seed]) {
+            dht.clone().with_identity(attester.clone()).publish_did_document(&attester.document()).await.unwrap();
+        }
+        let (alice, sybils) = attesters.split_first().unwrap();
+        let eigentrust = ScoringStrategy::EigenTrust(EigenTrustConfig {
+            pre_trusted: vec![seed.did.id.clone()],
+            ..EigenTrustConfig::default()
+        });
+        let trusted = ReputationSystem::new(DidResolver::new(dht.clone())).with_strategy(eigentrust);
+        let average = ReputationSystem::new(DidResolver::new(dht)).with_strategy(ScoringStrategy::Average);
+
+        let mut attestations = vec![
+            seed.create_attestation(alice.did.id.clone(), 4.0, "task_completed".to_string()),
+            alice.create_attestation("did:duxnet:bob".to_string(), 3.0, "task_completed".to_string()),
+        ];
+        for sybil in sybils {
+            attestations.push(sybil.create_attestation("did:duxnet:bob".to_string(), 5.0, "task_completed".to_string()));
+            attestations.push(sybil.create_attestation(alice.did.id.clone(), 0.0, "task_completed".to_string()));
+        }
+        for attestation in attestations {
+            trusted.add_attestation(attestation.clone()).await.unwrap();
+            average.add_attestation(attestation).await.unwrap();
+        }
+
+        assert_eq!(trusted.get_reputation(&alice.did.id).await, 4.0);
+        assert!((trusted.get_reputation("did:duxnet:bob").await - 3.0).abs() < 1e-9);
+        assert!(average.get_reputation("did:duxnet:bob").await > 4.8);
+        assert!(average.get_reputation(&alice.did.id).await < 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_attestations_count_in_full_only_for_settled_interactions() {
+        let (buyer, seller, stranger) = (DIDManager::new(vec![]), DIDManager::new(vec![]), DIDManager::new(vec![]));
+        let dht = DHT::new(NodeId(buyer.did.id.clone()));
+        for identity in [&buyer, &seller, &stranger] {
+            dht.clone().with_identity(identity.clone()).publish_did_document(&identity.document()).await.unwrap();
+        }
+        let escrows = EscrowManager::new(DidResolver::new(dht.clone()));
+        let tasks = TaskEngine::new();
+        let interactions = Arc::new(LocalInteractions::new(escrows.clone(), tasks.clone(), DidResolver::new(dht.clone())));
+        let strict = ReputationSystem::new(DidResolver::new(dht.clone()))
+            .with_interactions(interactions.clone())
+            .with_unbacked_policy(UnbackedPolicy::Reject);
+        let lenient = ReputationSystem::new(DidResolver::new(dht))
+            .with_strategy(ScoringStrategy::Average)
+            .with_interactions(interactions)
+            .with_unbacked_policy(UnbackedPolicy::Discount(0.25));
+
+        let escrow_id = escrows.create_escrow(buyer.did.id.clone(), seller.did.id.clone(), vec![], 10).await.unwrap();
+        let escrow = InteractionRef::Escrow(escrow_id.clone());
+        let rate = |attester: &DIDManager, score: f64, interaction: Option<InteractionRef>| {
+            let mut attestation = attester.create_attestation(seller.did.id.clone(), score, "escrow".to_string());
+            attestation.interaction = interaction;
+            attestation.signature = attester.sign(&attestation);
+            attestation
+        };
+
+        // Unsettled, unnamed, or between other parties: not backed.
+        let early = rate(&buyer, 5.0, Some(escrow.clone()));
+        assert!(strict.add_attestation(early.clone()).await.is_err());
+        assert!(strict.add_attestation(rate(&stranger, 1.0, None)).await.is_err());
+        lenient.add_attestation(early).await.unwrap();
+        lenient.add_attestation(rate(&stranger, 1.0, None)).await.unwrap();
+        assert!((lenient.get_reputation(&seller.did.id).await - 3.0).abs() < 1e-9);
+
+        for state in [EscrowState::Funded, EscrowState::Completed] {
+            for party in [&buyer, &seller] {
+                let signature = party.sign(&EscrowApproval { escrow_id: &escrow_id, state: &state });
+                escrows.add_signature(&escrow_id, &party.did.id, signature).await.unwrap();
+            }
+            if state == EscrowState::Funded {
+                escrows.update_state(&escrow_id, EscrowState::InProgress).await.unwrap();
+            }
+        }
+        assert!(strict.add_attestation(rate(&stranger, 1.0, Some(escrow.clone()))).await.is_err());
+        strict.add_attestation(rate(&buyer, 5.0, Some(escrow))).await.unwrap();
+
+        // Marked completed without both parties signing off: not settled.
+        let disputed_id = escrows
+            .create_escrow(buyer.did.id.clone(), seller.did.id.clone(), vec![stranger.did.id.clone()], 10)
+            .await
+            .unwrap();
+        for party in [&buyer, &seller] {
+            let signature = party.sign(&EscrowApproval { escrow_id: &disputed_id, state: &EscrowState::Funded });
+            escrows.add_signature(&disputed_id, &party.did.id, signature).await.unwrap();
+        }
+        for state in [EscrowState::Disputed, EscrowState::Completed] {
+            escrows.update_state(&disputed_id, state).await.unwrap();
+        }
+        let mut disputed = rate(&buyer, 5.0, Some(InteractionRef::Escrow(disputed_id)));
+        disputed.timestamp += 2;
+        disputed.signature = buyer.sign(&disputed);
+        assert!(strict.add_attestation(disputed).await.is_err());
+
+        // The escrow settled after the buyer attested: it now counts in full.
+        lenient.rescore().await;
+        assert!((lenient.get_reputation(&seller.did.id).await - (5.0 + 0.25) / 1.25).abs() < 1e-9);
+
+        // A completed task backs ratings between its processor and the escrow's buyer.
+        let task = Task {
+            id: TaskId("task-1".to_string()),
+            escrow_id: escrow_id.clone(),
+            service_id: ServiceId("svc-1".to_string()),
+            submitter_did: buyer.did.id.clone(),
+            payload: vec![],
+            requirements: TaskRequirements { cpu_cores: 1, memory_mb: 64, timeout_seconds: 5 },
+            created_at: get_current_timestamp(),
+        };
+        tasks.submit_task(task).await.unwrap();
+        tasks.accept_task(&TaskId("task-1".to_string()), seller.did.id.clone()).await.unwrap();
+        tasks
+            .complete_task(TaskResult {
+                task_id: TaskId("task-1".to_string()),
+                processor_did: seller.did.id.clone(),
+                result: vec![],
+                proof: vec![],
+                completed_at: get_current_timestamp(),
+                escrow_id: escrow_id.clone(),
+            })
+            .await
+            .unwrap();
+        let mut for_task = rate(&buyer, 4.0, Some(InteractionRef::Task(TaskId("task-1".to_string()))));
+        for_task.timestamp += 1;
+        for_task.signature = buyer.sign(&for_task);
+        strict.add_attestation(for_task).await.unwrap();
+        assert_eq!(strict.attestations.read().await[&seller.did.id].len(), 2);
+    }
+}
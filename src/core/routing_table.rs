@@ -0,0 +1,217 @@
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+
+/// Width of the keyspace; node ids and record keys are SHA-256 digests.
+pub const KEY_BITS: usize = 256;
+/// Default bucket size (Kademlia's `k`).
+pub const DEFAULT_K: usize = 20;
+
+pub type RoutingKey = [u8; 32];
+
+/// Position of a node id or record key in the XOR keyspace.
+pub fn routing_key(id: &str) -> RoutingKey {
+    Sha256::digest(id.as_bytes()).into()
+}
+
+pub fn xor_distance(a: &RoutingKey, b: &RoutingKey) -> RoutingKey {
+    let mut distance = [0u8; 32];
+    for (i, byte) in distance.iter_mut().enumerate() {
+        *byte = a[i] ^ b[i];
+    }
+    distance
+}
+
+/// Bucket `i` holds peers whose distance from us lies in `[2^i, 2^(i+1))`.
+fn bucket_index(local: &RoutingKey, key: &RoutingKey) -> Option<usize> {
+    let distance = xor_distance(local, key);
+    let leading_zeros = distance
+        .iter()
+        .position(|byte| *byte != 0)
+        .map(|i| i * 8 + distance[i].leading_zeros() as usize)?;
+    Some(KEY_BITS - 1 - leading_zeros)
+}
+
+#[derive(Debug, Clone)]
+struct PeerEntry {
+    peer_id: String,
+    key: RoutingKey,
+}
+
+/// Peers ordered from least to most recently seen, plus a replacement cache of
+/// peers that arrived while the bucket was full.
+#[derive(Debug, Clone, Default)]
+struct KBucket {
+    entries: VecDeque<PeerEntry>,
+    replacements: VecDeque<PeerEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertOutcome {
+    Added,
+    Refreshed,
+    /// The bucket is full. The new peer waits in the replacement cache until the
+    /// caller has checked whether `least_recently_seen` is still alive.
+    BucketFull { least_recently_seen: String },
+    /// Our own id never goes into the table.
+    Ignored,
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutingTable {
+    local_key: RoutingKey,
+    k: usize,
+    buckets: Vec<KBucket>,
+}
+
+impl RoutingTable {
+    pub fn new(local_id: &str, k: usize) -> Self {
+        RoutingTable {
+            local_key: routing_key(local_id),
+            k,
+            buckets: vec![KBucket::default(); KEY_BITS],
+        }
+    }
+
+    pub fn insert(&mut self, peer_id: &str) -> InsertOutcome {
+        let key = routing_key(peer_id);
+        let Some(index) = bucket_index(&self.local_key, &key) else {
+            return InsertOutcome::Ignored;
+        };
+        let k = self.k;
+        let bucket = &mut self.buckets[index];
+
+        if let Some(position) = bucket.entries.iter().position(|e| e.peer_id == peer_id) {
+            let entry = bucket.entries.remove(position).expect("position is in range");
+            bucket.entries.push_back(entry);
+            return InsertOutcome::Refreshed;
+        }
+
+        let entry = PeerEntry { peer_id: peer_id.to_string(), key };
+        if bucket.entries.len() < k {
+            bucket.entries.push_back(entry);
+            return InsertOutcome::Added;
+        }
+
+        bucket.replacements.retain(|e| e.peer_id != peer_id);
+        bucket.replacements.push_back(entry);
+        if bucket.replacements.len() > k {
+            bucket.replacements.pop_front();
+        }
+        InsertOutcome::BucketFull {
+            least_recently_seen: bucket.entries.front().expect("bucket is full").peer_id.clone(),
+        }
+    }
+
+    /// Records that a peer answered a liveness check, moving it to the back of its bucket.
+    pub fn mark_alive(&mut self, peer_id: &str) {
+        if self.contains(peer_id) {
+            self.insert(peer_id);
+        }
+    }
+
+    /// Drops a peer and promotes the most recently seen replacement, if any.
+    /// Returns the promoted peer.
+    pub fn remove(&mut self, peer_id: &str) -> Option<String> {
+        let index = bucket_index(&self.local_key, &routing_key(peer_id))?;
+        let bucket = &mut self.buckets[index];
+        bucket.replacements.retain(|e| e.peer_id != peer_id);
+
+        let position = bucket.entries.iter().position(|e| e.peer_id == peer_id)?;
+        bucket.entries.remove(position);
+        let promoted = bucket.replacements.pop_back()?;
+        let promoted_id = promoted.peer_id.clone();
+        bucket.entries.push_back(promoted);
+        Some(promoted_id)
+    }
+
+    pub fn contains(&self, peer_id: &str) -> bool {
+        bucket_index(&self.local_key, &routing_key(peer_id))
+            .map(|index| self.buckets[index].entries.iter().any(|e| e.peer_id == peer_id))
+            .unwrap_or(false)
+    }
+
+    /// The `count` known peers closest to `key` by XOR distance, closest first.
+    pub fn closest_peers(&self, key: &RoutingKey, count: usize) -> Vec<String> {
+        let mut peers: Vec<(RoutingKey, &String)> = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.entries.iter())
+            .map(|entry| (xor_distance(&entry.key, key), &entry.peer_id))
+            .collect();
+        peers.sort();
+        peers.into_iter().take(count).map(|(_, peer_id)| peer_id.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.entries.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peer ids that all land in the same bucket of `table`.
+    fn same_bucket_peers(table: &RoutingTable, count: usize) -> Vec<String> {
+        let target = bucket_index(&table.local_key, &routing_key("peer-0"));
+        (0..)
+            .map(|i| format!("peer-{}", i))
+            .filter(|id| bucket_index(&table.local_key, &routing_key(id)) == target)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_index_follows_highest_differing_bit() {
+        let local = [0u8; 32];
+        let mut key = [0u8; 32];
+        assert_eq!(bucket_index(&local, &key), None);
+        key[31] = 1;
+        assert_eq!(bucket_index(&local, &key), Some(0));
+        key[0] = 0x80;
+        assert_eq!(bucket_index(&local, &key), Some(KEY_BITS - 1));
+    }
+
+    #[test]
+    fn test_full_bucket_keeps_live_peers_and_promotes_on_eviction() {
+        let mut table = RoutingTable::new("local", 2);
+        let peers = same_bucket_peers(&table, 3);
+
+        assert_eq!(table.insert(&peers[0]), InsertOutcome::Added);
+        assert_eq!(table.insert(&peers[1]), InsertOutcome::Added);
+        assert_eq!(
+            table.insert(&peers[2]),
+            InsertOutcome::BucketFull { least_recently_seen: peers[0].clone() }
+        );
+        assert!(!table.contains(&peers[2]));
+
+        // A live peer stays; it is now the most recently seen.
+        table.mark_alive(&peers[0]);
+        assert_eq!(
+            table.insert(&peers[2]),
+            InsertOutcome::BucketFull { least_recently_seen: peers[1].clone() }
+        );
+
+        // A dead one is replaced by the waiting peer.
+        assert_eq!(table.remove(&peers[1]), Some(peers[2].clone()));
+        assert!(table.contains(&peers[2]));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.insert("local"), InsertOutcome::Ignored);
+    }
+
+    #[test]
+    fn test_closest_peers_are_sorted_by_xor_distance() {
+        let mut table = RoutingTable::new("local", DEFAULT_K);
+        for i in 0..50 {
+            table.insert(&format!("peer-{}", i));
+        }
+
+        let target = routing_key("service:abc");
+        let closest = table.closest_peers(&target, 5);
+        assert_eq!(closest.len(), 5);
+
+        let mut all: Vec<String> = (0..50).map(|i| format!("peer-{}", i)).filter(|peer| table.contains(peer)).collect();
+        all.sort_by_key(|peer| xor_distance(&routing_key(peer), &target));
+        assert_eq!(closest, all[..5].to_vec());
+    }
+}
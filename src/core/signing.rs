@@ -0,0 +1,278 @@
+//! The one encoding everything signed on DuxNet is signed over: a domain tag
+//! naming the type, then each field in a fixed order. Strings and byte strings
+//! are length-prefixed, integers are big-endian and floats are encoded by
+//! their IEEE 754 bits, so no two distinct values encode alike.
+
+use crate::core::data_structures::*;
+use serde::Serialize;
+
+const TAG_NONE: u8 = 0;
+const TAG_SOME: u8 = 1;
+
+/// A value with a canonical signing payload.
+pub trait Signable {
+    /// Distinguishes this type's payloads from every other type's,
+    /// e.g. `duxnet/attestation/v1`.
+    const DOMAIN: &'static str;
+
+    /// Writes the signed fields, in order.
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder);
+
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut encoder = CanonicalEncoder::new(Self::DOMAIN);
+        self.encode_fields(&mut encoder);
+        encoder.finish()
+    }
+}
+
+pub struct CanonicalEncoder {
+    buffer: Vec<u8>,
+}
+
+impl CanonicalEncoder {
+    pub fn new(domain: &str) -> Self {
+        let mut encoder = CanonicalEncoder { buffer: Vec::new() };
+        encoder.str(domain);
+        encoder
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u64(value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Signed zero and NaN payloads are folded so equal scores encode alike.
+    pub fn f64(&mut self, value: f64) -> &mut Self {
+        let value = if value == 0.0 {
+            0.0
+        } else if value.is_nan() {
+            f64::NAN
+        } else {
+            value
+        };
+        self.u64(value.to_bits())
+    }
+
+    pub fn option_str(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.buffer.push(TAG_SOME);
+                self.str(value)
+            }
+            None => {
+                self.buffer.push(TAG_NONE);
+                self
+            }
+        }
+    }
+
+    pub fn option_u64(&mut self, value: Option<u64>) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.buffer.push(TAG_SOME);
+                self.u64(value)
+            }
+            None => {
+                self.buffer.push(TAG_NONE);
+                self
+            }
+        }
+    }
+
+    /// A fieldless enum, by the variant name it serializes to.
+    pub fn variant(&mut self, value: &impl Serialize) -> &mut Self {
+        match serde_json::to_value(value) {
+            Ok(serde_json::Value::String(name)) => self.str(&name),
+            _ => panic!("CanonicalEncoder::variant takes fieldless enums"),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Signable for ReputationAttestation {
+    const DOMAIN: &'static str = "duxnet/attestation/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .str(&self.attester_did)
+            .str(&self.target_did)
+            .f64(self.score)
+            .str(&self.interaction_type)
+            .u64(self.timestamp);
+        // Appended only when present, so attestations signed before
+        // interactions could be named still verify.
+        match &self.interaction {
+            Some(InteractionRef::Escrow(escrow_id)) => {
+                encoder.str("escrow").str(escrow_id);
+            }
+            Some(InteractionRef::Task(task_id)) => {
+                encoder.str("task").str(&task_id.0);
+            }
+            None => {}
+        }
+    }
+}
+
+impl Signable for Message {
+    const DOMAIN: &'static str = "duxnet/message/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .str(&self.id)
+            .str(&self.from_did)
+            .str(&self.to_did)
+            .str(&self.content)
+            .variant(&self.message_type)
+            .u64(self.timestamp)
+            .option_str(self.reply_to.as_deref());
+    }
+}
+
+/// A party's approval of an escrow moving to `state`.
+#[derive(Debug, Clone)]
+pub struct EscrowApproval<'a> {
+    pub escrow_id: &'a str,
+    pub state: &'a EscrowState,
+}
+
+impl Signable for EscrowApproval<'_> {
+    const DOMAIN: &'static str = "duxnet/escrow-approval/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder.str(self.escrow_id).variant(self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::{Currency, Transaction, TransactionStatus};
+
+    #[test]
+    fn test_payload_vectors() {
+        let attestation = ReputationAttestation {
+            attester_did: "did:duxnet:a".to_string(),
+            target_did: "did:duxnet:b".to_string(),
+            score: 0.5,
+            interaction_type: "task".to_string(),
+            timestamp: 1_700_000_000,
+            signature: Vec::new(),
+            interaction: None,
+        };
+        assert_eq!(
+            hex::encode(attestation.signing_payload()),
+            concat!(
+                "0000000000000015", "6475786e65742f6174746573746174696f6e2f7631", // duxnet/attestation/v1
+                "000000000000000c", "6469643a6475786e65743a61", // did:duxnet:a
+                "000000000000000c", "6469643a6475786e65743a62", // did:duxnet:b
+                "3fe0000000000000", // 0.5
+                "0000000000000004", "7461736b", // task
+                "000000006553f100", // 1700000000
+            )
+        );
+
+        let approval = EscrowApproval { escrow_id: "e1", state: &EscrowState::Funded };
+        assert_eq!(
+            hex::encode(approval.signing_payload()),
+            concat!(
+                "0000000000000019", "6475786e65742f657363726f772d617070726f76616c2f7631",
+                "0000000000000002", "6531",
+                "0000000000000006", "46756e646564",
+            )
+        );
+
+        let message = Message {
+            id: "m".to_string(),
+            from_did: "a".to_string(),
+            to_did: "b".to_string(),
+            content: "hi".to_string(),
+            message_type: MessageType::Text,
+            timestamp: 1,
+            signature: Vec::new(),
+            is_read: false,
+            reply_to: None,
+        };
+        assert_eq!(
+            hex::encode(message.signing_payload()),
+            concat!(
+                "0000000000000011", "6475786e65742f6d6573736167652f7631",
+                "0000000000000001", "6d",
+                "0000000000000001", "61",
+                "0000000000000001", "62",
+                "0000000000000002", "6869",
+                "0000000000000004", "54657874",
+                "0000000000000001",
+                "00",
+            )
+        );
+
+        let transaction = Transaction {
+            id: "t".to_string(),
+            from: "a".to_string(),
+            to: "b".to_string(),
+            amount: 5,
+            currency: Currency::DUX,
+            timestamp: 2,
+            signature: Vec::new(),
+            status: TransactionStatus::Pending,
+            fee: 1,
+            block_height: None,
+            confirmations: 0,
+            memo: Some("x".to_string()),
+        };
+        assert_eq!(
+            hex::encode(transaction.signing_payload()),
+            concat!(
+                "0000000000000015", "6475786e65742f7472616e73616374696f6e2f7631",
+                "0000000000000001", "74",
+                "0000000000000001", "61",
+                "0000000000000001", "62",
+                "0000000000000005",
+                "0000000000000003", "445558",
+                "0000000000000001",
+                "0000000000000002",
+                "01", "0000000000000001", "78",
+            )
+        );
+    }
+
+    #[test]
+    fn test_separators_and_scores_cannot_collide() {
+        let attestation = |attester: &str, target: &str, score: f64| ReputationAttestation {
+            attester_did: attester.to_string(),
+            target_did: target.to_string(),
+            score,
+            interaction_type: "task".to_string(),
+            timestamp: 1,
+            signature: Vec::new(),
+            interaction: None,
+        };
+        // Both joined to "a:b:c" by the old format! payloads.
+        assert_ne!(attestation("a:b", "c", 1.0).signing_payload(), attestation("a", "b:c", 1.0).signing_payload());
+        assert_eq!(attestation("a", "b", 0.0).signing_payload(), attestation("a", "b", -0.0).signing_payload());
+        assert_ne!(attestation("a", "b", 0.1).signing_payload(), attestation("a", "b", 0.1 + f64::EPSILON).signing_payload());
+
+        // Naming the interaction, and which kind it is, changes what is signed.
+        let escrow = ReputationAttestation { interaction: Some(InteractionRef::Escrow("x".to_string())), ..attestation("a", "b", 1.0) };
+        let task = ReputationAttestation { interaction: Some(InteractionRef::Task(TaskId("x".to_string()))), ..attestation("a", "b", 1.0) };
+        assert_ne!(escrow.signing_payload(), attestation("a", "b", 1.0).signing_payload());
+        assert_ne!(escrow.signing_payload(), task.signing_payload());
+
+        // The same fields under another type's domain sign differently.
+        let approval = EscrowApproval { escrow_id: "a", state: &EscrowState::Funded };
+        assert!(!approval.signing_payload().starts_with(&CanonicalEncoder::new(ReputationAttestation::DOMAIN).finish()));
+    }
+}
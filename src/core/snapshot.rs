@@ -0,0 +1,141 @@
+//! File format for DHT snapshots: a header line followed by one signed record
+//! per line, all JSON. Records keep their signatures, so an imported snapshot
+//! is verified like records from any other peer.
+
+use crate::core::data_structures::get_current_timestamp;
+use crate::core::dht::DHTEntry;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+const SNAPSHOT_FORMAT: &str = "duxnet-dht-snapshot";
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub format: String,
+    pub version: u32,
+    /// DID of the node the snapshot was taken on.
+    pub node_id: String,
+    pub exported_at: u64,
+    pub entries: usize,
+}
+
+/// What to do with a snapshot record whose key this node already holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Keep the local record.
+    Skip,
+    /// Replace the local record with any its publisher may write over it,
+    /// however old.
+    Overwrite,
+    /// Keep whichever record was published last.
+    #[default]
+    NewerWins,
+}
+
+impl MergePolicy {
+    /// Whether `imported` should replace `existing`, the live local record for
+    /// its key. Only asked once `imported` keeps to the ownership and sequence
+    /// rules of `DHT::store_entry_local`.
+    pub fn replaces(&self, imported: &DHTEntry, existing: &DHTEntry) -> bool {
+        match self {
+            MergePolicy::Skip => false,
+            MergePolicy::Overwrite => imported.signature != existing.signature,
+            MergePolicy::NewerWins => (imported.timestamp, imported.seq) > (existing.timestamp, existing.seq),
+        }
+    }
+}
+
+/// What `DHT::import_snapshot` did with each record.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Kept the local record under the merge policy.
+    pub skipped: usize,
+    pub expired: usize,
+    /// Failed verification, the ownership and sequence rules or a quota.
+    pub rejected: usize,
+}
+
+pub fn write_snapshot(writer: &mut impl Write, node_id: &str, entries: &[DHTEntry]) -> Result<()> {
+    let header = SnapshotHeader {
+        format: SNAPSHOT_FORMAT.to_string(),
+        version: SNAPSHOT_VERSION,
+        node_id: node_id.to_string(),
+        exported_at: get_current_timestamp(),
+        entries: entries.len(),
+    };
+    serde_json::to_writer(&mut *writer, &header)?;
+    writer.write_all(b"\n")?;
+    for entry in entries {
+        serde_json::to_writer(&mut *writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Parses a whole snapshot up front, so a malformed file imports nothing.
+pub fn read_snapshot(reader: impl BufRead) -> Result<(SnapshotHeader, Vec<DHTEntry>)> {
+    let mut lines = reader.lines();
+    let header: SnapshotHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line?).map_err(|e| anyhow::anyhow!("Invalid snapshot header: {}", e))?,
+        None => return Err(anyhow::anyhow!("Snapshot is empty")),
+    };
+    if header.format != SNAPSHOT_FORMAT {
+        return Err(anyhow::anyhow!("Not a DHT snapshot: format {:?}", header.format));
+    }
+    if header.version != SNAPSHOT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Snapshot version {} is not supported; expected {}",
+            header.version,
+            SNAPSHOT_VERSION
+        ));
+    }
+
+    let mut entries = Vec::with_capacity(header.entries);
+    for (number, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("Invalid snapshot record on line {}: {}", number + 2, e))?;
+        entries.push(entry);
+    }
+    if entries.len() != header.entries {
+        return Err(anyhow::anyhow!(
+            "Snapshot is truncated: header promises {} records, found {}",
+            header.entries,
+            entries.len()
+        ));
+    }
+    Ok((header, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::identity::DIDManager;
+
+    #[test]
+    fn test_snapshot_header_is_checked() {
+        let publisher = DIDManager::new(vec![]);
+        let entries = vec![DHTEntry::signed(&publisher, "aoi:s1".to_string(), b"key".to_vec(), 60, 1)];
+        let mut snapshot = Vec::new();
+        write_snapshot(&mut snapshot, "node", &entries).unwrap();
+
+        let (header, read) = read_snapshot(snapshot.as_slice()).unwrap();
+        assert_eq!((header.version, header.node_id.as_str()), (SNAPSHOT_VERSION, "node"));
+        assert_eq!(read[0].key, "aoi:s1");
+
+        let text = String::from_utf8(snapshot).unwrap();
+        let truncated = text.lines().next().unwrap();
+        assert!(read_snapshot(truncated.as_bytes()).is_err());
+        let future = text.replace("\"version\":1", "\"version\":2");
+        assert!(read_snapshot(future.as_bytes()).is_err());
+        assert!(read_snapshot("".as_bytes()).is_err());
+    }
+}
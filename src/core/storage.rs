@@ -0,0 +1,328 @@
+//! The node's data directory and the durable collections subsystems keep in it.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Layout version of the data directory. Bump it when stored data changes
+/// shape, and add the step that upgrades the previous version to `MIGRATIONS`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[i]` upgrades a data directory from version `i + 1` to `i + 2`.
+const MIGRATIONS: &[fn(&Path) -> Result<()>] = &[];
+
+const VERSION_FILE: &str = "schema_version";
+
+/// A node's data directory, migrated to `SCHEMA_VERSION` when opened.
+#[derive(Debug, Clone)]
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+        let data_dir = DataDir { root };
+
+        let version_path = data_dir.path(VERSION_FILE);
+        let mut version = if version_path.exists() {
+            let contents = fs::read_to_string(&version_path)?;
+            contents
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("Unreadable schema version {:?} in {}", contents, version_path.display()))?
+        } else {
+            SCHEMA_VERSION
+        };
+        if version > SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "{} holds schema version {}, but this release only understands up to {}",
+                data_dir.root.display(),
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+        while version < SCHEMA_VERSION {
+            info!("Migrating {} from schema version {}", data_dir.root.display(), version);
+            MIGRATIONS[version as usize - 1](&data_dir.root)?;
+            version += 1;
+            write_atomically(&version_path, version.to_string().as_bytes())?;
+        }
+        if !version_path.exists() {
+            write_atomically(&version_path, SCHEMA_VERSION.to_string().as_bytes())?;
+        }
+
+        Ok(data_dir)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Opens the collection stored under `name`, creating it if needed.
+    pub fn collection<V: Serialize + DeserializeOwned + Clone>(&self, name: &str) -> Result<Collection<V>> {
+        Collection::open(self.path(&format!("{}.log", name)))
+    }
+}
+
+/// Replaces `path` with `contents` so that a crash leaves either the old file or
+/// the new one, never a mix.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temporary, path)?;
+    sync_parent(path)
+}
+
+fn sync_parent(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+enum LogRecord<V> {
+    Put(String, V),
+    Remove(String),
+}
+
+/// Durable key-value collection: an append-only log of JSON lines, synced on
+/// every write, with the current values held in memory. The log is replayed
+/// and compacted on open; a torn final line left by a crash is ignored, while
+/// a bad line before it fails the open.
+pub struct Collection<V> {
+    path: PathBuf,
+    log: BufWriter<File>,
+    entries: HashMap<String, V>,
+    /// Records in the log, superseded ones included.
+    log_records: usize,
+}
+
+impl<V: Serialize + DeserializeOwned + Clone> Collection<V> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut entries = HashMap::new();
+        if path.exists() {
+            let mut lines = BufReader::new(File::open(&path)?).lines().enumerate().peekable();
+            while let Some((number, line)) = lines.next() {
+                let record = match line.map_err(anyhow::Error::from).and_then(|line| Ok(serde_json::from_str(&line)?)) {
+                    Ok(record) => record,
+                    // Only the last line can be torn by a crash. Anything earlier is
+                    // corruption, and compacting past it would drop the records after it.
+                    Err(e) if lines.peek().is_none() => {
+                        warn!("Ignoring torn final line {} of {}: {}", number + 1, path.display(), e);
+                        break;
+                    }
+                    Err(e) => return Err(anyhow::anyhow!("{} is corrupt at line {}: {}", path.display(), number + 1, e)),
+                };
+                match record {
+                    LogRecord::Put(key, value) => {
+                        entries.insert(key, value);
+                    }
+                    LogRecord::Remove(key) => {
+                        entries.remove(&key);
+                    }
+                }
+            }
+        }
+
+        let mut collection = Collection {
+            log: BufWriter::new(OpenOptions::new().create(true).append(true).open(&path)?),
+            path,
+            entries,
+            log_records: 0,
+        };
+        collection.compact()?;
+        Ok(collection)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, value: V) -> Result<()> {
+        self.append(&LogRecord::Put(key.clone(), value.clone()))?;
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<Option<V>> {
+        if !self.entries.contains_key(key) {
+            return Ok(None);
+        }
+        self.append(&LogRecord::Remove(key.to_string()))?;
+        Ok(self.entries.remove(key))
+    }
+
+    /// Keeps only the values `keep` accepts, rewriting the log once.
+    pub fn retain(&mut self, keep: impl FnMut(&String, &mut V) -> bool) -> Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(keep);
+        if self.entries.len() != before {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Rewrites the log with only the current values. The new log replaces the
+    /// old one in a single rename.
+    fn compact(&mut self) -> Result<()> {
+        let temporary = self.path.with_extension("compacting");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            for (key, value) in &self.entries {
+                write_record(&mut writer, &LogRecord::Put(key.clone(), value.clone()))?;
+            }
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&temporary, &self.path)?;
+        sync_parent(&self.path)?;
+        self.log = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        self.log_records = self.entries.len();
+        Ok(())
+    }
+
+    fn append(&mut self, record: &LogRecord<V>) -> Result<()> {
+        write_record(&mut self.log, record)?;
+        self.log.flush()?;
+        self.log.get_ref().sync_data()?;
+        self.log_records += 1;
+        if self.log_records > 2 * self.entries.len() + 64 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_record<V: Serialize>(writer: &mut impl Write, record: &LogRecord<V>) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Where a subsystem writes its state through to. Subsystems built without
+/// storage keep everything in memory.
+pub struct Persisted<V> {
+    collection: Option<Arc<Mutex<Collection<V>>>>,
+}
+
+impl<V> Clone for Persisted<V> {
+    fn clone(&self) -> Self {
+        Persisted { collection: self.collection.clone() }
+    }
+}
+
+impl<V: Serialize + DeserializeOwned + Clone + Send + 'static> Persisted<V> {
+    pub fn memory() -> Self {
+        Persisted { collection: None }
+    }
+
+    pub fn new(collection: Collection<V>) -> Self {
+        Persisted { collection: Some(Arc::new(Mutex::new(collection))) }
+    }
+
+    pub async fn save(&self, key: &str, value: &V) -> Result<()> {
+        let (key, value) = (key.to_string(), value.clone());
+        self.write(move |collection| collection.insert(key, value)).await
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let key = key.to_string();
+        self.write(move |collection| collection.remove(&key).map(|_| ())).await
+    }
+
+    /// Runs `op` on a blocking thread, since every write waits on an fsync.
+    async fn write(&self, op: impl FnOnce(&mut Collection<V>) -> Result<()> + Send + 'static) -> Result<()> {
+        let Some(collection) = &self.collection else {
+            return Ok(());
+        };
+        let mut collection = collection.clone().lock_owned().await;
+        tokio::task::spawn_blocking(move || op(&mut collection)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_survives_reopen_and_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("escrows.log");
+
+        {
+            let mut collection = Collection::open(&path).unwrap();
+            for round in 0..100u64 {
+                collection.insert("escrow-1".to_string(), round).unwrap();
+            }
+            collection.insert("escrow-2".to_string(), 7).unwrap();
+            collection.remove("escrow-2").unwrap();
+        }
+        // Superseded records were compacted away along the way.
+        assert!(fs::read_to_string(&path).unwrap().lines().count() < 100);
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"Put\":[\"escr").unwrap();
+
+        let mut collection: Collection<u64> = Collection::open(&path).unwrap();
+        assert_eq!(collection.len(), 1);
+        assert_eq!(collection.get("escrow-1"), Some(&99));
+        collection.insert("escrow-3".to_string(), 3).unwrap();
+        assert_eq!(Collection::<u64>::open(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_corrupt_line_before_the_tail_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.log");
+        fs::write(&path, "{\"Put\":[\"task-1\",1]}\nnot json\n{\"Put\":[\"task-2\",2]}\n").unwrap();
+
+        assert!(Collection::<u64>::open(&path).is_err());
+        // The log is left as it was rather than compacted down to task-1.
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_schema_version_is_recorded_and_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = DataDir::open(dir.path().join("node")).unwrap();
+        let version_path = data_dir.path(VERSION_FILE);
+        assert_eq!(fs::read_to_string(&version_path).unwrap(), SCHEMA_VERSION.to_string());
+
+        DataDir::open(data_dir.root()).unwrap();
+        fs::write(&version_path, (SCHEMA_VERSION + 1).to_string()).unwrap();
+        assert!(DataDir::open(data_dir.root()).is_err());
+    }
+}
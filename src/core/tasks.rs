@@ -1,5 +1,7 @@
 use crate::core::data_structures::*;
+use crate::core::storage::{Collection, Persisted};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -12,6 +14,15 @@ pub struct TaskEngine {
     pub completed_tasks: Arc<RwLock<HashMap<TaskId, TaskResult>>>,
     pub processing_tasks: Arc<RwLock<HashMap<TaskId, String>>>, // task_id -> processor_did
     pub community_fund_manager: Option<Arc<crate::core::community_fund::CommunityFundManager>>,
+    store: Persisted<TaskRecord>,
+}
+
+/// What the task store keeps per task id: the task's latest stage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TaskRecord {
+    Pending(Task),
+    Processing(String),
+    Completed(TaskResult),
 }
 
 impl TaskEngine {
@@ -21,6 +32,36 @@ impl TaskEngine {
             completed_tasks: Arc::new(RwLock::new(HashMap::new())),
             processing_tasks: Arc::new(RwLock::new(HashMap::new())),
             community_fund_manager: None,
+            store: Persisted::memory(),
+        }
+    }
+
+    /// Loads the tasks kept in `collection` and writes every change back to it.
+    pub fn with_storage(collection: Collection<TaskRecord>) -> Self {
+        let mut pending = HashMap::new();
+        let mut processing = HashMap::new();
+        let mut completed = HashMap::new();
+        for (task_id, record) in collection.iter() {
+            let task_id = TaskId(task_id.clone());
+            match record.clone() {
+                TaskRecord::Pending(task) => {
+                    pending.insert(task_id, task);
+                }
+                TaskRecord::Processing(processor_did) => {
+                    processing.insert(task_id, processor_did);
+                }
+                TaskRecord::Completed(result) => {
+                    completed.insert(task_id, result);
+                }
+            }
+        }
+
+        TaskEngine {
+            pending_tasks: Arc::new(RwLock::new(pending)),
+            completed_tasks: Arc::new(RwLock::new(completed)),
+            processing_tasks: Arc::new(RwLock::new(processing)),
+            community_fund_manager: None,
+            store: Persisted::new(collection),
         }
     }
 
@@ -31,6 +72,7 @@ impl TaskEngine {
 
     pub async fn submit_task(&self, task: Task) -> Result<()> {
         let mut pending = self.pending_tasks.write().await;
+        self.store.save(&task.id.0, &TaskRecord::Pending(task.clone())).await?;
         pending.insert(task.id.clone(), task.clone());
         info!("Submitted task: {}", task.id.0);
         Ok(())
@@ -40,6 +82,9 @@ impl TaskEngine {
         let mut pending = self.pending_tasks.write().await;
         if let Some(task) = pending.remove(task_id) {
             let mut processing = self.processing_tasks.write().await;
+            if let Err(e) = self.store.save(&task_id.0, &TaskRecord::Processing(processor_did.clone())).await {
+                error!("Failed to persist acceptance of task {}: {}", task_id.0, e);
+            }
             processing.insert(task_id.clone(), processor_did.clone());
             info!("Task {} accepted by {}", task_id.0, processor_did);
             Some(task)
@@ -48,10 +93,25 @@ impl TaskEngine {
         }
     }
 
+    /// Records the result of a task, which must have been accepted by the
+    /// processor that reports it.
     pub async fn complete_task(&self, result: TaskResult) -> Result<()> {
         let mut completed = self.completed_tasks.write().await;
         let mut processing = self.processing_tasks.write().await;
+        match processing.get(&result.task_id) {
+            Some(processor_did) if *processor_did == result.processor_did => {}
+            Some(processor_did) => {
+                return Err(anyhow::anyhow!(
+                    "Task {} is processed by {}, not {}",
+                    result.task_id.0,
+                    processor_did,
+                    result.processor_did
+                ))
+            }
+            None => return Err(anyhow::anyhow!("Task {} is not being processed", result.task_id.0)),
+        }
         
+        self.store.save(&result.task_id.0, &TaskRecord::Completed(result.clone())).await?;
         completed.insert(result.task_id.clone(), result.clone());
         processing.remove(&result.task_id);
         
@@ -73,6 +133,7 @@ impl TaskEngine {
             result: result_data.into_bytes(),
             proof,
             completed_at: get_current_timestamp(),
+            escrow_id: task.escrow_id,
         })
     }
 
@@ -89,32 +150,6 @@ impl TaskEngine {
         pending.values().cloned().collect()
     }
 
-    pub async fn get_completed_tasks(&self) -> Vec<TaskResult> {
-        let completed = self.completed_tasks.read().await;
-        completed.values().cloned().collect()
-    }
-
-    pub async fn get_processing_tasks(&self) -> Vec<(TaskId, String)> {
-        let processing = self.processing_tasks.read().await;
-        processing.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
-    }
-
-    pub async fn get_task_status(&self, task_id: &TaskId) -> TaskStatus {
-        let pending = self.pending_tasks.read().await;
-        let completed = self.completed_tasks.read().await;
-        let processing = self.processing_tasks.read().await;
-        
-        if pending.contains_key(task_id) {
-            TaskStatus::Pending
-        } else if processing.contains_key(task_id) {
-            TaskStatus::Processing
-        } else if completed.contains_key(task_id) {
-            TaskStatus::Completed
-        } else {
-            TaskStatus::NotFound
-        }
-    }
-
     pub async fn process_pending_tasks(&self) -> Result<()> {
         let pending_tasks = self.get_pending_tasks().await;
         
@@ -173,14 +208,6 @@ impl TaskEngine {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum TaskStatus {
-    Pending,
-    Processing,
-    Completed,
-    NotFound,
-}
-
 #[derive(Debug, Clone)]
 pub struct TaskStats {
     pub pending_count: usize,
@@ -2,14 +2,12 @@ mod core;
 mod network;
 mod api;
 mod wallet;
-mod frontend;
 
 use anyhow::Result;
 use tracing::{info, error, warn};
-use tracing_subscriber;
 use std::env;
 use tokio::time::{sleep, Duration};
-use crate::core::data_structures::{ReputationAttestation, ServiceId, TaskRequirements};
+use crate::core::data_structures::{ServiceId, TaskRequirements};
 use std::sync::Arc;
 
 #[tokio::main]
@@ -22,8 +20,13 @@ async fn main() -> Result<()> {
     // Check if we're in test mode
     let test_mode = env::var("DUXNET_TEST_MODE").unwrap_or_default() == "1";
     
+    // Ports, bootstrap peers and LAN discovery come from DUXNET_* variables
+    let config = core::config::NodeConfig::from_env()?;
+    let (p2p_port, api_port) = (config.p2p_port, config.api_port);
+    
     // Create and start the DuxNet node
-    let mut node = core::DuxNetNode::new(8080).await?;
+    let mut node = core::DuxNetNode::with_config(config).await?;
+    let api_node = Arc::new(node.clone());
     
     if test_mode {
         info!("🧪 TEST MODE ENABLED - Simulating active network with users");
@@ -32,12 +35,13 @@ async fn main() -> Result<()> {
     
     // Start the web API server
     let api_handle = tokio::spawn(async move {
-        if let Err(e) = api::start_api_server(8081).await {
+        if let Err(e) = api::start_api_server(api_port, api_node).await {
             error!("API server error: {}", e);
         }
     });
     
     // Start the P2P network
+    let shutdown_node = node.clone();
     let network_handle = tokio::spawn(async move {
         if let Err(e) = node.start().await {
             error!("Network error: {}", e);
@@ -45,16 +49,24 @@ async fn main() -> Result<()> {
     });
     
     info!("DuxNet node started successfully!");
-    info!("Web API available at: http://localhost:8081");
-    info!("P2P node listening on port: 8080");
+    info!("Web API available at: http://localhost:{}", api_port);
+    info!("P2P node listening on port: {}", p2p_port);
     
     if test_mode {
-        info!("🌐 Frontend available at: http://localhost:8081 (with simulated data)");
+        info!("🌐 Frontend available at: http://localhost:{} (with simulated data)", api_port);
         info!("💡 Use 'DUXNET_TEST_MODE=1' environment variable to enable test mode");
     }
     
-    // Wait for both handles
-    tokio::try_join!(api_handle, network_handle)?;
+    // Run until either handle finishes or we are asked to shut down
+    tokio::select! {
+        result = async { tokio::try_join!(api_handle, network_handle) } => {
+            result?;
+        }
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutting down DuxNet node");
+            shutdown_node.stop().await?;
+        }
+    }
     
     Ok(())
 }
@@ -88,14 +100,14 @@ async fn start_test_simulation(node: Arc<core::DuxNetNode>) {
 async fn simulate_transaction(node: &core::DuxNetNode) {
     use crate::wallet::Currency;
     
-    let currencies = vec![Currency::BTC, Currency::ETH];
+    let currencies = [Currency::BTC, Currency::ETH];
     let currency = currencies[rand::random::<usize>() % currencies.len()];
     let amount = rand::random::<u64>() % 1000 + 10; // 10-1010 units
     
     match node.wallet.write().await.create_transaction(
         format!("did:duxnet:test-user-{}", rand::random::<u32>()),
         amount,
-        currency.clone()
+        currency
     ) {
         Ok(tx) => {
             info!("💰 Simulated transaction: {} {} (fee: {})", 
@@ -108,7 +120,7 @@ async fn simulate_transaction(node: &core::DuxNetNode) {
 }
 
 async fn simulate_service_registration(node: &core::DuxNetNode) {
-    let services = vec![
+    let services = [
         ("AI Text Processing", "Advanced NLP services", 150),
         ("Image Analysis", "Computer vision processing", 200),
         ("Data Computation", "High-performance analytics", 100),
@@ -121,7 +133,8 @@ async fn simulate_service_registration(node: &core::DuxNetNode) {
     match node.register_service(
         service.0.to_string(),
         service.1.to_string(),
-        service.2
+        service.2,
+        Vec::new()
     ).await {
         Ok(_) => {
             info!("🔧 Simulated service registration: {}", service.0);
@@ -133,7 +146,7 @@ async fn simulate_service_registration(node: &core::DuxNetNode) {
 }
 
 async fn simulate_task_submission(node: &core::DuxNetNode) {
-    let tasks = vec![
+    let tasks = [
         ("text-processing", "Analyze sentiment of customer reviews"),
         ("image-analysis", "Detect objects in surveillance footage"),
         ("data-computation", "Process financial market data"),
@@ -148,8 +161,16 @@ async fn simulate_task_submission(node: &core::DuxNetNode) {
         timeout_seconds: 3600,
     };
     let service_id = ServiceId(task.0.to_string());
+    let identity = match node.identities.select(None).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!("❌ Failed to simulate task submission: {}", e);
+            return;
+        }
+    };
     
     match node.submit_task(
+        &identity,
         service_id,
         task.1.as_bytes().to_vec(),
         requirements.clone()
@@ -166,22 +187,12 @@ async fn simulate_task_submission(node: &core::DuxNetNode) {
 async fn simulate_reputation_update(node: &core::DuxNetNode) {
     let target_did = format!("did:duxnet:user-{}", rand::random::<u32>());
     let score = (rand::random::<f64>() * 2.0) + 3.0; // 3.0-5.0 score
-    let interaction = vec!["service_provided", "task_completed", "escrow_finalized"];
+    let interaction = ["service_provided", "task_completed", "escrow_finalized"];
     let interaction_type = interaction[rand::random::<usize>() % interaction.len()];
     
-    let attestation = ReputationAttestation {
-        target_did: target_did.clone(),
-        attester_did: format!("did:duxnet:attester-{}", rand::random::<u32>()),
-        score,
-        interaction_type: interaction_type.to_string(),
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-        signature: vec![], // Simplified for simulation
-    };
+    let attestation = node.did_manager.create_attestation(target_did.clone(), score, interaction_type.to_string());
     
-    match node.reputation_system.add_attestation(attestation).await {
+    match node.add_reputation_attestation(attestation).await {
         Ok(_) => {
             info!("⭐ Simulated reputation update: {} -> {:.1}/5.0", target_did, score);
         }
@@ -0,0 +1,208 @@
+use crate::core::data_structures::NetworkMessage;
+use anyhow::Result;
+use bincode::Options;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upper bound on a single frame payload; anything larger is treated as a protocol error.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Size of the big-endian length prefix that precedes every frame.
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_FRAME_SIZE as u64)
+}
+
+pub fn encode_message(message: &NetworkMessage) -> Result<Vec<u8>> {
+    Ok(bincode_options().serialize(message)?)
+}
+
+pub fn decode_message(bytes: &[u8]) -> Result<NetworkMessage> {
+    Ok(bincode_options().deserialize(bytes)?)
+}
+
+/// Writes a single length-prefixed frame and flushes the writer.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> Result<()> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(anyhow::anyhow!("Frame too large: {} bytes (max {})", payload.len(), MAX_FRAME_SIZE));
+    }
+
+    writer.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a single length-prefixed frame. Returns `None` when the peer closed the
+/// stream cleanly on a frame boundary.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let length = u32::from_be_bytes(header) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(anyhow::anyhow!("Frame too large: {} bytes (max {})", length, MAX_FRAME_SIZE));
+    }
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_structures::*;
+    use crate::core::dht::tests::service;
+    use crate::core::dht::DHTEntry;
+    use crate::core::identity::DIDManager;
+    use std::collections::HashMap;
+
+    fn sample_messages() -> Vec<NetworkMessage> {
+        let service = ServiceMetadata { price: 200, reputation_score: 4.5, ..service("svc-1", "did:duxnet:provider") };
+        let record = DHTEntry::signed(
+            &DIDManager::new(vec![]),
+            "service:svc-1".to_string(),
+            serde_json::to_vec(&service).unwrap(),
+            3600,
+            1,
+        );
+        let task = Task {
+            id: TaskId("task-1".to_string()),
+            escrow_id: "escrow-1".to_string(),
+            service_id: ServiceId("svc-1".to_string()),
+            submitter_did: "did:duxnet:buyer".to_string(),
+            payload: vec![1, 2, 3],
+            requirements: TaskRequirements { cpu_cores: 2, memory_mb: 1024, timeout_seconds: 60 },
+            created_at: 2,
+        };
+        let result = TaskResult {
+            task_id: TaskId("task-1".to_string()),
+            processor_did: "did:duxnet:processor".to_string(),
+            result: vec![4, 5],
+            proof: vec![6],
+            completed_at: 3,
+            escrow_id: "escrow-1".to_string(),
+        };
+        let mut signatures = HashMap::new();
+        signatures.insert("did:duxnet:buyer".to_string(), vec![7u8; 64]);
+        let contract = EscrowContract {
+            id: "escrow-1".to_string(),
+            buyer_did: "did:duxnet:buyer".to_string(),
+            seller_did: "did:duxnet:seller".to_string(),
+            arbiters: vec!["did:duxnet:arbiter1".to_string()],
+            amount: 500,
+            state: EscrowState::Funded,
+            multisig_address: "multisig_escrow-1".to_string(),
+            signatures,
+            created_at: 4,
+        };
+        let attestation = ReputationAttestation {
+            attester_did: "did:duxnet:a".to_string(),
+            target_did: "did:duxnet:b".to_string(),
+            score: 4.25,
+            interaction_type: "task_completed".to_string(),
+            timestamp: 5,
+            signature: vec![8u8; 64],
+            interaction: Some(InteractionRef::Escrow("escrow-1".to_string())),
+        };
+        let message = Message {
+            id: "msg-1".to_string(),
+            from_did: "did:duxnet:a".to_string(),
+            to_did: "did:duxnet:b".to_string(),
+            content: "hello: world".to_string(),
+            message_type: MessageType::Text,
+            timestamp: 6,
+            signature: vec![9u8; 64],
+            is_read: false,
+            reply_to: Some("msg-0".to_string()),
+        };
+
+        vec![
+            NetworkMessage::ServiceAnnouncement(record),
+            NetworkMessage::ServiceQuery("image".to_string()),
+            NetworkMessage::ServiceResponse(vec![service]),
+            NetworkMessage::TaskSubmission(task),
+            NetworkMessage::TaskAcceptance(TaskId("task-1".to_string()), "did:duxnet:processor".to_string()),
+            NetworkMessage::TaskCompletion(result),
+            NetworkMessage::EscrowCreation(contract),
+            NetworkMessage::EscrowSignature("escrow-1".to_string(), "did:duxnet:buyer".to_string(), vec![1u8; 64]),
+            NetworkMessage::EscrowStateUpdate("escrow-1".to_string(), EscrowState::Completed),
+            NetworkMessage::ReputationAttestation(attestation.clone()),
+            NetworkMessage::ReputationQuery("did:duxnet:b".to_string()),
+            NetworkMessage::ReputationResponse("did:duxnet:b".to_string(), 4.25),
+            NetworkMessage::DirectMessage(message),
+            NetworkMessage::MessageAck("msg-1".to_string()),
+            NetworkMessage::MessageDelivery("msg-1".to_string()),
+            NetworkMessage::Ping,
+            NetworkMessage::Pong,
+            NetworkMessage::Gossip(GossipEnvelope {
+                message_id: "gossip-1".to_string(),
+                topic: "reputation".to_string(),
+                origin_did: "did:duxnet:a".to_string(),
+                hops: 1,
+                max_hops: 6,
+                payload: GossipPayload::ReputationAttestation(attestation),
+            }),
+            NetworkMessage::Request(7, Box::new(NetworkMessage::DhtFindValue("escrow:e1".to_string()))),
+            NetworkMessage::Response(7, Box::new(NetworkMessage::DhtStoreAck(true))),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_every_variant_roundtrips_through_frames() {
+        let messages = sample_messages();
+        let mut buffer = Vec::new();
+        for message in &messages {
+            write_frame(&mut buffer, &encode_message(message).unwrap()).await.unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        for expected in &messages {
+            let decoded = decode_message(&read_frame(&mut reader).await.unwrap().unwrap()).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", expected));
+        }
+        assert!(read_frame(&mut reader).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_nested_requests_are_rejected() {
+        let nested = NetworkMessage::Request(0, Box::new(NetworkMessage::Response(0, Box::new(NetworkMessage::Ping))));
+        assert!(decode_message(&encode_message(&nested).unwrap()).is_err());
+
+        // A frame nesting requests far deeper than the stack could follow.
+        let mut frame = Vec::new();
+        let level = encode_message(&NetworkMessage::Request(0, Box::new(NetworkMessage::Ping))).unwrap();
+        let prefix = &level[..level.len() - encode_message(&NetworkMessage::Ping).unwrap().len()];
+        for _ in 0..1_000_000 {
+            frame.extend_from_slice(prefix);
+        }
+        frame.extend(encode_message(&NetworkMessage::Ping).unwrap());
+        assert!(decode_message(&frame).is_err());
+
+        // The guard is reset for the next frame.
+        let request = NetworkMessage::Request(1, Box::new(NetworkMessage::Ping));
+        assert!(decode_message(&encode_message(&request).unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_is_rejected() {
+        let header = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+        let mut reader = &header[..];
+        assert!(read_frame(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_frame_is_an_error() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"partial payload").await.unwrap();
+        buffer.truncate(buffer.len() - 3);
+
+        let mut reader = buffer.as_slice();
+        assert!(read_frame(&mut reader).await.is_err());
+    }
+}
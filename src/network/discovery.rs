@@ -0,0 +1,229 @@
+use anyhow::Result;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use super::handshake::PROTOCOL_VERSION;
+
+/// UDP port LAN beacons are sent to and received on.
+pub const DEFAULT_DISCOVERY_PORT: u16 = 8082;
+/// Site-local multicast group every node on the subnet joins.
+pub const DISCOVERY_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 66, 78);
+/// How often a node announces itself.
+pub const DEFAULT_BEACON_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many beacon intervals a peer may go unheard before it is forgotten,
+/// and reported afresh should it come back.
+const SEEN_TTL_INTERVALS: u32 = 6;
+
+const BEACON_MAGIC: &[u8; 8] = b"DUXBEACN";
+const MAX_BEACON_SIZE: usize = 1024;
+
+/// What a node multicasts about itself. The sender's address is taken from the
+/// datagram, so only the P2P port travels in the beacon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryBeacon {
+    pub did: String,
+    pub p2p_port: u16,
+    pub protocol_version: u32,
+}
+
+/// A node seen on the LAN, ready to be dialed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredPeer {
+    pub did: String,
+    pub endpoint: String,
+}
+
+fn encode_beacon(beacon: &DiscoveryBeacon) -> Result<Vec<u8>> {
+    let mut bytes = BEACON_MAGIC.to_vec();
+    bytes.extend(bincode::DefaultOptions::new().with_limit(MAX_BEACON_SIZE as u64).serialize(beacon)?);
+    Ok(bytes)
+}
+
+fn decode_beacon(bytes: &[u8]) -> Result<DiscoveryBeacon> {
+    let body = bytes
+        .strip_prefix(BEACON_MAGIC.as_slice())
+        .ok_or_else(|| anyhow::anyhow!("Not a DuxNet beacon"))?;
+    Ok(bincode::DefaultOptions::new().with_limit(MAX_BEACON_SIZE as u64).deserialize(body)?)
+}
+
+/// Announces this node on the local network and reports the other nodes it hears.
+pub struct LanDiscovery {
+    socket: UdpSocket,
+    /// Where beacons are sent: the multicast group on the discovery port.
+    target: SocketAddr,
+    beacon: DiscoveryBeacon,
+    interval: Duration,
+}
+
+impl LanDiscovery {
+    /// Joins the discovery group on `port`. Several nodes on one machine can
+    /// share the port; each of them receives every beacon.
+    pub fn bind(port: u16, did: String, p2p_port: u16) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+        socket.join_multicast_v4(&DISCOVERY_MULTICAST_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+
+        let target = SocketAddrV4::new(DISCOVERY_MULTICAST_GROUP, port).into();
+        Ok(Self::from_socket(UdpSocket::from_std(socket.into())?, target, did, p2p_port))
+    }
+
+    fn from_socket(socket: UdpSocket, target: SocketAddr, did: String, p2p_port: u16) -> Self {
+        LanDiscovery {
+            socket,
+            target,
+            beacon: DiscoveryBeacon { did, p2p_port, protocol_version: PROTOCOL_VERSION },
+            interval: DEFAULT_BEACON_INTERVAL,
+        }
+    }
+
+    pub async fn announce(&self) -> Result<()> {
+        self.socket.send_to(&encode_beacon(&self.beacon)?, self.target).await?;
+        Ok(())
+    }
+
+    /// Beacons every interval and forwards each new sighting to `discovered`
+    /// until the receiver is dropped. A peer heard again is only forwarded if
+    /// its endpoint has changed or it had gone unheard long enough to be forgotten.
+    pub async fn run(self, discovered: mpsc::Sender<DiscoveredPeer>) {
+        info!("LAN discovery on {}", self.target);
+        let mut ticker = tokio::time::interval(self.interval);
+        let mut buffer = [0u8; MAX_BEACON_SIZE];
+        let seen_ttl = self.interval * SEEN_TTL_INTERVALS;
+        // Endpoint each peer was last heard at, and when.
+        let mut seen: HashMap<String, (String, Instant)> = HashMap::new();
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    seen.retain(|_, (_, heard)| heard.elapsed() < seen_ttl);
+                    if let Err(e) = self.announce().await {
+                        warn!("Failed to send discovery beacon: {}", e);
+                    }
+                }
+                received = self.socket.recv_from(&mut buffer) => {
+                    let (length, sender) = match received {
+                        Ok(received) => received,
+                        Err(e) => {
+                            warn!("Discovery socket error: {}", e);
+                            continue;
+                        }
+                    };
+                    let beacon = match decode_beacon(&buffer[..length]) {
+                        Ok(beacon) => beacon,
+                        Err(e) => {
+                            debug!("Ignoring datagram from {}: {}", sender, e);
+                            continue;
+                        }
+                    };
+                    if beacon.did == self.beacon.did {
+                        continue;
+                    }
+
+                    let peer = DiscoveredPeer {
+                        did: beacon.did,
+                        endpoint: format!("tcp://{}:{}", sender.ip(), beacon.p2p_port),
+                    };
+                    let known = seen
+                        .get(&peer.did)
+                        .is_some_and(|(endpoint, heard)| *endpoint == peer.endpoint && heard.elapsed() < seen_ttl);
+                    seen.insert(peer.did.clone(), (peer.endpoint.clone(), Instant::now()));
+                    if known {
+                        continue;
+                    }
+                    if discovered.send(peer).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beacon_roundtrip_and_foreign_datagrams() {
+        let beacon = DiscoveryBeacon { did: "did:duxnet:abc".to_string(), p2p_port: 9000, protocol_version: 1 };
+        let decoded = decode_beacon(&encode_beacon(&beacon).unwrap()).unwrap();
+        assert_eq!(decoded.did, beacon.did);
+        assert_eq!(decoded.p2p_port, 9000);
+
+        assert!(decode_beacon(b"M-SEARCH * HTTP/1.1").is_err());
+    }
+
+    /// A node whose beacons go straight to `target` over loopback.
+    async fn unicast(did: &str, p2p_port: u16, interval: Duration) -> (LanDiscovery, SocketAddr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let mut node = LanDiscovery::from_socket(socket, address, did.to_string(), p2p_port);
+        node.interval = interval;
+        (node, address)
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_peers_once_until_they_go_quiet() {
+        let interval = Duration::from_millis(20);
+        let (mut a, a_address) = unicast("did:duxnet:a", 9001, interval).await;
+        let (mut b, b_address) = unicast("did:duxnet:b", 9002, interval).await;
+        a.target = b_address;
+        b.target = a_address;
+
+        let (a_tx, mut a_rx) = mpsc::channel(16);
+        tokio::spawn(a.run(a_tx));
+        let (b_tx, _b_rx) = mpsc::channel(16);
+        let b_task = tokio::spawn(b.run(b_tx));
+
+        let peer = tokio::time::timeout(Duration::from_secs(2), a_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(peer, DiscoveredPeer { did: "did:duxnet:b".to_string(), endpoint: "tcp://127.0.0.1:9002".to_string() });
+        // Later beacons from the same peer are not reported again.
+        assert!(tokio::time::timeout(interval * 4, a_rx.recv()).await.is_err());
+
+        // Once b has been quiet long enough it is forgotten, and reported
+        // again when it comes back.
+        b_task.abort();
+        tokio::time::sleep(interval * (SEEN_TTL_INTERVALS + 2)).await;
+        let (mut b, _) = unicast("did:duxnet:b", 9002, interval).await;
+        b.target = a_address;
+        let (b_tx, _b_rx) = mpsc::channel(16);
+        tokio::spawn(b.run(b_tx));
+        let peer = tokio::time::timeout(Duration::from_secs(2), a_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(peer.did, "did:duxnet:b");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a host that delivers multicast datagrams"]
+    async fn test_nodes_sharing_a_port_hear_each_other() {
+        // Grab a free port, then let both nodes bind it.
+        let port = std::net::UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let mut a = LanDiscovery::bind(port, "did:duxnet:a".to_string(), 9001).unwrap();
+        let mut b = LanDiscovery::bind(port, "did:duxnet:b".to_string(), 9002).unwrap();
+
+        let (a_tx, mut a_rx) = mpsc::channel(16);
+        let (b_tx, _b_rx) = mpsc::channel(16);
+        let interval = Duration::from_millis(50);
+        (a.interval, b.interval) = (interval, interval);
+        tokio::spawn(a.run(a_tx));
+        tokio::spawn(b.run(b_tx));
+
+        let peer = tokio::time::timeout(Duration::from_secs(2), a_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(peer.did, "did:duxnet:b");
+        assert!(peer.endpoint.ends_with(":9002"), "{}", peer.endpoint);
+        // Later beacons from the same peer are not reported again.
+        assert!(tokio::time::timeout(interval * 5, a_rx.recv()).await.is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::{Mutex, RwLock};
+
+/// Number of peers a message is forwarded to per topic.
+pub const DEFAULT_MESH_SIZE: usize = 6;
+/// Hops a message may travel before relays stop forwarding it.
+pub const DEFAULT_MAX_HOPS: u8 = 6;
+/// Message ids remembered for deduplication.
+pub const DEFAULT_SEEN_CACHE_SIZE: usize = 8192;
+
+#[derive(Debug, Clone)]
+pub struct GossipConfig {
+    pub mesh_size: usize,
+    pub max_hops: u8,
+    pub seen_cache_size: usize,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        GossipConfig {
+            mesh_size: DEFAULT_MESH_SIZE,
+            max_hops: DEFAULT_MAX_HOPS,
+            seen_cache_size: DEFAULT_SEEN_CACHE_SIZE,
+        }
+    }
+}
+
+/// Bounded set of recently seen message ids; the oldest id is forgotten first.
+struct SeenCache {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenCache {
+    fn new(capacity: usize) -> Self {
+        SeenCache { ids: HashSet::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// Records `id` and returns `true` if it had not been seen before.
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+/// Local gossip state: deduplication and the per-topic mesh of peers we forward to.
+pub struct Gossip {
+    config: GossipConfig,
+    seen: Mutex<SeenCache>,
+    meshes: RwLock<HashMap<String, Vec<String>>>,
+}
+
+impl Gossip {
+    pub fn new(config: GossipConfig) -> Self {
+        let seen = Mutex::new(SeenCache::new(config.seen_cache_size));
+        Gossip {
+            config,
+            seen,
+            meshes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &GossipConfig {
+        &self.config
+    }
+
+    /// Returns `true` the first time a message id is seen.
+    pub async fn mark_seen(&self, message_id: &str) -> bool {
+        self.seen.lock().await.insert(message_id)
+    }
+
+    /// Picks the peers to forward a `topic` message to. The mesh is kept stable
+    /// between calls: members that are no longer candidates are dropped and the
+    /// gap is filled with random candidates.
+    pub async fn mesh_peers(&self, topic: &str, candidates: &[String], exclude: &[&str]) -> Vec<String> {
+        let mut meshes = self.meshes.write().await;
+        let mesh = meshes.entry(topic.to_string()).or_default();
+        mesh.retain(|peer| candidates.contains(peer));
+
+        if mesh.len() < self.config.mesh_size {
+            let mut fresh: Vec<&String> = candidates.iter().filter(|peer| !mesh.contains(peer)).collect();
+            fresh.shuffle(&mut rand::thread_rng());
+            let missing = self.config.mesh_size - mesh.len();
+            mesh.extend(fresh.into_iter().take(missing).cloned());
+        }
+
+        mesh.iter()
+            .filter(|peer| !exclude.contains(&peer.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seen_cache_dedups_and_forgets_oldest() {
+        let gossip = Gossip::new(GossipConfig { seen_cache_size: 2, ..GossipConfig::default() });
+        assert!(gossip.mark_seen("a").await);
+        assert!(!gossip.mark_seen("a").await);
+        assert!(gossip.mark_seen("b").await);
+        assert!(gossip.mark_seen("c").await);
+        // "a" was evicted to make room for "c".
+        assert!(gossip.mark_seen("a").await);
+    }
+
+    #[tokio::test]
+    async fn test_mesh_is_bounded_and_stable() {
+        let gossip = Gossip::new(GossipConfig { mesh_size: 3, ..GossipConfig::default() });
+        let peers: Vec<String> = (0..10).map(|i| format!("peer-{}", i)).collect();
+
+        let first = gossip.mesh_peers("services", &peers, &[]).await;
+        assert_eq!(first.len(), 3);
+        assert_eq!(gossip.mesh_peers("services", &peers, &[]).await, first);
+
+        // Losing a mesh member pulls in a replacement.
+        let remaining: Vec<String> = peers.iter().filter(|p| **p != first[0]).cloned().collect();
+        let second = gossip.mesh_peers("services", &remaining, &[]).await;
+        assert_eq!(second.len(), 3);
+        assert!(!second.contains(&first[0]));
+
+        let without_sender = gossip.mesh_peers("services", &remaining, &[second[0].as_str()]).await;
+        assert_eq!(without_sender.len(), 2);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::core::data_structures::DID;
+use crate::core::did::{DidDocument, DidUpdate};
+use crate::core::identity::DIDManager;
+use anyhow::Result;
+use bincode::Options;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::codec;
+use super::session::SecureChannel;
+
+/// Wire protocol version spoken by this build.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest protocol version this build can still talk to. Version 2 added the
+/// DID's update chain to `Hello`.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Domain separator for the proof-of-possession signature.
+const HANDSHAKE_DOMAIN: &[u8] = b"duxnet/handshake/v1";
+const NONCE_SIZE: usize = 32;
+
+/// Capabilities advertised by default: one per pub/sub topic.
+pub fn default_capabilities() -> Vec<String> {
+    ["services", "tasks", "escrow", "reputation", "messaging"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HandshakeMessage {
+    Hello {
+        protocol_version: u32,
+        min_protocol_version: u32,
+        did: DID,
+        /// The DID's update chain, leading from the key it was derived from to `did.public_key`.
+        updates: Vec<DidUpdate>,
+        nonce: Vec<u8>,
+        capabilities: Vec<String>,
+        /// Port the sender accepts connections on, or 0 if it is not listening.
+        listen_port: u16,
+    },
+    Proof {
+        signature: Vec<u8>,
+    },
+    Reject {
+        reason: String,
+    },
+}
+
+/// What we learned about the remote side once the handshake succeeded.
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    pub did: DID,
+    /// Replayed from the update chain the peer presented.
+    pub document: DidDocument,
+    pub capabilities: Vec<String>,
+    pub listen_port: u16,
+}
+
+fn encode(message: &HandshakeMessage) -> Result<Vec<u8>> {
+    Ok(bincode::DefaultOptions::new()
+        .with_limit(codec::MAX_FRAME_SIZE as u64)
+        .serialize(message)?)
+}
+
+fn decode(bytes: &[u8]) -> Result<HandshakeMessage> {
+    Ok(bincode::DefaultOptions::new()
+        .with_limit(codec::MAX_FRAME_SIZE as u64)
+        .deserialize(bytes)?)
+}
+
+async fn send<S>(channel: &mut SecureChannel<S>, message: &HandshakeMessage) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    channel.send_frame(&encode(message)?).await
+}
+
+async fn receive<S>(channel: &mut SecureChannel<S>) -> Result<HandshakeMessage>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match channel.recv_frame().await? {
+        Some(frame) => match decode(&frame)? {
+            HandshakeMessage::Reject { reason } => {
+                Err(anyhow::anyhow!("Peer rejected handshake: {}", reason))
+            }
+            message => Ok(message),
+        },
+        None => Err(anyhow::anyhow!("Peer closed the connection during handshake")),
+    }
+}
+
+/// Bytes signed by `signer_did` to prove it holds its key: the session's handshake
+/// hash, both nonces and the signer's DID. Covering the handshake hash ties the
+/// encrypted session to the DID key, so a relaying man-in-the-middle cannot reuse it.
+fn proof_payload(session_hash: &[u8], challenge: &[u8], own_nonce: &[u8], signer_did: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(HANDSHAKE_DOMAIN);
+    hasher.update(session_hash);
+    hasher.update(challenge);
+    hasher.update(own_nonce);
+    hasher.update(signer_did.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// Picks the protocol version both sides will speak, or explains why there is none.
+pub fn negotiate_version(peer_version: u32, peer_min_version: u32) -> Result<u32> {
+    let negotiated = PROTOCOL_VERSION.min(peer_version);
+    if negotiated < MIN_PROTOCOL_VERSION || negotiated < peer_min_version {
+        return Err(anyhow::anyhow!(
+            "Incompatible protocol version: we speak {}-{}, peer speaks {}-{}",
+            MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, peer_min_version, peer_version
+        ));
+    }
+    Ok(negotiated)
+}
+
+/// Runs the symmetric DID handshake over an established session. Both sides send
+/// a `Hello` carrying their DID and a fresh nonce, then sign the other side's nonce
+/// together with the session's handshake hash.
+pub async fn perform_handshake<S>(
+    stream: &mut SecureChannel<S>,
+    identity: &DIDManager,
+    capabilities: &[String],
+    listen_port: u16,
+) -> Result<PeerIdentity>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut nonce = vec![0u8; NONCE_SIZE];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    send(stream, &HandshakeMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        did: identity.did.clone(),
+        updates: identity.updates.clone(),
+        nonce: nonce.clone(),
+        capabilities: capabilities.to_vec(),
+        listen_port,
+    }).await?;
+
+    let (peer_did, peer_updates, peer_nonce, peer_capabilities, peer_listen_port) = match receive(stream).await? {
+        HandshakeMessage::Hello { protocol_version, min_protocol_version, did, updates, nonce, capabilities, listen_port } => {
+            if let Err(e) = negotiate_version(protocol_version, min_protocol_version) {
+                let _ = send(stream, &HandshakeMessage::Reject { reason: e.to_string() }).await;
+                return Err(e);
+            }
+            (did, updates, nonce, capabilities, listen_port)
+        }
+        other => return Err(anyhow::anyhow!("Expected Hello, got {:?}", other)),
+    };
+
+    if peer_nonce.len() != NONCE_SIZE {
+        return Err(anyhow::anyhow!("Invalid handshake nonce length: {}", peer_nonce.len()));
+    }
+    // A rotated DID no longer derives from its key; its update chain vouches for it.
+    let peer_document = match DidDocument::presented(&peer_did, &peer_updates) {
+        Ok(document) => document,
+        Err(e) => {
+            let _ = send(stream, &HandshakeMessage::Reject { reason: e.to_string() }).await;
+            return Err(e);
+        }
+    };
+    if peer_did.id == identity.did.id {
+        return Err(anyhow::anyhow!("Refusing to connect to ourselves"));
+    }
+
+    let session_hash = *stream.handshake_hash();
+    let signature = identity.sign_message(&proof_payload(&session_hash, &peer_nonce, &nonce, &identity.did.id));
+    send(stream, &HandshakeMessage::Proof { signature }).await?;
+
+    match receive(stream).await? {
+        HandshakeMessage::Proof { signature } => {
+            let expected = proof_payload(&session_hash, &nonce, &peer_nonce, &peer_did.id);
+            if !DIDManager::verify_with_public_key(&peer_did.public_key, &signature, &expected) {
+                return Err(anyhow::anyhow!("Invalid proof of possession for {}", peer_did.id));
+            }
+        }
+        other => return Err(anyhow::anyhow!("Expected Proof, got {:?}", other)),
+    }
+
+    Ok(PeerIdentity {
+        did: peer_did,
+        document: peer_document,
+        capabilities: peer_capabilities,
+        listen_port: peer_listen_port,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use tokio::io::DuplexStream;
+    use x25519_dalek::StaticSecret;
+
+    async fn session_pair() -> (SecureChannel<DuplexStream>, SecureChannel<DuplexStream>) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let (a_key, b_key) = (StaticSecret::random_from_rng(OsRng), StaticSecret::random_from_rng(OsRng));
+        let (a, b) = tokio::join!(SecureChannel::initiate(a, &a_key), SecureChannel::respond(b, &b_key));
+        (a.unwrap(), b.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_identifies_both_sides() {
+        let alice = DIDManager::new(vec![]);
+        let bob = DIDManager::new(vec![]);
+        let (mut a, mut b) = session_pair().await;
+
+        let caps = default_capabilities();
+        let (a_view, b_view) = tokio::join!(
+            perform_handshake(&mut a, &alice, &caps, 4000),
+            perform_handshake(&mut b, &bob, &caps, 0),
+        );
+
+        let a_view = a_view.unwrap();
+        let b_view = b_view.unwrap();
+        assert_eq!(a_view.did.id, bob.did.id);
+        assert_eq!(b_view.did.id, alice.did.id);
+        assert_eq!(a_view.capabilities, caps);
+        assert_eq!(b_view.listen_port, 4000);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_forged_did() {
+        let alice = DIDManager::new(vec![]);
+        let mut mallory = DIDManager::new(vec![]);
+        // Claim alice's DID and public key without holding her private key.
+        mallory.did = alice.did.clone();
+        let bob = DIDManager::new(vec![]);
+        let (mut m, mut b) = session_pair().await;
+
+        let caps = default_capabilities();
+        let (_, b_view) = tokio::join!(
+            perform_handshake(&mut m, &mallory, &caps, 0),
+            perform_handshake(&mut b, &bob, &caps, 0),
+        );
+
+        let err = b_view.unwrap_err().to_string();
+        assert!(err.contains("Invalid proof of possession"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_accepts_rotated_keys_through_their_update_chain() {
+        let mut alice = DIDManager::new(vec![]);
+        alice.rotate_key(true).unwrap();
+        let bob = DIDManager::new(vec![]);
+        let (mut a, mut b) = session_pair().await;
+
+        let caps = default_capabilities();
+        let (a_view, b_view) = tokio::join!(
+            perform_handshake(&mut a, &alice, &caps, 0),
+            perform_handshake(&mut b, &bob, &caps, 0),
+        );
+        a_view.unwrap();
+        let b_view = b_view.unwrap();
+        assert_eq!(b_view.did.id, alice.did.id);
+        assert_eq!(b_view.document.current_key().public_key, alice.get_public_key());
+
+        // Without the chain, the new key does not vouch for the DID.
+        let mut unchained = alice.clone();
+        unchained.updates.clear();
+        let (mut a, mut b) = session_pair().await;
+        let (_, b_view) = tokio::join!(
+            perform_handshake(&mut a, &unchained, &caps, 0),
+            perform_handshake(&mut b, &bob, &caps, 0),
+        );
+        assert!(b_view.is_err());
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        assert_eq!(negotiate_version(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION).unwrap(), PROTOCOL_VERSION);
+        // A newer peer that still supports our version talks our version.
+        assert_eq!(negotiate_version(PROTOCOL_VERSION + 3, PROTOCOL_VERSION).unwrap(), PROTOCOL_VERSION);
+
+        let err = negotiate_version(PROTOCOL_VERSION + 3, PROTOCOL_VERSION + 1).unwrap_err();
+        assert!(err.to_string().contains("Incompatible protocol version"));
+        assert!(negotiate_version(MIN_PROTOCOL_VERSION - 1, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_peer_gets_clear_rejection() {
+        let alice = DIDManager::new(vec![]);
+        let (mut a, mut b) = session_pair().await;
+
+        let future_peer = async {
+            let mut nonce = vec![0u8; NONCE_SIZE];
+            rand::rngs::OsRng.fill_bytes(&mut nonce);
+            let bob = DIDManager::new(vec![]);
+            send(&mut b, &HandshakeMessage::Hello {
+                protocol_version: PROTOCOL_VERSION + 5,
+                min_protocol_version: PROTOCOL_VERSION + 5,
+                did: bob.did.clone(),
+                updates: vec![],
+                nonce,
+                capabilities: vec![],
+                listen_port: 0,
+            }).await.unwrap();
+            // Skip alice's Hello, then expect her rejection.
+            b.recv_frame().await.unwrap();
+            let frame = b.recv_frame().await.unwrap().unwrap();
+            decode(&frame).unwrap()
+        };
+
+        let caps = default_capabilities();
+        let (result, rejection) = tokio::join!(
+            perform_handshake(&mut a, &alice, &caps, 0),
+            future_peer,
+        );
+        assert!(result.unwrap_err().to_string().contains("Incompatible protocol version"));
+        assert!(matches!(rejection, HandshakeMessage::Reject { reason } if reason.contains("Incompatible")));
+    }
+}
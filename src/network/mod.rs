@@ -1,23 +1,101 @@
+pub mod codec;
+pub mod discovery;
+pub mod gossip;
+pub mod handshake;
+pub mod session;
+
 use crate::core::data_structures::*;
+use crate::core::dht::{DHTEntry, DhtTransport, FindValueResult};
+use crate::core::did::DidDocument;
+use crate::core::identity::DIDManager;
 use anyhow::Result;
+use gossip::{Gossip, GossipConfig};
+use handshake::PeerIdentity;
+use rand::rngs::OsRng;
+use session::{SecureChannel, SecureReader, SecureWriter};
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use x25519_dalek::StaticSecret;
+
+/// Capacity of each peer's outbound queue.
+const OUTBOUND_QUEUE_SIZE: usize = 256;
+/// Capacity of the shared inbound queue drained by `next_event`.
+const INBOUND_QUEUE_SIZE: usize = 1024;
+/// How long a peer gets to complete the handshake before we hang up.
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long `request` waits for the matching response.
+pub const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A message received from a connected peer.
+#[derive(Debug, Clone)]
+pub struct InboundMessage {
+    pub peer_id: String,
+    pub message: NetworkMessage,
+    /// Set when the peer expects a reply through `P2PNetwork::respond`.
+    pub request_id: Option<u64>,
+}
+
+struct PeerConnection {
+    id: u64,
+    address: SocketAddr,
+    identity: PeerIdentity,
+    outbound: mpsc::Sender<NetworkMessage>,
+    reader: JoinHandle<()>,
+    writer: JoinHandle<()>,
+}
+
+/// Checks the DID document a peer presented in the handshake against what
+/// the node knows about that DID, before the connection is accepted.
+#[async_trait::async_trait]
+pub trait PeerVerifier: Send + Sync {
+    async fn verify_peer(&self, presented: &DidDocument) -> Result<()>;
+}
+
+/// Shared state needed to bring up and tear down peer connections.
+#[derive(Clone)]
+struct ConnectionContext {
+    identity: DIDManager,
+    /// X25519 key authenticated by the session handshake; regenerated on every start of the node.
+    session_key: Arc<StaticSecret>,
+    capabilities: Vec<String>,
+    connections: Arc<RwLock<HashMap<String, PeerConnection>>>,
+    connected_peers: Arc<RwLock<Vec<String>>>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+    next_connection_id: Arc<AtomicU64>,
+    gossip: Arc<Gossip>,
+    /// Port we accept connections on, advertised in the handshake; 0 until `start`.
+    listen_port: Arc<AtomicU16>,
+    pending_requests: Arc<Mutex<HashMap<u64, oneshot::Sender<NetworkMessage>>>>,
+    next_request_id: Arc<AtomicU64>,
+    /// Set once the node can resolve DIDs; see `P2PNetwork::set_peer_verifier`.
+    verifier: Arc<RwLock<Option<Arc<dyn PeerVerifier>>>>,
+}
 
 pub struct P2PNetwork {
     pub local_peer_id: String,
+    pub listen_port: u16,
     pub topics: Arc<RwLock<HashMap<String, String>>>,
     pub is_running: Arc<RwLock<bool>>,
     pub connected_peers: Arc<RwLock<Vec<String>>>,
+    context: ConnectionContext,
+    local_addr: Arc<RwLock<Option<SocketAddr>>>,
+    listener_task: Mutex<Option<JoinHandle<()>>>,
+    inbound_rx: Mutex<mpsc::Receiver<InboundMessage>>,
 }
 
 impl P2PNetwork {
-    pub async fn new(port: u16) -> Result<Self> {
-        let local_peer_id = format!("peer_{}", port);
-        
+    pub async fn new(port: u16, identity: DIDManager) -> Result<Self> {
+        let local_peer_id = identity.did.id.clone();
+
         info!("Local peer ID: {:?}", local_peer_id);
-        
+
         let topics = Arc::new(RwLock::new(HashMap::new()));
         {
             let mut topics_guard = topics.write().await;
@@ -27,68 +105,240 @@ impl P2PNetwork {
             topics_guard.insert("reputation".to_string(), "reputation".to_string());
             topics_guard.insert("messaging".to_string(), "messaging".to_string());
         }
-        
+
         let is_running = Arc::new(RwLock::new(false));
         let connected_peers = Arc::new(RwLock::new(Vec::new()));
-        
+        let (inbound_tx, inbound_rx) = mpsc::channel(INBOUND_QUEUE_SIZE);
+        let context = ConnectionContext {
+            identity,
+            session_key: Arc::new(StaticSecret::random_from_rng(OsRng)),
+            capabilities: handshake::default_capabilities(),
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            connected_peers: connected_peers.clone(),
+            inbound_tx,
+            next_connection_id: Arc::new(AtomicU64::new(0)),
+            gossip: Arc::new(Gossip::new(GossipConfig::default())),
+            listen_port: Arc::new(AtomicU16::new(0)),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            next_request_id: Arc::new(AtomicU64::new(0)),
+            verifier: Arc::new(RwLock::new(None)),
+        };
+
         Ok(P2PNetwork {
             local_peer_id,
+            listen_port: port,
             topics,
             is_running,
             connected_peers,
+            context,
+            local_addr: Arc::new(RwLock::new(None)),
+            listener_task: Mutex::new(None),
+            inbound_rx: Mutex::new(inbound_rx),
         })
     }
-    
+
+    /// Has every peer checked by `verifier` once its handshake succeeds. The
+    /// DID resolver needs the DHT, which needs the network, so it comes later.
+    pub async fn set_peer_verifier(&self, verifier: Arc<dyn PeerVerifier>) {
+        *self.context.verifier.write().await = Some(verifier);
+    }
+
     pub async fn start(&self) -> Result<()> {
         info!("Starting P2P network...");
+
+        let listener = TcpListener::bind(("0.0.0.0", self.listen_port)).await?;
+        let local_addr = listener.local_addr()?;
+        *self.local_addr.write().await = Some(local_addr);
+        self.context.listen_port.store(local_addr.port(), Ordering::Relaxed);
+        info!("P2P network listening on {}", local_addr);
+
+        let context = self.context.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, address)) => {
+                        debug!("Accepted connection from {}", address);
+                        // Handshake off the accept loop so a slow peer cannot stall it.
+                        let context = context.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = context.establish(stream, address, false).await {
+                                warn!("Inbound connection from {} failed: {}", address, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                    }
+                }
+            }
+        });
+        *self.listener_task.lock().await = Some(handle);
+
         {
             let mut running = self.is_running.write().await;
             *running = true;
         }
         Ok(())
     }
-    
+
     pub async fn stop(&self) -> Result<()> {
         info!("Stopping P2P network...");
         {
             let mut running = self.is_running.write().await;
             *running = false;
         }
+
+        if let Some(handle) = self.listener_task.lock().await.take() {
+            handle.abort();
+        }
+
+        let mut connections = self.context.connections.write().await;
+        for (_, connection) in connections.drain() {
+            connection.reader.abort();
+            connection.writer.abort();
+        }
+        self.connected_peers.write().await.clear();
+        *self.local_addr.write().await = None;
+        self.context.listen_port.store(0, Ordering::Relaxed);
+        self.context.pending_requests.lock().await.clear();
         Ok(())
     }
-    
-    pub async fn process_events(&self) -> Result<()> {
-        // Check if we should stop
+
+    /// Address the listener is bound to, once `start` has been called.
+    pub async fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.read().await
+    }
+
+    /// Dials a peer at `tcp://host:port` (or plain `host:port`), sets up an
+    /// encrypted session, runs the DID handshake and returns the peer's DID.
+    pub async fn connect(&self, endpoint: &str) -> Result<String> {
+        let address = endpoint.strip_prefix("tcp://").unwrap_or(endpoint);
+        let remote = tokio::net::lookup_host(address)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Could not resolve peer address: {}", endpoint))?;
+
         {
-            let running = self.is_running.read().await;
-            if !*running {
-                return Ok(());
+            let connections = self.context.connections.read().await;
+            if let Some((peer_id, _)) = connections.iter().find(|(_, c)| c.address == remote) {
+                return Ok(peer_id.clone());
             }
         }
-        
-        // Mock event processing
-        debug!("Processing network events...");
-        
+
+        let stream = TcpStream::connect(remote).await?;
+        let peer_id = self.context.establish(stream, remote, true).await?;
+        info!("Connected to peer {}", peer_id);
+        Ok(peer_id)
+    }
+
+    /// Waits for the next inbound message.
+    pub async fn next_event(&self) -> Option<InboundMessage> {
+        self.inbound_rx.lock().await.recv().await
+    }
+
+    pub async fn send_to(&self, peer_id: &str, message: NetworkMessage) -> Result<()> {
+        let outbound = {
+            let connections = self.context.connections.read().await;
+            match connections.get(peer_id) {
+                Some(connection) => connection.outbound.clone(),
+                None => return Err(anyhow::anyhow!("Peer not connected: {}", peer_id)),
+            }
+        };
+
+        outbound
+            .send(message)
+            .await
+            .map_err(|_| anyhow::anyhow!("Connection to {} is closed", peer_id))
+    }
+
+    /// Sends `message` as a request and waits for the peer's response.
+    pub async fn request(&self, peer_id: &str, message: NetworkMessage) -> Result<NetworkMessage> {
+        let request_id = self.context.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (response_tx, response_rx) = oneshot::channel();
+        self.context.pending_requests.lock().await.insert(request_id, response_tx);
+
+        let result = async {
+            self.send_to(peer_id, NetworkMessage::Request(request_id, Box::new(message))).await?;
+            match tokio::time::timeout(REQUEST_TIMEOUT, response_rx).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(_)) => Err(anyhow::anyhow!("Connection to {} closed before it responded", peer_id)),
+                Err(_) => Err(anyhow::anyhow!("Request to {} timed out", peer_id)),
+            }
+        }
+        .await;
+
+        if result.is_err() {
+            self.context.pending_requests.lock().await.remove(&request_id);
+        }
+        result
+    }
+
+    /// Answers a request received as an `InboundMessage` with a `request_id`.
+    pub async fn respond(&self, peer_id: &str, request_id: u64, message: NetworkMessage) -> Result<()> {
+        self.send_to(peer_id, NetworkMessage::Response(request_id, Box::new(message))).await
+    }
+
+    /// Where a connected peer accepts connections: the address we see it at and
+    /// the port it advertised. `None` if it is not listening.
+    pub async fn peer_contact(&self, peer_id: &str) -> Option<PeerContact> {
+        let connections = self.context.connections.read().await;
+        let connection = connections.get(peer_id)?;
+        if connection.identity.listen_port == 0 {
+            return None;
+        }
+        Some(PeerContact {
+            did: peer_id.to_string(),
+            endpoint: format!("tcp://{}:{}", connection.address.ip(), connection.identity.listen_port),
+        })
+    }
+
+    /// Makes sure we have a connection to `contact`, dialing it if needed.
+    async fn ensure_connected(&self, contact: &PeerContact) -> Result<()> {
+        if self.context.connections.read().await.contains_key(&contact.did) {
+            return Ok(());
+        }
+        let peer_id = self.connect(&contact.endpoint).await?;
+        if peer_id != contact.did {
+            return Err(anyhow::anyhow!("{} is served by {}, not {}", contact.endpoint, peer_id, contact.did));
+        }
         Ok(())
     }
-    
-    pub async fn publish_message(&self, topic_name: &str, message: &NetworkMessage) -> Result<()> {
+
+    /// Gossips a message to every subscriber of `topic` across the network.
+    pub async fn publish_message(&self, topic_name: &str, payload: GossipPayload) -> Result<()> {
+        self.ensure_topic(topic_name).await?;
+
+        let envelope = GossipEnvelope {
+            message_id: uuid::Uuid::new_v4().to_string(),
+            topic: topic_name.to_string(),
+            origin_did: self.local_peer_id.clone(),
+            hops: 0,
+            max_hops: self.context.gossip.config().max_hops,
+            payload,
+        };
+        self.context.gossip.mark_seen(&envelope.message_id).await;
+        let delivered = self.context.relay(&envelope, None).await;
+        debug!("Published message to topic {} ({} peers)", topic_name, delivered);
+        Ok(())
+    }
+
+    async fn ensure_topic(&self, topic_name: &str) -> Result<()> {
         let topics = self.topics.read().await;
-        if topics.contains_key(topic_name) {
-            debug!("Published message to topic: {}", topic_name);
+        if !topics.contains_key(topic_name) {
+            return Err(anyhow::anyhow!("Unknown topic: {}", topic_name));
         }
         Ok(())
     }
-    
+
     pub async fn get_peers(&self) -> Vec<String> {
         let peers = self.connected_peers.read().await;
         peers.clone()
     }
-    
+
     pub async fn get_stats(&self) -> NetworkStats {
         let peers = self.get_peers().await;
         let topics = self.topics.read().await;
-        
+
         NetworkStats {
             local_peer_id: self.local_peer_id.clone(),
             connected_peers: peers.len(),
@@ -97,9 +347,423 @@ impl P2PNetwork {
     }
 }
 
+#[async_trait::async_trait]
+impl DhtTransport for P2PNetwork {
+    async fn ping(&self, peer_id: &str) -> bool {
+        matches!(self.request(peer_id, NetworkMessage::Ping).await, Ok(NetworkMessage::Pong))
+    }
+
+    async fn find_node(&self, contact: &PeerContact, key: &str) -> Result<Vec<PeerContact>> {
+        self.ensure_connected(contact).await?;
+        match self.request(&contact.did, NetworkMessage::DhtFindNode(key.to_string())).await? {
+            NetworkMessage::DhtNodes(contacts) => Ok(contacts),
+            other => Err(anyhow::anyhow!("Unexpected reply to FIND_NODE: {:?}", other)),
+        }
+    }
+
+    async fn find_value(&self, contact: &PeerContact, key: &str) -> Result<FindValueResult> {
+        self.ensure_connected(contact).await?;
+        match self.request(&contact.did, NetworkMessage::DhtFindValue(key.to_string())).await? {
+            NetworkMessage::DhtValue(entry) => Ok(FindValueResult::Found(entry)),
+            NetworkMessage::DhtNodes(contacts) => Ok(FindValueResult::Closer(contacts)),
+            other => Err(anyhow::anyhow!("Unexpected reply to FIND_VALUE: {:?}", other)),
+        }
+    }
+
+    async fn store(&self, contact: &PeerContact, entry: &DHTEntry) -> Result<bool> {
+        self.ensure_connected(contact).await?;
+        match self.request(&contact.did, NetworkMessage::DhtStore(entry.clone())).await? {
+            NetworkMessage::DhtStoreAck(accepted) => Ok(accepted),
+            other => Err(anyhow::anyhow!("Unexpected reply to STORE: {:?}", other)),
+        }
+    }
+}
+
+impl ConnectionContext {
+    /// Runs the session and DID handshakes on a fresh stream and, if they succeed,
+    /// registers the connection under the peer's DID.
+    async fn establish(&self, stream: TcpStream, address: SocketAddr, outbound: bool) -> Result<String> {
+        let _ = stream.set_nodelay(true);
+        let (identity, channel) = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            let mut channel = if outbound {
+                SecureChannel::initiate(stream, &self.session_key).await?
+            } else {
+                SecureChannel::respond(stream, &self.session_key).await?
+            };
+            let listen_port = self.listen_port.load(Ordering::Relaxed);
+            let identity = handshake::perform_handshake(&mut channel, &self.identity, &self.capabilities, listen_port).await?;
+            Ok::<_, anyhow::Error>((identity, channel))
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Handshake with {} timed out", address))??;
+        let verifier = self.verifier.read().await.clone();
+        if let Some(verifier) = verifier {
+            verifier.verify_peer(&identity.document).await?;
+        }
+
+        let peer_id = identity.did.id.clone();
+        // When both sides dial each other at once, keep the connection opened by
+        // the smaller DID so both ends make the same choice.
+        let dialer = if outbound { &self.identity.did.id } else { &peer_id };
+        let preferred = dialer == std::cmp::min(&self.identity.did.id, &peer_id);
+
+        let (stream, send, recv) = channel.into_parts();
+        let (read_half, write_half) = stream.into_split();
+        let reader = SecureReader::new(read_half, recv);
+        let writer = SecureWriter::new(write_half, send);
+        let mut connections = self.connections.write().await;
+        if let Some(existing) = connections.get(&peer_id) {
+            if !preferred {
+                debug!("Dropping duplicate connection to {}", peer_id);
+                return Ok(peer_id);
+            }
+            existing.reader.abort();
+            existing.writer.abort();
+        }
+
+        let connection = self.spawn_connection(peer_id.clone(), address, identity, reader, writer);
+        connections.insert(peer_id.clone(), connection);
+        drop(connections);
+
+        let mut peers = self.connected_peers.write().await;
+        if !peers.contains(&peer_id) {
+            peers.push(peer_id.clone());
+        }
+        Ok(peer_id)
+    }
+
+    fn spawn_connection(
+        &self,
+        peer_id: String,
+        address: SocketAddr,
+        identity: PeerIdentity,
+        mut reader: SecureReader<OwnedReadHalf>,
+        mut writer: SecureWriter<OwnedWriteHalf>,
+    ) -> PeerConnection {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let (outbound_tx, mut outbound_rx) = mpsc::channel::<NetworkMessage>(OUTBOUND_QUEUE_SIZE);
+
+        let writer_peer_id = peer_id.clone();
+        let writer_task = tokio::spawn(async move {
+            while let Some(message) = outbound_rx.recv().await {
+                let sent = match codec::encode_message(&message) {
+                    Ok(payload) => writer.send_frame(&payload).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
+                    warn!("Failed to send message to {}: {}", writer_peer_id, e);
+                    break;
+                }
+            }
+        });
+
+        let context = self.clone();
+        let reader_task = tokio::spawn(async move {
+            loop {
+                let received = reader
+                    .recv_frame()
+                    .await
+                    .and_then(|frame| frame.map(|payload| codec::decode_message(&payload)).transpose());
+                match received {
+                    Ok(Some(NetworkMessage::Gossip(envelope))) => {
+                        if !context.handle_gossip(&peer_id, envelope).await {
+                            break;
+                        }
+                    }
+                    Ok(Some(NetworkMessage::Response(request_id, response))) => {
+                        context.complete_request(request_id, *response).await;
+                    }
+                    Ok(Some(NetworkMessage::Request(request_id, request))) => {
+                        debug!("Received request {} from {}", request_id, peer_id);
+                        let inbound = InboundMessage { peer_id: peer_id.clone(), message: *request, request_id: Some(request_id) };
+                        if context.inbound_tx.send(inbound).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Some(message)) => {
+                        debug!("Received message from {}", peer_id);
+                        let inbound = InboundMessage { peer_id: peer_id.clone(), message, request_id: None };
+                        if context.inbound_tx.send(inbound).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        debug!("Peer {} closed the connection", peer_id);
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("Dropping connection to {}: {}", peer_id, e);
+                        break;
+                    }
+                }
+            }
+            context.remove_connection(&peer_id, id).await;
+        });
+
+        PeerConnection {
+            id,
+            address,
+            identity,
+            outbound: outbound_tx,
+            reader: reader_task,
+            writer: writer_task,
+        }
+    }
+
+    /// Delivers a gossiped message locally the first time it is seen and passes it
+    /// on to the topic mesh. Returns `false` once the node is shutting down.
+    async fn handle_gossip(&self, peer_id: &str, mut envelope: GossipEnvelope) -> bool {
+        if !self.gossip.mark_seen(&envelope.message_id).await {
+            return true;
+        }
+        debug!("Gossip {} on {} from {} (hop {})", envelope.message_id, envelope.topic, peer_id, envelope.hops);
+
+        // Handed on whole: `peer_id` only relayed it, so the payload must speak for itself.
+        let inbound = InboundMessage {
+            peer_id: peer_id.to_string(),
+            message: NetworkMessage::Gossip(envelope.clone()),
+            request_id: None,
+        };
+        if self.inbound_tx.send(inbound).await.is_err() {
+            return false;
+        }
+
+        envelope.hops = envelope.hops.saturating_add(1);
+        if envelope.hops < envelope.max_hops.min(self.gossip.config().max_hops) {
+            self.relay(&envelope, Some(peer_id)).await;
+        }
+        true
+    }
+
+    /// Sends a gossip envelope to the mesh for its topic, skipping the peer it came
+    /// from and its origin. Returns how many peers accepted it.
+    async fn relay(&self, envelope: &GossipEnvelope, from: Option<&str>) -> usize {
+        let (candidates, senders): (Vec<String>, HashMap<String, mpsc::Sender<NetworkMessage>>) = {
+            let connections = self.connections.read().await;
+            let subscribed: Vec<(&String, &PeerConnection)> = connections
+                .iter()
+                .filter(|(_, c)| c.identity.capabilities.contains(&envelope.topic))
+                .collect();
+            (
+                subscribed.iter().map(|(peer_id, _)| (*peer_id).clone()).collect(),
+                subscribed.iter().map(|(peer_id, c)| ((*peer_id).clone(), c.outbound.clone())).collect(),
+            )
+        };
+
+        let mut exclude = vec![envelope.origin_did.as_str()];
+        exclude.extend(from);
+        let targets = self.gossip.mesh_peers(&envelope.topic, &candidates, &exclude).await;
+
+        let mut delivered = 0;
+        for peer_id in targets {
+            if let Some(sender) = senders.get(&peer_id) {
+                match sender.send(NetworkMessage::Gossip(envelope.clone())).await {
+                    Ok(_) => delivered += 1,
+                    Err(_) => debug!("Skipping closed connection to {}", peer_id),
+                }
+            }
+        }
+        delivered
+    }
+
+    async fn complete_request(&self, request_id: u64, response: NetworkMessage) {
+        match self.pending_requests.lock().await.remove(&request_id) {
+            // The requester may have timed out and gone away in the meantime.
+            Some(waiter) => {
+                let _ = waiter.send(response);
+            }
+            None => debug!("Dropping response to unknown request {}", request_id),
+        }
+    }
+
+    /// Forgets a connection, unless it has already been replaced by a newer one.
+    async fn remove_connection(&self, peer_id: &str, id: u64) {
+        let mut connections = self.connections.write().await;
+        if connections.get(peer_id).map(|c| c.id) != Some(id) {
+            return;
+        }
+        if let Some(connection) = connections.remove(peer_id) {
+            connection.writer.abort();
+        }
+        drop(connections);
+        self.connected_peers.write().await.retain(|p| p != peer_id);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NetworkStats {
     pub local_peer_id: String,
     pub connected_peers: usize,
     pub subscribed_topics: usize,
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    async fn started_network() -> (P2PNetwork, String) {
+        let network = P2PNetwork::new(0, DIDManager::new(vec![])).await.unwrap();
+        network.start().await.unwrap();
+        let port = network.local_addr().await.unwrap().port();
+        (network, format!("tcp://127.0.0.1:{}", port))
+    }
+
+    fn attestation() -> GossipPayload {
+        let attester = DIDManager::new(vec![]);
+        GossipPayload::ReputationAttestation(attester.create_attestation("did:duxnet:x".to_string(), 4.0, "task_completed".to_string()))
+    }
+
+    async fn wait_for_peers(network: &P2PNetwork, count: usize) {
+        timeout(Duration::from_secs(5), async {
+            while network.get_peers().await.len() != count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("peer count never settled");
+    }
+
+    #[tokio::test]
+    async fn test_two_nodes_exchange_messages() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+
+        let peer_b = node_a.connect(&endpoint_b).await.unwrap();
+        assert_eq!(peer_b, node_b.local_peer_id);
+        wait_for_peers(&node_b, 1).await;
+        assert_eq!(node_a.get_peers().await, vec![peer_b.clone()]);
+        assert_eq!(node_b.get_peers().await, vec![node_a.local_peer_id.clone()]);
+
+        node_a.send_to(&peer_b, NetworkMessage::Ping).await.unwrap();
+        let inbound = timeout(Duration::from_secs(5), node_b.next_event()).await.unwrap().unwrap();
+        assert!(matches!(inbound.message, NetworkMessage::Ping));
+        assert_eq!(inbound.peer_id, node_a.local_peer_id);
+
+        node_b.send_to(&inbound.peer_id, NetworkMessage::Pong).await.unwrap();
+        let reply = timeout(Duration::from_secs(5), node_a.next_event()).await.unwrap().unwrap();
+        assert!(matches!(reply.message, NetworkMessage::Pong));
+        assert_eq!(reply.peer_id, peer_b);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_matched_to_responses() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+        let peer_b = node_a.connect(&endpoint_b).await.unwrap();
+        wait_for_peers(&node_b, 1).await;
+
+        let responder = tokio::spawn(async move {
+            let inbound = node_b.next_event().await.unwrap();
+            let request_id = inbound.request_id.expect("sent as a request");
+            assert!(matches!(inbound.message, NetworkMessage::DhtFindValue(ref key) if key == "escrow:e1"));
+            node_b.respond(&inbound.peer_id, request_id, NetworkMessage::DhtStoreAck(true)).await.unwrap();
+            node_b
+        });
+
+        let reply = node_a.request(&peer_b, NetworkMessage::DhtFindValue("escrow:e1".to_string())).await.unwrap();
+        assert!(matches!(reply, NetworkMessage::DhtStoreAck(true)));
+
+        let node_b = responder.await.unwrap();
+        let contact = node_a.peer_contact(&peer_b).await.unwrap();
+        assert_eq!(contact.endpoint, endpoint_b);
+        assert_eq!(node_b.peer_contact(&node_a.local_peer_id).await.unwrap().did, node_a.local_peer_id);
+    }
+
+    #[tokio::test]
+    async fn test_connected_peers_tracks_disconnects() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+
+        // Stopping a node drops all of its connections.
+        let peer_b = node_a.connect(&endpoint_b).await.unwrap();
+        wait_for_peers(&node_b, 1).await;
+        node_b.stop().await.unwrap();
+        assert!(node_b.local_addr().await.is_none());
+        wait_for_peers(&node_a, 0).await;
+        assert!(node_a.send_to(&peer_b, NetworkMessage::Ping).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_dials_leave_one_connection() {
+        let (node_a, endpoint_a) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+
+        let (a_to_b, b_to_a) = tokio::join!(node_a.connect(&endpoint_b), node_b.connect(&endpoint_a));
+        a_to_b.unwrap();
+        b_to_a.unwrap();
+        wait_for_peers(&node_a, 1).await;
+        wait_for_peers(&node_b, 1).await;
+
+        // A message sent while the losing connection is being torn down may be
+        // lost, but the surviving connection must carry traffic.
+        let delivered = timeout(Duration::from_secs(5), async {
+            loop {
+                let _ = node_a.send_to(&node_b.local_peer_id, NetworkMessage::Ping).await;
+                if let Ok(Some(inbound)) = timeout(Duration::from_millis(200), node_b.next_event()).await {
+                    break inbound;
+                }
+            }
+        })
+        .await
+        .expect("no connection survived the simultaneous dial");
+        assert!(matches!(delivered.message, NetworkMessage::Ping));
+        assert_eq!(node_a.get_peers().await.len(), 1);
+        assert_eq!(node_b.get_peers().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_reaches_connected_peers() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+        node_a.connect(&endpoint_b).await.unwrap();
+        wait_for_peers(&node_b, 1).await;
+
+        node_a.publish_message("reputation", attestation()).await.unwrap();
+        let inbound = timeout(Duration::from_secs(5), node_b.next_event()).await.unwrap().unwrap();
+        assert!(matches!(
+            inbound.message,
+            NetworkMessage::Gossip(GossipEnvelope { payload: GossipPayload::ReputationAttestation(a), .. }) if a.target_did == "did:duxnet:x"
+        ));
+
+        assert!(node_a.publish_message("unknown", attestation()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gossip_is_relayed_past_direct_peers() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+        let (node_c, endpoint_c) = started_network().await;
+        // a - b - c: a and c only reach each other through b.
+        node_a.connect(&endpoint_b).await.unwrap();
+        node_b.connect(&endpoint_c).await.unwrap();
+        wait_for_peers(&node_b, 2).await;
+        wait_for_peers(&node_c, 1).await;
+
+        node_a.publish_message("reputation", attestation()).await.unwrap();
+
+        let received = timeout(Duration::from_secs(5), node_c.next_event()).await.unwrap().unwrap();
+        assert!(matches!(
+            received.message,
+            NetworkMessage::Gossip(GossipEnvelope { payload: GossipPayload::ReputationAttestation(a), .. }) if a.target_did == "did:duxnet:x"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gossip_is_delivered_once_per_node() {
+        let (node_a, _) = started_network().await;
+        let (node_b, endpoint_b) = started_network().await;
+        let (node_c, endpoint_c) = started_network().await;
+        // A triangle gives c two paths to every message from a.
+        node_a.connect(&endpoint_b).await.unwrap();
+        node_a.connect(&endpoint_c).await.unwrap();
+        node_b.connect(&endpoint_c).await.unwrap();
+        wait_for_peers(&node_b, 2).await;
+        wait_for_peers(&node_c, 2).await;
+
+        node_a.publish_message("reputation", attestation()).await.unwrap();
+
+        assert!(timeout(Duration::from_secs(5), node_c.next_event()).await.unwrap().is_some());
+        assert!(timeout(Duration::from_millis(300), node_c.next_event()).await.is_err());
+    }
+}
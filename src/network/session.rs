@@ -0,0 +1,404 @@
+//! Encrypted peer sessions following the Noise `XX` pattern
+//! (`Noise_XX_25519_ChaChaPoly_SHA256`).
+//!
+//! The handshake authenticates each side's X25519 static key. The DID handshake
+//! that runs over the finished session signs the handshake hash, which binds the
+//! session keys to the peer's DID signing key.
+
+use anyhow::Result;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::codec;
+
+const PROTOCOL_NAME: &[u8; 32] = b"Noise_XX_25519_ChaChaPoly_SHA256";
+/// Mixed into the handshake hash so DuxNet sessions cannot be confused with other Noise users.
+const PROLOGUE: &[u8] = b"duxnet/session/v1";
+const DH_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+/// Each direction switches to a fresh key after this many frames.
+pub const DEFAULT_REKEY_INTERVAL: u64 = 1 << 16;
+
+/// One direction of an established session: a key and its message counter.
+pub struct CipherState {
+    key: Option<[u8; 32]>,
+    nonce: u64,
+    rekey_interval: u64,
+}
+
+impl CipherState {
+    fn empty() -> Self {
+        CipherState { key: None, nonce: 0, rekey_interval: 0 }
+    }
+
+    fn with_key(key: [u8; 32], rekey_interval: u64) -> Self {
+        CipherState { key: Some(key), nonce: 0, rekey_interval }
+    }
+
+    fn nonce_bytes(nonce: u64) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[4..].copy_from_slice(&nonce.to_le_bytes());
+        bytes
+    }
+
+    fn encrypt_with_ad(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let Some(key) = self.key else {
+            return Ok(plaintext.to_vec());
+        };
+        if self.nonce == u64::MAX {
+            return Err(anyhow::anyhow!("Session nonce space exhausted"));
+        }
+        let ciphertext = ChaCha20Poly1305::new(&key.into())
+            .encrypt(&Self::nonce_bytes(self.nonce).into(), Payload { msg: plaintext, aad: ad })
+            .map_err(|_| anyhow::anyhow!("Session encryption failed"))?;
+        self.advance();
+        Ok(ciphertext)
+    }
+
+    fn decrypt_with_ad(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let Some(key) = self.key else {
+            return Ok(ciphertext.to_vec());
+        };
+        if self.nonce == u64::MAX {
+            return Err(anyhow::anyhow!("Session nonce space exhausted"));
+        }
+        let plaintext = ChaCha20Poly1305::new(&key.into())
+            .decrypt(&Self::nonce_bytes(self.nonce).into(), Payload { msg: ciphertext, aad: ad })
+            .map_err(|_| anyhow::anyhow!("Session frame failed authentication"))?;
+        self.advance();
+        Ok(plaintext)
+    }
+
+    fn advance(&mut self) {
+        self.nonce += 1;
+        if self.rekey_interval > 0 && self.nonce.is_multiple_of(self.rekey_interval) {
+            self.rekey();
+        }
+    }
+
+    /// Noise `REKEY`: the next key is the encryption of 32 zero bytes under the maximum nonce.
+    fn rekey(&mut self) {
+        if let Some(key) = self.key {
+            let output = ChaCha20Poly1305::new(&key.into())
+                .encrypt(&Self::nonce_bytes(u64::MAX).into(), Payload { msg: &[0u8; 32], aad: &[] })
+                .expect("rekey encryption cannot fail");
+            let mut next = [0u8; 32];
+            next.copy_from_slice(&output[..32]);
+            self.key = Some(next);
+        }
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with_ad(&[], plaintext)
+    }
+
+    pub fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt_with_ad(&[], ciphertext)
+    }
+}
+
+struct SymmetricState {
+    cipher: CipherState,
+    ck: [u8; 32],
+    h: [u8; 32],
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        let mut state = SymmetricState { cipher: CipherState::empty(), ck: *PROTOCOL_NAME, h: *PROTOCOL_NAME };
+        state.mix_hash(PROLOGUE);
+        state
+    }
+
+    fn hkdf(ck: &[u8; 32], input: &[u8]) -> ([u8; 32], [u8; 32]) {
+        let mut output = [0u8; 64];
+        Hkdf::<Sha256>::new(Some(ck), input)
+            .expand(&[], &mut output)
+            .expect("64 bytes is a valid HKDF-SHA256 output length");
+        let mut first = [0u8; 32];
+        let mut second = [0u8; 32];
+        first.copy_from_slice(&output[..32]);
+        second.copy_from_slice(&output[32..]);
+        (first, second)
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(self.h);
+        hasher.update(data);
+        self.h.copy_from_slice(&hasher.finalize());
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        let (ck, key) = Self::hkdf(&self.ck, input);
+        self.ck = ck;
+        self.cipher = CipherState::with_key(key, 0);
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let h = self.h;
+        let ciphertext = self.cipher.encrypt_with_ad(&h, plaintext)?;
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let h = self.h;
+        let plaintext = self.cipher.decrypt_with_ad(&h, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    /// Returns the initiator-to-responder and responder-to-initiator cipher states.
+    fn split(&self, rekey_interval: u64) -> (CipherState, CipherState) {
+        let (first, second) = Self::hkdf(&self.ck, &[]);
+        (CipherState::with_key(first, rekey_interval), CipherState::with_key(second, rekey_interval))
+    }
+}
+
+fn public_key_from(bytes: &[u8]) -> Result<PublicKey> {
+    let bytes: [u8; DH_LEN] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid X25519 public key length: {}", bytes.len()))?;
+    Ok(PublicKey::from(bytes))
+}
+
+async fn read_handshake_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>> {
+    codec::read_frame(stream)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Peer closed the connection during session setup"))
+}
+
+/// An established encrypted session over `stream`.
+pub struct SecureChannel<S> {
+    stream: S,
+    send: CipherState,
+    recv: CipherState,
+    handshake_hash: [u8; 32],
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SecureChannel<S> {
+    /// Initiator side of `XX`: `-> e`, `<- e, ee, s, es`, `-> s, se`.
+    pub async fn initiate(stream: S, static_key: &StaticSecret) -> Result<Self> {
+        Self::initiate_with_rekey_interval(stream, static_key, DEFAULT_REKEY_INTERVAL).await
+    }
+
+    pub async fn initiate_with_rekey_interval(mut stream: S, static_key: &StaticSecret, rekey_interval: u64) -> Result<Self> {
+        let mut state = SymmetricState::new();
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+
+        // -> e
+        state.mix_hash(ephemeral_public.as_bytes());
+        let mut message = ephemeral_public.as_bytes().to_vec();
+        message.extend(state.encrypt_and_hash(&[])?);
+        codec::write_frame(&mut stream, &message).await?;
+
+        // <- e, ee, s, es
+        let message = read_handshake_frame(&mut stream).await?;
+        if message.len() < DH_LEN + DH_LEN + TAG_LEN {
+            return Err(anyhow::anyhow!("Session handshake message too short"));
+        }
+        let remote_ephemeral = public_key_from(&message[..DH_LEN])?;
+        state.mix_hash(remote_ephemeral.as_bytes());
+        state.mix_key(ephemeral.diffie_hellman(&remote_ephemeral).as_bytes());
+        let remote_static = public_key_from(&state.decrypt_and_hash(&message[DH_LEN..DH_LEN * 2 + TAG_LEN])?)?;
+        state.mix_key(ephemeral.diffie_hellman(&remote_static).as_bytes());
+        state.decrypt_and_hash(&message[DH_LEN * 2 + TAG_LEN..])?;
+
+        // -> s, se
+        let mut message = state.encrypt_and_hash(PublicKey::from(static_key).as_bytes())?;
+        state.mix_key(static_key.diffie_hellman(&remote_ephemeral).as_bytes());
+        message.extend(state.encrypt_and_hash(&[])?);
+        codec::write_frame(&mut stream, &message).await?;
+
+        let (send, recv) = state.split(rekey_interval);
+        Ok(SecureChannel { stream, send, recv, handshake_hash: state.h })
+    }
+
+    /// Responder side of `XX`.
+    pub async fn respond(stream: S, static_key: &StaticSecret) -> Result<Self> {
+        Self::respond_with_rekey_interval(stream, static_key, DEFAULT_REKEY_INTERVAL).await
+    }
+
+    pub async fn respond_with_rekey_interval(mut stream: S, static_key: &StaticSecret, rekey_interval: u64) -> Result<Self> {
+        let mut state = SymmetricState::new();
+
+        // -> e
+        let message = read_handshake_frame(&mut stream).await?;
+        if message.len() < DH_LEN {
+            return Err(anyhow::anyhow!("Session handshake message too short"));
+        }
+        let remote_ephemeral = public_key_from(&message[..DH_LEN])?;
+        state.mix_hash(remote_ephemeral.as_bytes());
+        state.decrypt_and_hash(&message[DH_LEN..])?;
+
+        // <- e, ee, s, es
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        state.mix_hash(ephemeral_public.as_bytes());
+        let mut message = ephemeral_public.as_bytes().to_vec();
+        state.mix_key(ephemeral.diffie_hellman(&remote_ephemeral).as_bytes());
+        message.extend(state.encrypt_and_hash(PublicKey::from(static_key).as_bytes())?);
+        state.mix_key(static_key.diffie_hellman(&remote_ephemeral).as_bytes());
+        message.extend(state.encrypt_and_hash(&[])?);
+        codec::write_frame(&mut stream, &message).await?;
+
+        // -> s, se
+        let message = read_handshake_frame(&mut stream).await?;
+        if message.len() < DH_LEN + TAG_LEN {
+            return Err(anyhow::anyhow!("Session handshake message too short"));
+        }
+        let remote_static = public_key_from(&state.decrypt_and_hash(&message[..DH_LEN + TAG_LEN])?)?;
+        state.mix_key(ephemeral.diffie_hellman(&remote_static).as_bytes());
+        state.decrypt_and_hash(&message[DH_LEN + TAG_LEN..])?;
+
+        let (recv, send) = state.split(rekey_interval);
+        Ok(SecureChannel { stream, send, recv, handshake_hash: state.h })
+    }
+
+    /// Hash of the whole handshake transcript; identical on both ends of the session.
+    pub fn handshake_hash(&self) -> &[u8; 32] {
+        &self.handshake_hash
+    }
+
+    pub async fn send_frame(&mut self, payload: &[u8]) -> Result<()> {
+        let ciphertext = self.send.encrypt(payload)?;
+        codec::write_frame(&mut self.stream, &ciphertext).await
+    }
+
+    pub async fn recv_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        match codec::read_frame(&mut self.stream).await? {
+            Some(ciphertext) => Ok(Some(self.recv.decrypt(&ciphertext)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Gives back the underlying stream with the sending and receiving cipher states.
+    pub fn into_parts(self) -> (S, CipherState, CipherState) {
+        (self.stream, self.send, self.recv)
+    }
+}
+
+/// Sending half of a session once the stream has been split.
+pub struct SecureWriter<W> {
+    inner: W,
+    cipher: CipherState,
+}
+
+impl<W: AsyncWrite + Unpin> SecureWriter<W> {
+    pub fn new(inner: W, cipher: CipherState) -> Self {
+        SecureWriter { inner, cipher }
+    }
+
+    pub async fn send_frame(&mut self, payload: &[u8]) -> Result<()> {
+        let ciphertext = self.cipher.encrypt(payload)?;
+        codec::write_frame(&mut self.inner, &ciphertext).await
+    }
+}
+
+/// Receiving half of a session once the stream has been split.
+pub struct SecureReader<R> {
+    inner: R,
+    cipher: CipherState,
+}
+
+impl<R: AsyncRead + Unpin> SecureReader<R> {
+    pub fn new(inner: R, cipher: CipherState) -> Self {
+        SecureReader { inner, cipher }
+    }
+
+    pub async fn recv_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        match codec::read_frame(&mut self.inner).await? {
+            Some(ciphertext) => Ok(Some(self.cipher.decrypt(&ciphertext)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    async fn session_pair(rekey_interval: u64) -> (SecureChannel<tokio::io::DuplexStream>, SecureChannel<tokio::io::DuplexStream>) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let alice = StaticSecret::random_from_rng(OsRng);
+        let bob = StaticSecret::random_from_rng(OsRng);
+        let (initiator, responder) = tokio::join!(
+            SecureChannel::initiate_with_rekey_interval(a, &alice, rekey_interval),
+            SecureChannel::respond_with_rekey_interval(b, &bob, rekey_interval),
+        );
+        (initiator.unwrap(), responder.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_session_roundtrip_and_shared_transcript() {
+        let (mut initiator, mut responder) = session_pair(DEFAULT_REKEY_INTERVAL).await;
+        assert_eq!(initiator.handshake_hash(), responder.handshake_hash());
+
+        initiator.send_frame(b"escrow signature").await.unwrap();
+        assert_eq!(responder.recv_frame().await.unwrap().unwrap(), b"escrow signature");
+        responder.send_frame(b"ack").await.unwrap();
+        assert_eq!(initiator.recv_frame().await.unwrap().unwrap(), b"ack");
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_not_cleartext() {
+        let (initiator, _responder) = session_pair(DEFAULT_REKEY_INTERVAL).await;
+        let (_, send, _) = initiator.into_parts();
+
+        let (a, mut b) = tokio::io::duplex(1024);
+        let mut writer = SecureWriter::new(a, send);
+        writer.send_frame(b"TaskSubmission payload").await.unwrap();
+        drop(writer);
+
+        let mut wire = Vec::new();
+        b.read_to_end(&mut wire).await.unwrap();
+        assert!(!wire.windows(14).any(|w| w == b"TaskSubmission"));
+    }
+
+    #[tokio::test]
+    async fn test_tampered_frame_is_rejected() {
+        let (initiator, responder) = session_pair(DEFAULT_REKEY_INTERVAL).await;
+        let (_, mut send, _) = initiator.into_parts();
+        let (_, _, mut recv) = responder.into_parts();
+
+        let mut ciphertext = send.encrypt(b"pay 10 DUX").unwrap();
+        ciphertext[0] ^= 0x01;
+        assert!(recv.decrypt(&ciphertext).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replayed_frame_is_rejected() {
+        let (initiator, responder) = session_pair(DEFAULT_REKEY_INTERVAL).await;
+        let (_, mut send, _) = initiator.into_parts();
+        let (_, _, mut recv) = responder.into_parts();
+
+        let ciphertext = send.encrypt(b"once").unwrap();
+        assert_eq!(recv.decrypt(&ciphertext).unwrap(), b"once");
+        assert!(recv.decrypt(&ciphertext).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_both_sides_rekey_in_step() {
+        let (mut initiator, mut responder) = session_pair(3).await;
+        for i in 0..10u8 {
+            initiator.send_frame(&[i]).await.unwrap();
+            assert_eq!(responder.recv_frame().await.unwrap().unwrap(), vec![i]);
+        }
+
+        // Rekeying changes the ciphertext from the first frame after the interval on.
+        let mut rekeying = CipherState::with_key([7u8; 32], 3);
+        let mut fixed = CipherState::with_key([7u8; 32], 0);
+        for _ in 0..3 {
+            assert_eq!(rekeying.encrypt(b"frame").unwrap(), fixed.encrypt(b"frame").unwrap());
+        }
+        assert_ne!(rekeying.encrypt(b"frame").unwrap(), fixed.encrypt(b"frame").unwrap());
+    }
+}
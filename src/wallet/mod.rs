@@ -1,14 +1,17 @@
 use crate::core::data_structures::*;
+use crate::core::identity::DIDManager;
+use crate::core::mnemonic::{wallet_path, Mnemonic};
+use crate::core::signing::{CanonicalEncoder, Signable};
 use anyhow::Result;
-use ed25519_dalek::{SigningKey, VerifyingKey, Signature, Signer, Verifier};
+use ed25519_dalek::{SigningKey, VerifyingKey, Signer};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
 use tracing::info;
 use base64::{Engine as _, engine::general_purpose};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)] // Ticker symbols, serialized as written.
 pub enum Currency {
     BTC,    // Bitcoin
     ETH,    // Ethereum
@@ -32,18 +35,6 @@ impl Currency {
         }
     }
 
-    pub fn name(&self) -> &'static str {
-        match self {
-            Currency::BTC => "Bitcoin",
-            Currency::ETH => "Ethereum",
-            Currency::USDC => "USD Coin",
-            Currency::LTC => "Litecoin",
-            Currency::XMR => "Monero",
-            Currency::DOGE => "Dogecoin",
-            Currency::DUX => "DUX Coin",
-        }
-    }
-
     pub fn decimals(&self) -> u8 {
         match self {
             Currency::BTC => 8,
@@ -96,10 +87,17 @@ pub struct Wallet {
     pub transactions: Vec<Transaction>,
     pub preferred_currency: Currency,
     pub addresses: HashMap<Currency, String>, // currency -> address
+    /// Per-currency secret keys of a wallet derived from a recovery phrase.
+    /// Currencies without one use `secret_key`.
+    #[serde(default)]
+    pub currency_keys: HashMap<Currency, Vec<u8>>,
     pub created_at: u64,
     pub last_activity: u64,
 }
 
+const ALL_CURRENCIES: [Currency; 7] =
+    [Currency::BTC, Currency::ETH, Currency::USDC, Currency::LTC, Currency::XMR, Currency::DOGE, Currency::DUX];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub id: String,
@@ -116,6 +114,22 @@ pub struct Transaction {
     pub memo: Option<String>,
 }
 
+impl Signable for Transaction {
+    const DOMAIN: &'static str = "duxnet/transaction/v1";
+
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .str(&self.id)
+            .str(&self.from)
+            .str(&self.to)
+            .u64(self.amount)
+            .str(self.currency.symbol())
+            .u64(self.fee)
+            .u64(self.timestamp)
+            .option_str(self.memo.as_deref());
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TransactionStatus {
     Pending,
@@ -179,24 +193,44 @@ impl Wallet {
             transactions: Vec::new(),
             preferred_currency: Currency::USDC, // Default to USDC for stability
             addresses,
+            currency_keys: HashMap::new(),
             created_at: now,
             last_activity: now,
         })
     }
 
-    pub fn set_preferred_currency(&mut self, currency: Currency) {
-        self.preferred_currency = currency;
-        self.last_activity = get_current_timestamp();
-        info!("Set preferred currency to: {}", self.preferred_currency.name());
+    /// Rebuilds a wallet from its recovery phrase: the DID is the one the phrase
+    /// derives, and each currency gets its own key at `wallet_path(currency)`.
+    /// `secret_key` holds the DUX key.
+    pub fn from_mnemonic(mnemonic: &Mnemonic) -> Result<Self> {
+        let did = DIDManager::from_mnemonic(mnemonic, Vec::new()).did.id;
+        let currency_keys: HashMap<Currency, Vec<u8>> = ALL_CURRENCIES
+            .into_iter()
+            .map(|currency| (currency, mnemonic.derive_key(&wallet_path(currency)).to_vec()))
+            .collect();
+        Self::with_keys(did, currency_keys[&Currency::DUX].clone(), currency_keys)
+    }
+
+    fn with_keys(did: String, secret_key: Vec<u8>, currency_keys: HashMap<Currency, Vec<u8>>) -> Result<Self> {
+        let mut wallet = Self::import_private_key(secret_key, did)?;
+        wallet.currency_keys = currency_keys;
+        for currency in ALL_CURRENCIES {
+            let keypair = wallet.get_currency_keypair(&currency)?;
+            wallet.addresses.insert(currency, Self::generate_address(&currency, &keypair.verifying_key()));
+        }
+        Ok(wallet)
     }
 
-    pub fn get_preferred_currency(&self) -> &Currency {
-        &self.preferred_currency
+    pub fn get_keypair(&self) -> Result<SigningKey> {
+        signing_key(&self.secret_key)
     }
 
-    pub fn get_keypair(&self) -> Result<SigningKey> {
-        let secret_key: [u8; 32] = self.secret_key.clone().try_into().unwrap();
-        Ok(SigningKey::from_bytes(&secret_key))
+    /// The key that signs for `currency`.
+    pub fn get_currency_keypair(&self, currency: &Currency) -> Result<SigningKey> {
+        match self.currency_keys.get(currency) {
+            Some(secret_key) => signing_key(secret_key),
+            None => self.get_keypair(),
+        }
     }
 
     pub fn get_public_key(&self) -> Result<Vec<u8>> {
@@ -216,7 +250,7 @@ impl Wallet {
     pub fn get_address(&self, currency: &Currency) -> String {
         self.addresses.get(currency).cloned().unwrap_or_else(|| {
             // Generate address if not exists
-            if let Ok(keypair) = self.get_keypair() {
+            if let Ok(keypair) = self.get_currency_keypair(currency) {
                 Self::generate_address(currency, &keypair.verifying_key())
             } else {
                 "Invalid address".to_string()
@@ -279,7 +313,7 @@ impl Wallet {
     pub fn add_funds(&mut self, currency: Currency, amount: u64) {
         let current_balance = self.get_balance(&currency);
         let new_balance = current_balance + amount;
-        self.balances.insert(currency.clone(), new_balance);
+        self.balances.insert(currency, new_balance);
         self.last_activity = get_current_timestamp();
         info!("Added {} to wallet", currency.format_amount(amount));
     }
@@ -290,7 +324,7 @@ impl Wallet {
             return Err(anyhow::anyhow!("Insufficient balance"));
         }
         let new_balance = current_balance - amount;
-        self.balances.insert(currency.clone(), new_balance);
+        self.balances.insert(*currency, new_balance);
         self.last_activity = get_current_timestamp();
         info!("Removed {} from wallet", currency.format_amount(amount));
         Ok(())
@@ -316,52 +350,45 @@ impl Wallet {
         let transaction_id = uuid::Uuid::new_v4().to_string();
         let timestamp = get_current_timestamp();
         
-        let message = format!("{}:{}:{}:{}:{}:{}:{}", 
-            transaction_id, self.did, request.to_address, request.amount, 
-            request.currency.symbol(), fee, tax_amount);
-        
-        let keypair = self.get_keypair()?;
-        let signature = keypair.sign(message.as_bytes()).to_bytes().to_vec();
+        let keypair = self.get_currency_keypair(&request.currency)?;
         
         let to_address_clone = request.to_address.clone();
-        let currency_clone = request.currency.clone();
+        let currency_clone = request.currency;
         
         // Create main transaction
-        let transaction = Transaction {
+        let mut transaction = Transaction {
             id: transaction_id.clone(),
             from: self.did.clone(),
             to: request.to_address,
             amount: request.amount,
-            currency: request.currency.clone(),
+            currency: request.currency,
             timestamp,
-            signature,
+            signature: Vec::new(),
             status: TransactionStatus::Pending,
             fee,
             block_height: None,
             confirmations: 0,
             memo: request.memo.clone(),
         };
+        transaction.signature = keypair.sign(&transaction.signing_payload()).to_bytes().to_vec();
         
         // Create tax transaction
         let tax_transaction_id = format!("{}-tax", transaction_id);
-        let tax_message = format!("{}:{}:{}:{}:{}", 
-            tax_transaction_id, self.did, "community_fund", tax_amount, currency_clone.symbol());
-        let tax_signature = keypair.sign(tax_message.as_bytes()).to_bytes().to_vec();
-        
-        let tax_transaction = Transaction {
+        let mut tax_transaction = Transaction {
             id: tax_transaction_id,
             from: self.did.clone(),
             to: "community_fund".to_string(),
             amount: tax_amount,
-            currency: currency_clone.clone(),
+            currency: currency_clone,
             timestamp,
-            signature: tax_signature,
+            signature: Vec::new(),
             status: TransactionStatus::Pending,
             fee: 0,
             block_height: None,
             confirmations: 0,
             memo: Some("Community Fund Tax".to_string()),
         };
+        tax_transaction.signature = keypair.sign(&tax_transaction.signing_payload()).to_bytes().to_vec();
         
         // Remove funds from wallet
         self.remove_funds(&request.currency, total_amount)?;
@@ -387,19 +414,15 @@ impl Wallet {
     pub fn receive_funds(&mut self, from_address: String, amount: u64, currency: Currency, 
                         transaction_id: String, signature: Vec<u8>) -> Result<()> {
         let from_address_clone = from_address.clone();
-        // Verify the transaction signature
-                let _message = format!("{}:{}:{}:{}:{}",
-            transaction_id, from_address, self.did, amount, currency.symbol());
-        
-        // In a real implementation, you'd verify the signature here
-        // For now, we'll just accept it
+        // In a real implementation, you'd verify the signature over the
+        // transaction's `signing_payload()` here. For now, we'll just accept it
         
         let transaction = Transaction {
             id: transaction_id,
             from: from_address,
             to: self.did.clone(),
             amount,
-            currency: currency.clone(),
+            currency,
             timestamp: get_current_timestamp(),
             signature,
             status: TransactionStatus::Confirmed,
@@ -442,98 +465,27 @@ impl Wallet {
         let transaction_id = uuid::Uuid::new_v4().to_string();
         let timestamp = get_current_timestamp();
         
-        let message = format!("{}:{}:{}:{}:{}", 
-            transaction_id, self.did, to, amount, currency.symbol());
-        
-        let keypair = self.get_keypair()?;
-        let signature = keypair.sign(message.as_bytes()).to_bytes().to_vec();
+        let keypair = self.get_currency_keypair(&currency)?;
         
-        let transaction = Transaction {
+        let mut transaction = Transaction {
             id: transaction_id,
             from: self.did.clone(),
             to,
             amount,
             currency,
             timestamp,
-            signature,
+            signature: Vec::new(),
             status: TransactionStatus::Pending,
             fee: 0,
             block_height: None,
             confirmations: 0,
             memo: None,
         };
+        transaction.signature = keypair.sign(&transaction.signing_payload()).to_bytes().to_vec();
         
         Ok(transaction)
     }
 
-    pub fn sign_transaction(&self, transaction: &mut Transaction) -> Result<()> {
-        let message = format!("{}:{}:{}:{}:{}", 
-            transaction.id, transaction.from, transaction.to, 
-            transaction.amount, transaction.currency.symbol());
-        
-        let keypair = self.get_keypair()?;
-        let signature = keypair.sign(message.as_bytes()).to_bytes().to_vec();
-        transaction.signature = signature;
-        transaction.timestamp = get_current_timestamp();
-        
-        Ok(())
-    }
-
-    pub fn verify_transaction(&self, transaction: &Transaction, public_key: &[u8]) -> bool {
-        let message = format!("{}:{}:{}:{}:{}", 
-            transaction.id, transaction.from, transaction.to, 
-            transaction.amount, transaction.currency.symbol());
-        
-        if public_key.len() == 32 {
-            if let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key.try_into().unwrap()) {
-                if transaction.signature.len() == 64 {
-                    if let Ok(array) = <[u8; 64]>::try_from(&transaction.signature[..]) {
-                        let signature = Signature::from_bytes(&array);
-                        return verifying_key.verify(message.as_bytes(), &signature).is_ok();
-                    }
-                }
-            }
-        }
-        false
-    }
-
-    pub fn process_transaction(&mut self, transaction: &Transaction) -> Result<()> {
-        // Verify the transaction signature
-        if !self.verify_transaction(transaction, &self.get_public_key()?) {
-            return Err(anyhow::anyhow!("Invalid transaction signature"));
-        }
-        
-        match transaction.status {
-            TransactionStatus::Confirmed => {
-                if transaction.to == self.did {
-                    // We're receiving funds
-                    self.add_funds(transaction.currency.clone(), transaction.amount);
-                } else if transaction.from == self.did {
-                    // We're sending funds
-                    self.remove_funds(&transaction.currency, transaction.amount)?;
-                }
-            },
-            TransactionStatus::Failed => {
-                // Handle failed transaction (e.g., refund if we were the sender)
-                if transaction.from == self.did {
-                    // Refund the amount
-                    self.add_funds(transaction.currency.clone(), transaction.amount);
-                }
-            },
-            _ => {
-                // Pending or other statuses - just add to history
-            }
-        }
-        
-        // Add to transaction history if not already present
-        if !self.transactions.iter().any(|t| t.id == transaction.id) {
-            self.transactions.push(transaction.clone());
-        }
-        
-        self.last_activity = get_current_timestamp();
-        Ok(())
-    }
-
     pub fn get_transaction_history(&self) -> Vec<Transaction> {
         self.transactions.clone()
     }
@@ -572,16 +524,8 @@ impl Wallet {
         })
     }
 
-    pub fn export_private_key(&self) -> Result<Vec<u8>> {
-        Ok(self.secret_key.clone())
-    }
-
-    pub fn export_private_key_base64(&self) -> Result<String> {
-        Ok(self.get_private_key_base64()?)
-    }
-
     pub fn import_private_key(secret_key_bytes: Vec<u8>, did: String) -> Result<Self> {
-        let keypair = SigningKey::from_bytes(&secret_key_bytes.clone().try_into().unwrap());
+        let keypair = signing_key(&secret_key_bytes)?;
         
         let mut balances = HashMap::new();
         let mut addresses = HashMap::new();
@@ -601,6 +545,7 @@ impl Wallet {
             transactions: Vec::new(),
             preferred_currency: Currency::USDC,
             addresses,
+            currency_keys: HashMap::new(),
             created_at: now,
             last_activity: now,
         })
@@ -610,6 +555,9 @@ impl Wallet {
         let wallet_data = serde_json::json!({
             "did": self.did,
             "secret_key": general_purpose::STANDARD.encode(&self.secret_key),
+            "currency_keys": self.currency_keys.iter()
+                .map(|(currency, key)| (currency.symbol().to_string(), general_purpose::STANDARD.encode(key)))
+                .collect::<HashMap<_, _>>(),
             "preferred_currency": self.preferred_currency,
             "created_at": self.created_at,
             "backup_version": "1.0"
@@ -618,105 +566,35 @@ impl Wallet {
         Ok(wallet_data.to_string())
     }
 
+    /// Restores a wallet from its recovery phrase, or from a `backup_wallet` export.
     pub fn restore_wallet(backup_data: &str) -> Result<Self> {
+        if !backup_data.trim_start().starts_with('{') {
+            return Self::from_mnemonic(&Mnemonic::parse(backup_data)?);
+        }
         let wallet_data: serde_json::Value = serde_json::from_str(backup_data)?;
         
-        let did = wallet_data["did"].as_str().unwrap().to_string();
-        let secret_key_encoded = wallet_data["secret_key"].as_str().unwrap();
+        let did = wallet_data["did"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Wallet backup has no DID"))?
+            .to_string();
+        let secret_key_encoded = wallet_data["secret_key"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Wallet backup has no secret key"))?;
         let secret_key_bytes = general_purpose::STANDARD.decode(secret_key_encoded)?;
-        
-        Self::import_private_key(secret_key_bytes, did)
-    }
-}
-
-// Multi-signature wallet for escrow
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MultiSigWallet {
-    pub address: String,
-    pub participants: Vec<String>,
-    pub threshold: usize,
-    pub balance: u64,
-    pub currency: Currency,
-    pub pending_transactions: Vec<MultiSigTransaction>,
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MultiSigTransaction {
-    pub id: String,
-    pub to: String,
-    pub amount: u64,
-    pub currency: Currency,
-    pub signatures: HashMap<String, Vec<u8>>,
-    pub status: TransactionStatus,
-    pub created_at: u64,
-}
-
-impl MultiSigWallet {
-    pub fn new(participants: Vec<String>, threshold: usize, currency: Currency) -> Self {
-        let address = format!("multisig_{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
-        
-        MultiSigWallet {
-            address,
-            participants,
-            threshold,
-            balance: 0,
-            currency,
-            pending_transactions: Vec::new(),
-        }
-    }
-
-    pub fn add_funds(&mut self, amount: u64) {
-        self.balance += amount;
-        info!("Added {} to multisig wallet", self.currency.format_amount(amount));
-    }
-
-    pub fn create_transaction(&mut self, to: String, amount: u64) -> Result<String> {
-        if amount > self.balance {
-            return Err(anyhow::anyhow!("Insufficient balance in multisig wallet"));
-        }
-
-        let transaction_id = uuid::Uuid::new_v4().to_string();
-        let to_clone = to.clone();
-        let transaction = MultiSigTransaction {
-            id: transaction_id.clone(),
-            to,
-            amount,
-            currency: self.currency.clone(),
-            signatures: HashMap::new(),
-            status: TransactionStatus::Pending,
-            created_at: get_current_timestamp(),
-        };
-
-        self.pending_transactions.push(transaction);
-        info!("Created multisig transaction: {} {} to {}", 
-            self.currency.format_amount(amount), self.currency.symbol(), to_clone);
-
-        Ok(transaction_id)
-    }
-
-    pub fn add_signature(&mut self, transaction_id: &str, signer: String, signature: Vec<u8>) -> Result<bool> {
-        if let Some(transaction) = self.pending_transactions.iter_mut().find(|t| t.id == transaction_id) {
-            if !self.participants.contains(&signer) {
-                return Err(anyhow::anyhow!("Signer is not a participant"));
-            }
-
-            transaction.signatures.insert(signer, signature);
-
-            // Check if we have enough signatures
-            if transaction.signatures.len() >= self.threshold {
-                transaction.status = TransactionStatus::Confirmed;
-                self.balance -= transaction.amount;
-                info!("Multisig transaction {} confirmed with {} signatures", 
-                    transaction_id, transaction.signatures.len());
-                return Ok(true);
+        let mut currency_keys = HashMap::new();
+        if let Some(keys) = wallet_data["currency_keys"].as_object() {
+            for currency in ALL_CURRENCIES {
+                if let Some(key) = keys.get(currency.symbol()).and_then(|key| key.as_str()) {
+                    currency_keys.insert(currency, general_purpose::STANDARD.decode(key)?);
+                }
             }
-            Ok(false)
-        } else {
-            Err(anyhow::anyhow!("Transaction not found"))
         }
+        
+        Self::with_keys(did, secret_key_bytes, currency_keys)
     }
+}
 
-    pub fn get_pending_transactions(&self) -> Vec<MultiSigTransaction> {
-        self.pending_transactions.clone()
-    }
-} 
\ No newline at end of file
+fn signing_key(secret_key: &[u8]) -> Result<SigningKey> {
+    let secret_key: [u8; 32] = secret_key.try_into()
+        .map_err(|_| anyhow::anyhow!("Wallet keys must be 32 bytes, got {}", secret_key.len()))?;
+    Ok(SigningKey::from_bytes(&secret_key))
+}